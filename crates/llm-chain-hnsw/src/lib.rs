@@ -1,19 +1,27 @@
 use std::{
-    collections::HashMap, fs::OpenOptions, io::BufReader, marker::PhantomData, path::PathBuf,
-    sync::Arc,
+    collections::{HashMap, HashSet},
+    fs::OpenOptions,
+    io::BufReader,
+    marker::PhantomData,
+    path::PathBuf,
+    sync::{Arc, Mutex as StdMutex},
 };
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use hnsw_rs::{hnsw::Hnsw, hnswio::*, prelude::*};
 use llm_chain::{
-    document_stores::document_store::*,
-    schema::Document,
-    traits::{Embeddings, EmbeddingsError, VectorStore, VectorStoreError},
+    document_stores::{document_store::*, keyword_index::KeywordIndex},
+    schema::{Document, ExpiryClock, Image},
+    traits::{Embeddings, EmbeddingsError, ImageEmbeddings, VectorStore, VectorStoreError},
 };
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::Mutex;
 
+/// How many candidates `hybrid_search` takes from each search per result it returns.
+const HYBRID_CANDIDATES_FACTOR: u32 = 4;
+
 pub struct HnswArgs {
     max_nb_connection: usize,
     max_elements: usize,
@@ -32,6 +40,48 @@ impl Default for HnswArgs {
     }
 }
 
+/// Tracks the points of the index that no longer hold the document they were inserted for.
+///
+/// Points can't be removed from the index, so updating a document tombstones its point and inserts
+/// a new one, and purging a document tombstones its point. Points inserted by updates count down
+/// from `usize::MAX`, so they never collide with document ids.
+#[derive(Default, Serialize, Deserialize)]
+struct PointMap {
+    /// Points to leave out of search results.
+    tombstones: HashSet<usize>,
+    /// The document id of each point inserted by an update.
+    document_ids: HashMap<usize, usize>,
+    /// The current point of each updated document.
+    points: HashMap<usize, usize>,
+}
+
+impl PointMap {
+    fn document_id(&self, point: usize) -> usize {
+        self.document_ids.get(&point).copied().unwrap_or(point)
+    }
+
+    fn point(&self, document_id: usize) -> usize {
+        self.points
+            .get(&document_id)
+            .copied()
+            .unwrap_or(document_id)
+    }
+
+    /// Tombstones the current point of `document_id` and returns the id of its new point.
+    fn reinsert(&mut self, document_id: usize) -> usize {
+        self.tombstones.insert(self.point(document_id));
+        let point = usize::MAX - self.document_ids.len();
+        self.document_ids.insert(point, document_id);
+        self.points.insert(document_id, point);
+        point
+    }
+
+    /// Tombstones the current point of `document_id`.
+    fn remove(&mut self, document_id: usize) {
+        self.tombstones.insert(self.point(document_id));
+    }
+}
+
 pub struct HnswVectorStore<E, D, M>
 where
     E: Embeddings,
@@ -41,6 +91,9 @@ where
     hnsw: Arc<Hnsw<f32, DistCosine>>,
     document_store: Arc<Mutex<D>>,
     embeddings: Arc<E>,
+    point_map: Arc<StdMutex<PointMap>>,
+    bm25: Arc<StdMutex<KeywordIndex>>,
+    clock: ExpiryClock,
     _marker: PhantomData<M>,
 }
 
@@ -62,14 +115,43 @@ where
             hnsw: Arc::new(hnsw),
             document_store,
             embeddings,
+            point_map: Default::default(),
+            bm25: Default::default(),
+            clock: Arc::new(Utc::now),
             _marker: Default::default(),
         }
     }
 
+    /// Replaces the clock used to leave expired documents out of search results, which defaults
+    /// to the system time.
+    pub fn with_clock<F>(mut self, clock: F) -> Self
+    where
+        F: Fn() -> DateTime<Utc> + Send + Sync + 'static,
+    {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Dumps the index to `{filename}.hnsw.graph` and `{filename}.hnsw.data`.
+    ///
+    /// If documents were updated or purged, the points they left behind are recorded in
+    /// `{filename}.hnsw.updates`. The keyword index used by `hybrid_search` is written to
+    /// `{filename}.hnsw.bm25`.
     pub fn dump_to_file(
         &self,
         filename: String,
     ) -> Result<i32, HnswVectorStoreError<E::Error, D::Error>> {
+        let bm25 = serde_json::to_string(&*self.bm25.lock().unwrap())
+            .map_err(|e| HnswVectorStoreError::FileDumpError(e.to_string()))?;
+        std::fs::write(format!("{}.hnsw.bm25", &filename), bm25)
+            .map_err(|e| HnswVectorStoreError::FileDumpError(e.to_string()))?;
+        let point_map = self.point_map.lock().unwrap();
+        if !point_map.tombstones.is_empty() {
+            let updates = serde_json::to_string(&*point_map)
+                .map_err(|e| HnswVectorStoreError::FileDumpError(e.to_string()))?;
+            std::fs::write(format!("{}.hnsw.updates", &filename), updates)
+                .map_err(|e| HnswVectorStoreError::FileDumpError(e.to_string()))?;
+        }
         self.hnsw
             .file_dump(&filename)
             .map_err(HnswVectorStoreError::FileDumpError)
@@ -108,13 +190,305 @@ where
         let hnsw_loaded: Hnsw<f32, DistCosine> =
             load_hnsw(&mut graph_in, &hnsw_description, &mut data_in).unwrap();
 
+        let updates_path = PathBuf::from(format!("{}.hnsw.updates", &filename));
+        let point_map = match std::fs::read_to_string(&updates_path) {
+            Ok(updates) => serde_json::from_str(&updates).map_err(|e| {
+                HnswVectorStoreError::FileLoadError(format!(
+                    "could not parse file {:?}: {}",
+                    updates_path.as_os_str(),
+                    e
+                ))
+            })?,
+            Err(_) => PointMap::default(),
+        };
+        let bm25_path = PathBuf::from(format!("{}.hnsw.bm25", &filename));
+        let bm25 = match std::fs::read_to_string(&bm25_path) {
+            Ok(bm25) => serde_json::from_str(&bm25).map_err(|e| {
+                HnswVectorStoreError::FileLoadError(format!(
+                    "could not parse file {:?}: {}",
+                    bm25_path.as_os_str(),
+                    e
+                ))
+            })?,
+            Err(_) => KeywordIndex::default(),
+        };
+
         Ok(HnswVectorStore {
             hnsw: Arc::new(hnsw_loaded),
             document_store,
             embeddings,
+            point_map: Arc::new(StdMutex::new(point_map)),
+            bm25: Arc::new(StdMutex::new(bm25)),
+            clock: Arc::new(Utc::now),
             _marker: Default::default(),
         })
     }
+
+    async fn insert_embedded(
+        &self,
+        embedding_vecs: Vec<Vec<f32>>,
+        documents: Vec<Document<M>>,
+    ) -> Result<Vec<String>, HnswVectorStoreError<E::Error, D::Error>> {
+        let document_store_arc = self.document_store.clone();
+        let mut document_store = document_store_arc.lock().await;
+
+        let next_id = document_store
+            .next_id()
+            .await
+            .map_err(HnswVectorStoreError::DocumentStoreError)?;
+        let ids = (0..embedding_vecs.len())
+            .map(|i| next_id + i)
+            .collect::<Vec<usize>>();
+
+        let iter = embedding_vecs
+            .into_iter()
+            .zip(documents.into_iter())
+            .zip(ids.iter());
+
+        for ((vec, document), id) in iter {
+            self.bm25
+                .lock()
+                .unwrap()
+                .insert(*id, &document.page_content);
+            document_store
+                .insert(&HashMap::from([(id.to_owned(), document)]))
+                .await
+                .map_err(HnswVectorStoreError::DocumentStoreError)?;
+            self.hnsw.insert((&vec, id.to_owned()));
+        }
+
+        let ids_str = ids
+            .iter()
+            .map(|&id| format!("{}", id))
+            .collect::<Vec<String>>();
+        Ok(ids_str)
+    }
+
+    /// Finds the documents nearest to `embedded_query`, leaving out expired documents.
+    ///
+    /// More neighbours are fetched until `limit` documents are found or the index runs out. A
+    /// neighbour missing from the document store is an error, as the index and the store are out
+    /// of sync.
+    async fn search_embedded(
+        &self,
+        embedded_query: Vec<f32>,
+        limit: u32,
+    ) -> Result<Vec<(Document<M>, f32)>, HnswVectorStoreError<E::Error, D::Error>> {
+        Ok(self
+            .search_embedded_with_ids(embedded_query, limit)
+            .await?
+            .into_iter()
+            .map(|(_, doc, score)| (doc, score))
+            .collect())
+    }
+
+    /// Like `search_embedded`, but also returns the id of each document.
+    async fn search_embedded_with_ids(
+        &self,
+        embedded_query: Vec<f32>,
+        limit: u32,
+    ) -> Result<Vec<(usize, Document<M>, f32)>, HnswVectorStoreError<E::Error, D::Error>> {
+        let document_store_arc = self.document_store.clone();
+        let document_store = document_store_arc.lock().await;
+
+        let ef_search = 30;
+        let limit = limit as usize;
+        let now = (self.clock)();
+        let mut knbn = limit + self.point_map.lock().unwrap().tombstones.len();
+        loop {
+            let (res, exhausted) = {
+                let point_map = self.point_map.lock().unwrap();
+                let neighbours = self.hnsw.search(&embedded_query, knbn, ef_search);
+                let exhausted = neighbours.len() < knbn;
+                let res = neighbours
+                    .into_iter()
+                    .filter(|r| !point_map.tombstones.contains(&r.d_id))
+                    .map(|r| (point_map.document_id(r.d_id), r.distance))
+                    .collect::<Vec<_>>();
+                (res, exhausted)
+            };
+
+            let mut out = vec![];
+            for (id, distance) in res {
+                let doc = document_store
+                    .get(&id)
+                    .await
+                    .map_err(HnswVectorStoreError::DocumentStoreError)?;
+                match doc {
+                    Some(doc) if doc.is_expired(now) => continue,
+                    Some(doc) => out.push((id, doc, 1.0 - distance)),
+                    None => return Err(HnswVectorStoreError::RelatedDocumentNotFound(id)),
+                }
+                if out.len() == limit {
+                    break;
+                }
+            }
+            if out.len() == limit || exhausted {
+                return Ok(out);
+            }
+            knbn *= 2;
+        }
+    }
+
+    /// Deletes the expired documents from the document store, and leaves them out of the index.
+    ///
+    /// Returns the ids of the deleted documents. Expired documents are left out of search results
+    /// even if they are not purged, purging reclaims the space they take up in the document store.
+    pub async fn purge_expired(
+        &self,
+    ) -> Result<Vec<String>, HnswVectorStoreError<E::Error, D::Error>> {
+        let mut document_store = self.document_store.lock().await;
+        let purged = document_store
+            .purge_expired()
+            .await
+            .map_err(HnswVectorStoreError::DocumentStoreError)?;
+        let mut point_map = self.point_map.lock().unwrap();
+        let mut bm25 = self.bm25.lock().unwrap();
+        for &id in &purged {
+            point_map.remove(id);
+            bm25.remove(id);
+        }
+        Ok(purged.iter().map(|id| id.to_string()).collect())
+    }
+
+    /// Replaces the content of the document with the given id, re-embedding it.
+    ///
+    /// The document keeps its id, so references to it stay valid.
+    pub async fn update_document(
+        &self,
+        id: &str,
+        new_document: Document<M>,
+    ) -> Result<(), HnswVectorStoreError<E::Error, D::Error>> {
+        let document_id = id
+            .parse::<usize>()
+            .map_err(|_| HnswVectorStoreError::UnknownDocumentId(id.to_string()))?;
+        let document_store_arc = self.document_store.clone();
+        let mut document_store = document_store_arc.lock().await;
+        if document_store
+            .get(&document_id)
+            .await
+            .map_err(HnswVectorStoreError::DocumentStoreError)?
+            .is_none()
+        {
+            return Err(HnswVectorStoreError::UnknownDocumentId(id.to_string()));
+        }
+
+        let embedding_vec = self
+            .embeddings
+            .embed_texts(vec![new_document.page_content.clone()])
+            .await?
+            .pop()
+            .ok_or(HnswVectorStoreError::NoEmbeddingReturned)?;
+
+        document_store
+            .replace(&document_id, &new_document)
+            .await
+            .map_err(HnswVectorStoreError::DocumentStoreError)?;
+        self.bm25
+            .lock()
+            .unwrap()
+            .insert(document_id, &new_document.page_content);
+        let point = self.point_map.lock().unwrap().reinsert(document_id);
+        self.hnsw.insert((&embedding_vec, point));
+        Ok(())
+    }
+
+    /// Finds the `k` documents that best match `query`, by both meaning and keywords.
+    ///
+    /// Each document is scored `alpha * vector_score + (1 - alpha) * keyword_score`, so an `alpha`
+    /// of 1 is a pure vector search and an `alpha` of 0 a pure keyword search. The vector score is
+    /// the cosine similarity between the document and the query. The keyword score is the
+    /// document's BM25 score divided by the best BM25 score for the query, so the best keyword
+    /// match scores 1 and both scores mostly fall between 0 and 1.
+    ///
+    /// The candidates are the best `4 * k` matches of each search. A document found by only one of
+    /// the searches scores 0 in the other.
+    pub async fn hybrid_search(
+        &self,
+        query: String,
+        k: u32,
+        alpha: f32,
+    ) -> Result<Vec<(Document<M>, f32)>, HnswVectorStoreError<E::Error, D::Error>> {
+        let fetch_k = k.saturating_mul(HYBRID_CANDIDATES_FACTOR);
+        let keyword_scores = self.bm25.lock().unwrap().search(&query, fetch_k as usize);
+        let best_keyword_score = keyword_scores.first().map_or(1.0, |&(_, score)| score);
+
+        let embedded_query = self.embeddings.embed_query(query).await?;
+        let mut candidates: HashMap<usize, (Option<Document<M>>, f32, f32)> = self
+            .search_embedded_with_ids(embedded_query, fetch_k)
+            .await?
+            .into_iter()
+            .map(|(id, doc, score)| (id, (Some(doc), score, 0.0)))
+            .collect();
+        for (id, score) in keyword_scores {
+            candidates.entry(id).or_insert((None, 0.0, 0.0)).2 = score / best_keyword_score;
+        }
+
+        let document_store = self.document_store.lock().await;
+        let now = (self.clock)();
+        let mut results = Vec::with_capacity(candidates.len());
+        for (id, (doc, vector_score, keyword_score)) in candidates {
+            let doc = match doc {
+                Some(doc) => doc,
+                None => match document_store
+                    .get(&id)
+                    .await
+                    .map_err(HnswVectorStoreError::DocumentStoreError)?
+                {
+                    Some(doc) if doc.is_expired(now) => continue,
+                    Some(doc) => doc,
+                    None => return Err(HnswVectorStoreError::RelatedDocumentNotFound(id)),
+                },
+            };
+            results.push((doc, alpha * vector_score + (1.0 - alpha) * keyword_score));
+        }
+        results.sort_by(|a, b| b.1.total_cmp(&a.1));
+        results.truncate(k as usize);
+        Ok(results)
+    }
+}
+
+impl<E, D, M> HnswVectorStore<E, D, M>
+where
+    E: ImageEmbeddings + Send + Sync,
+    D: DocumentStore<usize, M> + Send + Sync,
+    M: Send + Sync + Serialize + DeserializeOwned,
+{
+    /// Indexes image documents, embedding the image in their `binary_content`.
+    ///
+    /// Images are stored alongside text documents, so text queries can find images and image queries
+    /// can find text.
+    pub async fn add_images(
+        &self,
+        documents: Vec<Document<M>>,
+    ) -> Result<Vec<String>, HnswVectorStoreError<E::Error, D::Error>> {
+        let images = documents
+            .iter()
+            .map(|d| {
+                d.binary_content
+                    .clone()
+                    .map(Image::Bytes)
+                    .ok_or(HnswVectorStoreError::MissingBinaryContent)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let embedding_vecs = self.embeddings.embed_images(images).await?;
+        self.insert_embedded(embedding_vecs, documents).await
+    }
+
+    /// Finds the documents most similar to an image, with their cosine similarity.
+    pub async fn similarity_search_by_image(
+        &self,
+        image: Image,
+        limit: u32,
+    ) -> Result<Vec<(Document<M>, f32)>, HnswVectorStoreError<E::Error, D::Error>> {
+        let embedded_image = self
+            .embeddings
+            .embed_images(vec![image])
+            .await?
+            .pop()
+            .ok_or(HnswVectorStoreError::NoEmbeddingReturned)?;
+        self.search_embedded(embedded_image, limit).await
+    }
 }
 
 #[derive(Debug, Error)]
@@ -133,6 +507,12 @@ where
     FileDumpError(String),
     #[error("Unable to load hnsw index from file: \"{0}\"")]
     FileLoadError(String),
+    #[error("Image document has no binary content")]
+    MissingBinaryContent,
+    #[error("The embeddings model returned no embedding")]
+    NoEmbeddingReturned,
+    #[error("No document with id \"{0}\"")]
+    UnknownDocumentId(String),
 }
 
 impl<E, D> VectorStoreError for HnswVectorStoreError<E, D>
@@ -171,6 +551,7 @@ where
             .zip(ids.iter());
 
         for ((vec, text), id) in iter {
+            self.bm25.lock().unwrap().insert(*id, &text);
             document_store
                 .insert(&HashMap::from([(id.to_owned(), Document::new(text))]))
                 .await
@@ -186,38 +567,9 @@ where
     }
 
     async fn add_documents(&self, documents: Vec<Document<M>>) -> Result<Vec<String>, Self::Error> {
-        let document_store_arc = self.document_store.clone();
-        let mut document_store = document_store_arc.lock().await;
-
         let texts = documents.iter().map(|d| d.page_content.clone()).collect();
         let embedding_vecs = self.embeddings.embed_texts(texts).await?;
-
-        let next_id = document_store
-            .next_id()
-            .await
-            .map_err(HnswVectorStoreError::DocumentStoreError)?;
-        let ids = (0..embedding_vecs.len())
-            .map(|i| next_id + i)
-            .collect::<Vec<usize>>();
-
-        let iter = embedding_vecs
-            .into_iter()
-            .zip(documents.into_iter())
-            .zip(ids.iter());
-
-        for ((vec, document), id) in iter {
-            document_store
-                .insert(&HashMap::from([(id.to_owned(), document)]))
-                .await
-                .map_err(HnswVectorStoreError::DocumentStoreError)?;
-            self.hnsw.insert((&vec, id.to_owned()));
-        }
-
-        let ids_str = ids
-            .iter()
-            .map(|&id| format!("{}", id))
-            .collect::<Vec<String>>();
-        Ok(ids_str)
+        self.insert_embedded(embedding_vecs, documents).await
     }
 
     async fn similarity_search(
@@ -225,25 +577,260 @@ where
         query: String,
         limit: u32,
     ) -> Result<Vec<Document<M>>, Self::Error> {
-        let document_store_arc = self.document_store.clone();
-        let document_store = document_store_arc.lock().await;
+        Ok(self
+            .similarity_search_with_score(query, limit)
+            .await?
+            .into_iter()
+            .map(|(doc, _)| doc)
+            .collect())
+    }
 
+    /// The score is the cosine similarity, i.e. one minus the cosine distance.
+    async fn similarity_search_with_score(
+        &self,
+        query: String,
+        limit: u32,
+    ) -> Result<Vec<(Document<M>, f32)>, Self::Error> {
         let embedded_query = self.embeddings.embed_query(query).await?;
+        self.search_embedded(embedded_query, limit).await
+    }
+}
 
-        let ef_search = 30;
-        let res = self.hnsw.search(&embedded_query, limit as usize, ef_search);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use llm_chain::document_stores::in_memory_document_store::InMemoryDocumentStore;
 
-        let mut out = vec![];
-        for r in res {
-            let id = r.d_id;
-            let doc = document_store
-                .get(&id)
-                .await
-                .map_err(HnswVectorStoreError::DocumentStoreError)?
-                .ok_or_else(|| HnswVectorStoreError::RelatedDocumentNotFound(r.d_id))?;
-            out.push(doc);
+    #[derive(Debug, Error)]
+    #[error("unreachable")]
+    struct WordEmbeddingsError;
+
+    impl EmbeddingsError for WordEmbeddingsError {}
+
+    /// Embeds texts by counting the words "cat" and "dog" in them. Images are embedded as the text
+    /// their bytes spell.
+    struct WordEmbeddings;
+
+    fn embed(text: &str) -> Vec<f32> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let count = |word: &str| words.iter().filter(|w| **w == word).count() as f32;
+        vec![count("cat"), count("dog"), 0.1]
+    }
+
+    #[async_trait]
+    impl Embeddings for WordEmbeddings {
+        type Error = WordEmbeddingsError;
+
+        async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+            Ok(texts.iter().map(|text| embed(text)).collect())
+        }
+
+        async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+            Ok(embed(&query))
         }
+    }
+
+    #[async_trait]
+    impl ImageEmbeddings for WordEmbeddings {
+        async fn embed_images(&self, images: Vec<Image>) -> Result<Vec<Vec<f32>>, Self::Error> {
+            Ok(images
+                .iter()
+                .map(|image| match image {
+                    Image::Bytes(bytes) => embed(&String::from_utf8_lossy(bytes)),
+                    Image::Path(_) => unimplemented!(),
+                })
+                .collect())
+        }
+    }
+
+    type TestStore = HnswVectorStore<WordEmbeddings, InMemoryDocumentStore<()>, ()>;
+
+    fn store() -> TestStore {
+        HnswVectorStore::new(
+            HnswArgs::default(),
+            Arc::new(WordEmbeddings),
+            Arc::new(Mutex::new(InMemoryDocumentStore::new())),
+        )
+    }
+
+    fn contents(results: &[(Document<()>, f32)]) -> Vec<&str> {
+        results
+            .iter()
+            .map(|(doc, _)| doc.page_content.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search_fuses_vector_and_keyword_scores() {
+        let store = store();
+        let texts = ["cat cat cat", "dog", "cat food for dogs"];
+        store
+            .add_texts(texts.iter().map(|t| t.to_string()).collect())
+            .await
+            .unwrap();
+
+        // The vector search prefers the document pointing the same way as the query, BM25 the one
+        // repeating the query term.
+        let vector = store
+            .hybrid_search("cat".to_string(), 3, 1.0)
+            .await
+            .unwrap();
+        assert_eq!(contents(&vector)[..2], ["cat food for dogs", "cat cat cat"]);
+        let keyword = store
+            .hybrid_search("cat".to_string(), 3, 0.0)
+            .await
+            .unwrap();
+        assert_eq!(
+            contents(&keyword)[..2],
+            ["cat cat cat", "cat food for dogs"]
+        );
+        assert_eq!(keyword[0].1, 1.0);
+        assert_eq!(contents(&keyword)[2], "dog");
+        assert_eq!(keyword[2].1, 0.0);
+
+        let blended = store
+            .hybrid_search("cat".to_string(), 2, 0.5)
+            .await
+            .unwrap();
+        assert_eq!(blended.len(), 2);
+        assert!(blended
+            .iter()
+            .all(|(doc, _)| doc.page_content.contains("cat")));
+    }
+
+    #[tokio::test]
+    async fn test_searches_images_alongside_texts() {
+        let store = store();
+        store.add_texts(vec!["dog".to_string()]).await.unwrap();
+        let ids = store
+            .add_images(vec![Document::for_image(
+                b"cat".to_vec(),
+                "A picture".to_string(),
+            )])
+            .await
+            .unwrap();
+        assert_eq!(ids, ["1"]);
+
+        let found = store
+            .similarity_search_by_image(Image::Bytes(b"cat cat".to_vec()), 1)
+            .await
+            .unwrap();
+        assert_eq!(contents(&found), ["A picture"]);
+        assert_eq!(found[0].0.binary_content.as_deref(), Some(&b"cat"[..]));
+        let found = store
+            .similarity_search_by_image(Image::Bytes(b"dog".to_vec()), 1)
+            .await
+            .unwrap();
+        assert_eq!(contents(&found), ["dog"]);
+
+        // Text queries find images by what their embedding shows, not by their caption.
+        let found = store.similarity_search("cat".to_string(), 1).await.unwrap();
+        assert_eq!(found[0].page_content, "A picture");
+
+        assert!(matches!(
+            store
+                .add_images(vec![Document::new("No image".to_string())])
+                .await,
+            Err(HnswVectorStoreError::MissingBinaryContent)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_skips_expired_documents() {
+        let now = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+        let store: TestStore = HnswVectorStore::new(
+            HnswArgs::default(),
+            Arc::new(WordEmbeddings),
+            Arc::new(Mutex::new(
+                InMemoryDocumentStore::new().with_clock(move || now),
+            )),
+        )
+        .with_clock(move || now);
+        store
+            .add_documents(vec![
+                Document::new("cat".to_string()).with_expires_at(now),
+                Document::new("cat food".to_string()).with_expires_at(now + Duration::hours(1)),
+            ])
+            .await
+            .unwrap();
+
+        let found = store.similarity_search("cat".to_string(), 2).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].page_content, "cat food");
+        let found = store
+            .hybrid_search("cat".to_string(), 2, 0.0)
+            .await
+            .unwrap();
+        assert_eq!(contents(&found), ["cat food"]);
+
+        assert_eq!(store.purge_expired().await.unwrap(), ["0"]);
+        let found = store.similarity_search("cat".to_string(), 2).await.unwrap();
+        assert_eq!(found.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reports_documents_missing_from_the_document_store() {
+        let now = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+        let document_store = Arc::new(Mutex::new(InMemoryDocumentStore::new()));
+        let store: TestStore = HnswVectorStore::new(
+            HnswArgs::default(),
+            Arc::new(WordEmbeddings),
+            document_store.clone(),
+        );
+        store
+            .add_documents(vec![
+                Document::new("cat".to_string()).with_expires_at(now),
+                Document::new("cat food".to_string()),
+            ])
+            .await
+            .unwrap();
+        // Purging the document store directly leaves the index pointing at a deleted document.
+        document_store.lock().await.purge_expired().await.unwrap();
+
+        assert!(matches!(
+            store.similarity_search("cat".to_string(), 2).await,
+            Err(HnswVectorStoreError::RelatedDocumentNotFound(0))
+        ));
+        assert!(matches!(
+            store.hybrid_search("cat".to_string(), 2, 0.0).await,
+            Err(HnswVectorStoreError::RelatedDocumentNotFound(0))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_update_document_reembeds_it() {
+        let store = store();
+        store
+            .add_texts(vec!["cat".to_string(), "cat food".to_string()])
+            .await
+            .unwrap();
+        store
+            .update_document("0", Document::new("dog".to_string()))
+            .await
+            .unwrap();
+
+        // The new vector is found by the new content.
+        let dog = store
+            .similarity_search_with_score("dog".to_string(), 1)
+            .await
+            .unwrap();
+        assert_eq!(contents(&dog), ["dog"]);
+        assert!(dog[0].1 > 0.99);
+
+        // The old vector is gone, so the document is found once, and only by its new content.
+        let cat = store
+            .similarity_search_with_score("cat".to_string(), 3)
+            .await
+            .unwrap();
+        assert_eq!(contents(&cat), ["cat food", "dog"]);
+        assert!(cat[1].1 < 0.5);
 
-        Ok(out)
+        assert!(matches!(
+            store
+                .update_document("2", Document::new("dog".to_string()))
+                .await,
+            Err(HnswVectorStoreError::UnknownDocumentId(id)) if id == "2"
+        ));
     }
 }
@@ -111,5 +111,9 @@ pub(crate) fn get_executor_initial_opts(opt: &OptionsCascade) -> Option<(String,
     opt_extract!(opt, max_context_size, MaxContextSize);
     let mut cp = ContextParams::new();
     cp.n_ctx = *max_context_size as i32;
+    if let Some(Opt::Seed(seed)) = opt.get(OptDiscriminants::Seed) {
+        // llama.cpp takes a non-negative i32 seed, negative values mean a random seed.
+        cp.seed = (*seed % i32::MAX as u64) as i32;
+    }
     Some((model.to_path(), cp))
 }
@@ -12,6 +12,8 @@ use llm_chain::tokens::{
     PromptTokensError, TokenCollection, TokenCount, Tokenizer, TokenizerError,
 };
 use llm_chain::traits::{ExecutorCreationError, ExecutorError};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use std::convert::Infallible;
 use std::path::Path;
 use thiserror::Error;
@@ -98,12 +100,16 @@ impl llm_chain::traits::Executor for Executor {
             .with_options(&DEFAULT_OPTIONS)
             .with_options(&self.options)
             .with_options(options);
+        let mut rng = match opts.get(OptDiscriminants::Seed) {
+            Some(Opt::Seed(seed)) => StdRng::seed_from_u64(*seed),
+            _ => StdRng::from_entropy(),
+        };
         let session = &mut self.llm.start_session(Default::default());
         let mut output = String::new();
         session
             .infer::<Infallible>(
                 self.llm.as_ref(),
-                &mut rand::thread_rng(),
+                &mut rng,
                 &InferenceRequest {
                     prompt: prompt.to_text().as_str(),
                     parameters: Some(
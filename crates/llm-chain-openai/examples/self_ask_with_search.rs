@@ -14,6 +14,7 @@ async fn main() {
         search_tool,
         EarlyStoppingConfig {
             max_iterations: Some(10),
+            max_tool_calls: None,
             max_time_elapsed_seconds: Some(30.0),
         },
     );
@@ -0,0 +1,262 @@
+use super::executor::OpenAITokenizer;
+use super::prompt::{
+    completion_to_output, create_chat_completion_request, format_chat_messages, stream_to_output,
+};
+use super::sse::parse_event_stream;
+use async_openai::types::{CreateChatCompletionRequest, CreateChatCompletionResponse};
+use async_trait::async_trait;
+use llm_chain::options::{Opt, OptDiscriminants, Options, OptionsCascade};
+use llm_chain::output::Output;
+use llm_chain::prompt::Prompt;
+use llm_chain::tokens::{PromptTokensError, TokenCount, TokenizerError};
+use llm_chain::traits::{self, ExecutorCreationError, ExecutorError};
+use tiktoken_rs::async_openai::num_tokens_from_messages;
+
+/// The API version used when none is configured.
+pub const DEFAULT_AZURE_API_VERSION: &str = "2023-05-15";
+
+/// An executor for a chat model deployed on Azure OpenAI.
+///
+/// Azure serves each model from a deployment of an Azure OpenAI resource, and authenticates with an
+/// `api-key` header instead of a bearer token. The model option is only used to count tokens, the
+/// deployment decides which model answers.
+///
+/// `Executor::new` reads the configuration from the `AZURE_OPENAI_RESOURCE`,
+/// `AZURE_OPENAI_DEPLOYMENT`, `AZURE_OPENAI_API_VERSION` and `AZURE_OPENAI_API_KEY` environment
+/// variables. The API key can also be given with the `ApiKey` option.
+#[derive(Clone)]
+pub struct AzureExecutor {
+    resource_name: String,
+    deployment_name: String,
+    api_version: String,
+    api_key: String,
+    options: Options,
+    http_client: reqwest::Client,
+}
+
+impl AzureExecutor {
+    /// Creates an executor for `deployment_name` on the `resource_name` Azure OpenAI resource.
+    pub fn for_deployment(
+        resource_name: &str,
+        deployment_name: &str,
+        api_key: &str,
+        options: Options,
+    ) -> Self {
+        Self {
+            resource_name: resource_name.to_string(),
+            deployment_name: deployment_name.to_string(),
+            api_version: DEFAULT_AZURE_API_VERSION.to_string(),
+            api_key: api_key.to_string(),
+            options,
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    /// Sets the `api-version` sent with each request.
+    pub fn with_api_version(mut self, api_version: &str) -> Self {
+        self.api_version = api_version.to_string();
+        self
+    }
+
+    fn chat_completions_url(&self) -> String {
+        format!(
+            "https://{}.openai.azure.com/openai/deployments/{}/chat/completions",
+            self.resource_name, self.deployment_name
+        )
+    }
+
+    /// Builds the request posting `input` to the deployment.
+    fn chat_completions_request(
+        &self,
+        input: &CreateChatCompletionRequest,
+    ) -> reqwest::RequestBuilder {
+        self.http_client
+            .post(self.chat_completions_url())
+            .query(&[("api-version", &self.api_version)])
+            .header("api-key", &self.api_key)
+            .json(input)
+    }
+
+    fn get_model_from_invocation_options(&self, opts: &OptionsCascade) -> String {
+        match opts.get(OptDiscriminants::Model) {
+            Some(Opt::Model(model)) => model.to_name(),
+            _ => "gpt-3.5-turbo".to_string(),
+        }
+    }
+
+    fn get_max_tokens(&self, opts: &OptionsCascade) -> Option<usize> {
+        match opts.get(OptDiscriminants::MaxTokens) {
+            Some(Opt::MaxTokens(max_tokens)) => Some(*max_tokens),
+            _ => None,
+        }
+    }
+
+    fn cascade<'a>(&'a self, opts: Option<&'a Options>) -> OptionsCascade<'a> {
+        let mut v: Vec<&'a Options> = vec![&self.options];
+        if let Some(o) = opts {
+            v.push(o);
+        }
+        OptionsCascade::from_vec(v)
+    }
+}
+
+/// An error returned by the Azure OpenAI API.
+#[derive(thiserror::Error, Debug)]
+#[error("Azure OpenAI returned {status}: {message}")]
+pub struct AzureError {
+    pub status: reqwest::StatusCode,
+    pub message: String,
+}
+
+impl AzureError {
+    /// Reads the error message from the body of a failed response.
+    fn from_body(status: reqwest::StatusCode, body: &serde_json::Value) -> Self {
+        let message = body["error"]["message"]
+            .as_str()
+            .unwrap_or("no error message")
+            .to_string();
+        Self { status, message }
+    }
+}
+
+fn inner_error<E: std::error::Error + Send + Sync + 'static>(error: E) -> ExecutorError {
+    ExecutorError::InnerError(Box::new(error))
+}
+
+#[async_trait]
+impl traits::Executor for AzureExecutor {
+    type StepTokenizer<'a> = OpenAITokenizer;
+
+    fn new_with_options(options: Options) -> Result<Self, ExecutorCreationError> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .map_err(|_| ExecutorCreationError::FieldRequiredError(name.to_string()))
+        };
+        let api_key = match OptionsCascade::new()
+            .with_options(&options)
+            .get(OptDiscriminants::ApiKey)
+        {
+            Some(Opt::ApiKey(api_key)) => api_key.clone(),
+            _ => var("AZURE_OPENAI_API_KEY")?,
+        };
+        let executor = Self::for_deployment(
+            &var("AZURE_OPENAI_RESOURCE")?,
+            &var("AZURE_OPENAI_DEPLOYMENT")?,
+            &api_key,
+            options,
+        );
+        Ok(match std::env::var("AZURE_OPENAI_API_VERSION") {
+            Ok(api_version) => executor.with_api_version(&api_version),
+            Err(_) => executor,
+        })
+    }
+
+    async fn execute(&self, options: &Options, prompt: &Prompt) -> Result<Output, ExecutorError> {
+        let opts = self.cascade(Some(options));
+        let model = self.get_model_from_invocation_options(&opts);
+        let max_tokens = self.get_max_tokens(&opts);
+        let input = create_chat_completion_request(model, prompt, opts.is_streaming(), max_tokens)
+            .map_err(inner_error)?;
+        let response = self
+            .chat_completions_request(&input)
+            .send()
+            .await
+            .map_err(inner_error)?;
+        let status = response.status();
+        if !status.is_success() {
+            let body: serde_json::Value = response.json().await.unwrap_or_default();
+            return Err(inner_error(AzureError::from_body(status, &body)));
+        }
+        if opts.is_streaming() {
+            Ok(stream_to_output(parse_event_stream(Box::pin(
+                response.bytes_stream(),
+            ))))
+        } else {
+            let completion: CreateChatCompletionResponse =
+                response.json().await.map_err(inner_error)?;
+            Ok(completion_to_output(completion))
+        }
+    }
+
+    fn tokens_used(
+        &self,
+        opts: &Options,
+        prompt: &Prompt,
+    ) -> Result<TokenCount, PromptTokensError> {
+        let model = self.get_model_from_invocation_options(&self.cascade(Some(opts)));
+        let messages = format_chat_messages(prompt.to_chat())?;
+        let tokens_used = num_tokens_from_messages(&model, &messages)
+            .map_err(|_| PromptTokensError::NotAvailable)?;
+        Ok(TokenCount::new(
+            self.max_tokens_allowed(opts),
+            tokens_used as i32,
+        ))
+    }
+
+    fn max_tokens_allowed(&self, opts: &Options) -> i32 {
+        let model = self.get_model_from_invocation_options(&self.cascade(Some(opts)));
+        tiktoken_rs::model::get_context_size(&model)
+            .try_into()
+            .unwrap_or(4096)
+    }
+
+    fn answer_prefix(&self, _prompt: &Prompt) -> Option<String> {
+        None
+    }
+
+    fn get_tokenizer(&self, options: &Options) -> Result<OpenAITokenizer, TokenizerError> {
+        Ok(OpenAITokenizer::new(self.cascade(Some(options))))
+    }
+
+    fn configured_model(&self) -> Option<String> {
+        Some(self.deployment_name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn executor() -> AzureExecutor {
+        AzureExecutor::for_deployment(
+            "my-resource",
+            "my-deployment",
+            "secret",
+            Options::builder().build(),
+        )
+        .with_api_version("2024-02-01")
+    }
+
+    #[test]
+    fn test_builds_deployment_request() {
+        let input = create_chat_completion_request(
+            "gpt-3.5-turbo".to_string(),
+            &Prompt::text("Hello".to_string()),
+            false,
+            None,
+        )
+        .unwrap();
+        let request = executor().chat_completions_request(&input).build().unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "https://my-resource.openai.azure.com/openai/deployments/my-deployment/chat/completions?api-version=2024-02-01"
+        );
+        assert_eq!(request.headers()["api-key"], "secret");
+        assert!(request.headers().get("authorization").is_none());
+    }
+
+    #[test]
+    fn test_reads_error_message_from_body() {
+        let status = reqwest::StatusCode::UNAUTHORIZED;
+        let body = serde_json::json!({"error": {"code": "401", "message": "Access denied"}});
+        let error = AzureError::from_body(status, &body);
+        assert_eq!(error.message, "Access denied");
+        assert_eq!(
+            error.to_string(),
+            "Azure OpenAI returned 401 Unauthorized: Access denied"
+        );
+
+        let error = AzureError::from_body(status, &serde_json::Value::Null);
+        assert_eq!(error.message, "no error message");
+    }
+}
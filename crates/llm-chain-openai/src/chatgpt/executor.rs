@@ -1,14 +1,23 @@
+use super::key_pool::ApiKeyPool;
 use super::prompt::completion_to_output;
 use super::prompt::stream_to_output;
+use super::sse::parse_event_stream;
+use llm_chain::executor::{Heartbeat, RequestHook, RequestMetrics};
+use llm_chain::options::IdempotencyKey;
 use llm_chain::options::Opt;
 use llm_chain::options::Options;
 use llm_chain::options::OptionsCascade;
+use llm_chain::options::ResponseFormat;
 use llm_chain::output::Output;
 use llm_chain::tokens::TokenCollection;
 
 use super::prompt::create_chat_completion_request;
 use super::prompt::format_chat_messages;
 use async_openai::error::OpenAIError;
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionResponseStream, CreateChatCompletionRequest,
+    CreateChatCompletionResponse, Role, Usage,
+};
 use llm_chain::prompt::Prompt;
 
 use llm_chain::tokens::PromptTokensError;
@@ -21,7 +30,14 @@ use llm_chain::tokens::TokenCount;
 
 use tiktoken_rs::async_openai::num_tokens_from_messages;
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The header used to send idempotency keys to the API.
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// How long the list of available models is reused before it is fetched again.
+const MODEL_LIST_TTL: Duration = Duration::from_secs(300);
 
 /// The `Executor` struct for the ChatGPT model. This executor uses the `async_openai` crate to communicate with the OpenAI API.
 #[derive(Clone, Default)]
@@ -30,10 +46,25 @@ pub struct Executor {
     client: Arc<async_openai::Client>,
     /// The per-invocation options for this executor.
     options: Options,
+    /// The API keys requests are spread across, if any.
+    key_pool: Option<Arc<ApiKeyPool>>,
+    /// Invoked periodically while a request is in flight, if set.
+    heartbeat: Option<Heartbeat>,
+    /// Invoked after every request, if set.
+    on_request: Option<RequestHook>,
+    /// The most recently fetched list of available models, and when it was fetched.
+    model_list: Arc<Mutex<Option<(Instant, Vec<String>)>>>,
+    /// Sends the requests that carry an idempotency key, which `async_openai` can't attach.
+    http_client: reqwest::Client,
+    /// The organization sent with requests made through `http_client`, if any.
+    org_id: Option<String>,
 }
 
 impl Executor {
     /// Creates a new `Executor` with the given client.
+    ///
+    /// Requests with an idempotency key are sent to the client's API base with its API key, and
+    /// with the organization from the `OPENAI_ORG_ID` environment variable.
     pub fn for_client(client: async_openai::Client, options: Options) -> Self {
         use llm_chain::traits::Executor as _;
         let mut exec = Self::new_with_options(options).unwrap();
@@ -41,13 +72,194 @@ impl Executor {
         exec
     }
 
+    /// Creates a new `Executor` that spreads its requests across the keys in `pool`.
+    ///
+    /// Each request uses the next key from the pool. Keys that are rate limited are put on cooldown.
+    pub fn for_key_pool(pool: ApiKeyPool, options: Options) -> Self {
+        use llm_chain::traits::Executor as _;
+        let mut exec = Self::new_with_options(options).unwrap();
+        exec.key_pool = Some(Arc::new(pool));
+        exec
+    }
+
+    /// Invokes `heartbeat` periodically while a request is waiting for the API to respond.
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
+
+    /// Invokes `on_request` after every request to the API with the model, token usage, latency
+    /// and outcome of the request.
+    pub fn with_on_request<F>(mut self, on_request: F) -> Self
+    where
+        F: Fn(&RequestMetrics) + Send + Sync + 'static,
+    {
+        self.on_request = Some(Arc::new(on_request));
+        self
+    }
+
+    /// Reports a finished request to the `on_request` hook, if set.
+    fn report_request(&self, model: &str, started: Instant, usage: Option<&Usage>, success: bool) {
+        if let Some(on_request) = &self.on_request {
+            on_request(&RequestMetrics {
+                model: model.to_string(),
+                prompt_tokens: usage.map(|usage| usage.prompt_tokens),
+                completion_tokens: usage.map(|usage| usage.completion_tokens),
+                latency: started.elapsed(),
+                success,
+            });
+        }
+    }
+
     fn get_model_from_invocation_options(&self, opts: &OptionsCascade) -> String {
         let Some(Opt::Model(model)) = opts.get(llm_chain::options::OptDiscriminants::Model) else {
-            return "gpt-3.5-turbo".to_string()
+            return "gpt-3.5-turbo".to_string();
         };
         model.to_name()
     }
 
+    fn get_max_tokens(&self, opts: &OptionsCascade) -> Option<usize> {
+        match opts.get(llm_chain::options::OptDiscriminants::MaxTokens) {
+            Some(Opt::MaxTokens(max_tokens)) => Some(*max_tokens),
+            _ => None,
+        }
+    }
+
+    fn get_seed(&self, opts: &OptionsCascade) -> Option<u64> {
+        match opts.get(llm_chain::options::OptDiscriminants::Seed) {
+            Some(Opt::Seed(seed)) => Some(*seed),
+            _ => None,
+        }
+    }
+
+    /// Returns the request parameters `async_openai` can't send, which are added to the body of
+    /// requests sent through `http_client`.
+    fn get_extra_parameters(
+        &self,
+        opts: &OptionsCascade,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        let mut parameters = serde_json::Map::new();
+        if let Some(seed) = self.get_seed(opts) {
+            parameters.insert("seed".to_string(), seed.into());
+        }
+        if let Some(format) = self
+            .get_response_format(opts)
+            .as_ref()
+            .and_then(response_format)
+        {
+            parameters.insert("response_format".to_string(), format);
+        }
+        parameters
+    }
+
+    fn get_response_format(&self, opts: &OptionsCascade) -> Option<ResponseFormat> {
+        match opts.get(llm_chain::options::OptDiscriminants::ResponseFormat) {
+            Some(Opt::ResponseFormat(format)) => Some(format.clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns the idempotency key to send with `request`, if the options ask for one.
+    ///
+    /// The key is sent with every retry of the request, so the provider can deduplicate them.
+    fn get_idempotency_key(
+        &self,
+        opts: &OptionsCascade,
+        request: &CreateChatCompletionRequest,
+    ) -> Result<Option<String>, ExecutorError> {
+        match opts.get(llm_chain::options::OptDiscriminants::IdempotencyKey) {
+            Some(Opt::IdempotencyKey(IdempotencyKey::Fixed(key))) => Ok(Some(key.clone())),
+            Some(Opt::IdempotencyKey(IdempotencyKey::FromRequest)) => {
+                idempotency_key_for(request).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Sends `body`, with an `Idempotency-Key` header if given, returning the response if it
+    /// succeeded.
+    ///
+    /// `async_openai` has no way to add a header or a parameter it doesn't know to a request, so
+    /// such requests are sent with the executor's own HTTP client, which is shared by all requests
+    /// to keep connections alive.
+    async fn post(
+        &self,
+        body: &serde_json::Value,
+        api_key: Option<&str>,
+        idempotency_key: Option<&str>,
+    ) -> Result<reqwest::Response, OpenAIError> {
+        let mut http_request = self
+            .http_client
+            .post(format!("{}/chat/completions", self.client.api_base()))
+            .bearer_auth(api_key.unwrap_or(self.client.api_key()))
+            .json(body);
+        if let Some(idempotency_key) = idempotency_key {
+            http_request = http_request.header(IDEMPOTENCY_KEY_HEADER, idempotency_key);
+        }
+        if let Some(org_id) = &self.org_id {
+            http_request = http_request.header("OpenAI-Organization", org_id);
+        }
+        let response = http_request.send().await?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let mut body: serde_json::Value = response.json().await?;
+        Err(match serde_json::from_value(body["error"].take()) {
+            Ok(error) => OpenAIError::ApiError(error),
+            Err(e) => OpenAIError::JSONDeserialize(e),
+        })
+    }
+
+    /// Creates a completion, returning it with the system fingerprint if the API reported one.
+    async fn create(
+        &self,
+        client: async_openai::Client,
+        request: CreateChatCompletionRequest,
+        extra_parameters: serde_json::Map<String, serde_json::Value>,
+        api_key: Option<&str>,
+        idempotency_key: Option<&str>,
+    ) -> Result<(CreateChatCompletionResponse, Option<String>), OpenAIError> {
+        if idempotency_key.is_none() && extra_parameters.is_empty() {
+            return Ok((client.chat().create(request).await?, None));
+        }
+        let body = request_body(&request, extra_parameters)?;
+        let response = self.post(&body, api_key, idempotency_key).await?;
+        parse_completion(response.json().await?)
+    }
+
+    async fn create_stream(
+        &self,
+        client: async_openai::Client,
+        request: CreateChatCompletionRequest,
+        extra_parameters: serde_json::Map<String, serde_json::Value>,
+        api_key: Option<&str>,
+        idempotency_key: Option<&str>,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        if idempotency_key.is_none() && extra_parameters.is_empty() {
+            return client.chat().create_stream(request).await;
+        }
+        let body = request_body(&request, extra_parameters)?;
+        let response = self.post(&body, api_key, idempotency_key).await?;
+        Ok(parse_event_stream(Box::pin(response.bytes_stream())))
+    }
+
+    /// Converts a client error, putting `key` on cooldown if it was rate limited.
+    fn handle_error(&self, key: Option<&str>, error: OpenAIError) -> ExecutorError {
+        if let (Some(pool), Some(key)) = (&self.key_pool, key) {
+            if is_rate_limited(&error) {
+                pool.mark_rate_limited(key);
+            }
+        }
+        ExecutorError::InnerError(error.into())
+    }
+
+    async fn with_heartbeat_while<F: std::future::Future>(&self, future: F) -> F::Output {
+        match &self.heartbeat {
+            Some(heartbeat) => heartbeat.run(future).await,
+            None => future.await,
+        }
+    }
+
     fn cascade<'a>(&'a self, opts: Option<&'a Options>) -> OptionsCascade<'a> {
         let mut v: Vec<&'a Options> = vec![&self.options];
         if let Some(o) = opts {
@@ -57,10 +269,98 @@ impl Executor {
     }
 }
 
+/// Derives an idempotency key from the serialized request.
+///
+/// The hash doesn't depend on the process or Rust version, so a request resubmitted after a restart
+/// or an upgrade gets the same key.
+fn idempotency_key_for(request: &CreateChatCompletionRequest) -> Result<String, ExecutorError> {
+    let body = serde_json::to_string(request).map_err(|e| ExecutorError::InnerError(e.into()))?;
+    Ok(format!("llm-chain-{:016x}", fnv1a(body.as_bytes())))
+}
+
+/// Serializes `request`, adding the parameters `async_openai` can't send.
+fn request_body(
+    request: &CreateChatCompletionRequest,
+    extra_parameters: serde_json::Map<String, serde_json::Value>,
+) -> Result<serde_json::Value, OpenAIError> {
+    let mut body = serde_json::to_value(request).map_err(OpenAIError::JSONDeserialize)?;
+    if let serde_json::Value::Object(fields) = &mut body {
+        fields.extend(extra_parameters);
+    }
+    Ok(body)
+}
+
+/// Reads a completion and its system fingerprint, which `async_openai` doesn't deserialize, from
+/// the body of a response.
+fn parse_completion(
+    mut body: serde_json::Value,
+) -> Result<(CreateChatCompletionResponse, Option<String>), OpenAIError> {
+    let system_fingerprint = body["system_fingerprint"].as_str().map(str::to_string);
+    let completion = serde_json::from_value(body.take()).map_err(OpenAIError::JSONDeserialize)?;
+    Ok((completion, system_fingerprint))
+}
+
+/// Hashes `bytes` with 64-bit FNV-1a.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Returns true if the API rejected the request because of a rate limit.
+fn is_rate_limited(error: &OpenAIError) -> bool {
+    matches!(error, OpenAIError::ApiError(e) if e.message.to_lowercase().contains("rate limit"))
+}
+
+/// Returns the `response_format` parameter for `format`, if it isn't the default.
+fn response_format(format: &ResponseFormat) -> Option<serde_json::Value> {
+    match format {
+        ResponseFormat::Text => None,
+        ResponseFormat::JsonObject => Some(serde_json::json!({"type": "json_object"})),
+        ResponseFormat::JsonSchema(schema) => Some(serde_json::json!({
+            "type": "json_schema",
+            "json_schema": {"name": "response", "schema": schema},
+        })),
+    }
+}
+
+/// Returns the instruction asking the model to answer in `format`, if it needs one.
+///
+/// The API rejects JSON mode unless the messages mention JSON, and models without structured
+/// output support ignore the schema, so the format is spelled out in a system message too.
+fn json_instruction(format: &ResponseFormat) -> Option<String> {
+    match format {
+        ResponseFormat::Text => None,
+        ResponseFormat::JsonObject => Some("Respond only with a valid JSON object.".to_string()),
+        ResponseFormat::JsonSchema(schema) => Some(format!(
+            "Respond only with a valid JSON object matching this JSON schema:\n{}",
+            schema
+        )),
+    }
+}
+
+/// Checks that the response is a JSON object.
+///
+/// The API already enforces the `response_format`, so this only catches models and deployments that
+/// ignore it. Streamed responses are not checked.
+fn check_json_response(response: &CreateChatCompletionResponse) -> Result<(), Error> {
+    let content = response
+        .choices
+        .first()
+        .map(|choice| choice.message.content.as_str())
+        .unwrap_or_default();
+    match serde_json::from_str::<serde_json::Value>(content.trim()) {
+        Ok(serde_json::Value::Object(_)) => Ok(()),
+        _ => Err(Error::InvalidJson(content.to_string())),
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
-#[error(transparent)]
 pub enum Error {
+    #[error(transparent)]
     OpenAIError(#[from] OpenAIError),
+    #[error("The model did not respond with a JSON object: {0}")]
+    InvalidJson(String),
 }
 
 #[async_trait]
@@ -77,28 +377,82 @@ impl traits::Executor for Executor {
             client = client.with_api_key(api_key)
         }
 
-        if let Ok(org_id) = std::env::var("OPENAI_ORG_ID") {
+        let org_id = std::env::var("OPENAI_ORG_ID").ok();
+        if let Some(org_id) = &org_id {
             client = client.with_org_id(org_id);
         }
         let client = Arc::new(client);
-        Ok(Self { client, options })
+        Ok(Self {
+            client,
+            options,
+            key_pool: None,
+            heartbeat: None,
+            on_request: None,
+            model_list: Default::default(),
+            http_client: reqwest::Client::new(),
+            org_id,
+        })
     }
 
     async fn execute(&self, options: &Options, prompt: &Prompt) -> Result<Output, ExecutorError> {
         let opts = self.cascade(Some(options));
-        let client = self.client.clone();
         let model = self.get_model_from_invocation_options(&opts);
-        let input = create_chat_completion_request(model, prompt, opts.is_streaming()).unwrap();
+        let max_tokens = self.get_max_tokens(&opts);
+        let mut input =
+            create_chat_completion_request(model, prompt, opts.is_streaming(), max_tokens).unwrap();
+        let instruction = self
+            .get_response_format(&opts)
+            .as_ref()
+            .and_then(json_instruction);
+        let expects_json = instruction.is_some();
+        if let Some(instruction) = instruction {
+            input.messages.push(ChatCompletionRequestMessage {
+                role: Role::System,
+                content: instruction,
+                name: None,
+            });
+        }
+        let idempotency_key = self.get_idempotency_key(&opts, &input)?;
+        let extra_parameters = self.get_extra_parameters(&opts);
+        let mut client = (*self.client).clone();
+        let key = self.key_pool.as_ref().and_then(|pool| pool.next_key());
+        if let Some(key) = &key {
+            client = client.with_api_key(key);
+        }
+        let model = input.model.clone();
+        let started = Instant::now();
+        let (api_key, idempotency_key) = (key.as_deref(), idempotency_key.as_deref());
         if opts.is_streaming() {
-            let res = async move { client.chat().create_stream(input).await }
-                .await
-                .map_err(|e| ExecutorError::InnerError(e.into()))?;
+            let res = self
+                .with_heartbeat_while(self.create_stream(
+                    client,
+                    input,
+                    extra_parameters,
+                    api_key,
+                    idempotency_key,
+                ))
+                .await;
+            self.report_request(&model, started, None, res.is_ok());
+            let res = res.map_err(|e| self.handle_error(key.as_deref(), e))?;
             Ok(stream_to_output(res))
         } else {
-            let res = async move { client.chat().create(input).await }
-                .await
-                .map_err(|e| ExecutorError::InnerError(e.into()))?;
-            Ok(completion_to_output(res))
+            let res = self
+                .with_heartbeat_while(self.create(
+                    client,
+                    input,
+                    extra_parameters,
+                    api_key,
+                    idempotency_key,
+                ))
+                .await;
+            let usage = res.as_ref().ok().and_then(|(res, _)| res.usage.as_ref());
+            self.report_request(&model, started, usage, res.is_ok());
+            let (res, system_fingerprint) =
+                res.map_err(|e| self.handle_error(key.as_deref(), e))?;
+            if expects_json {
+                check_json_response(&res).map_err(|e| ExecutorError::InnerError(e.into()))?;
+            }
+            Ok(completion_to_output(res).with_system_fingerprint(system_fingerprint))
         }
     }
 
@@ -134,6 +488,33 @@ impl traits::Executor for Executor {
     fn get_tokenizer(&self, options: &Options) -> Result<OpenAITokenizer, TokenizerError> {
         Ok(OpenAITokenizer::new(self.cascade(Some(options))))
     }
+
+    fn configured_model(&self) -> Option<String> {
+        Some(self.get_model_from_invocation_options(&self.cascade(None)))
+    }
+
+    /// Lists the models available to the API key, from the `/models` endpoint.
+    ///
+    /// The list is cached for a few minutes.
+    async fn list_models(&self) -> Result<Vec<String>, ExecutorError> {
+        if let Some((fetched_at, models)) = &*self.model_list.lock().unwrap() {
+            if fetched_at.elapsed() < MODEL_LIST_TTL {
+                return Ok(models.clone());
+            }
+        }
+        let models: Vec<String> = self
+            .client
+            .models()
+            .list()
+            .await
+            .map_err(|e| ExecutorError::InnerError(e.into()))?
+            .data
+            .into_iter()
+            .map(|model| model.id)
+            .collect();
+        *self.model_list.lock().unwrap() = Some((Instant::now(), models.clone()));
+        Ok(models)
+    }
 }
 
 pub struct OpenAITokenizer {
@@ -178,3 +559,108 @@ impl Tokenizer for OpenAITokenizer {
         Ok(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn test_idempotency_key_depends_only_on_request() {
+        let request = |text: &str| {
+            create_chat_completion_request(
+                "gpt-3.5-turbo".to_string(),
+                &Prompt::text(text.to_string()),
+                false,
+                None,
+            )
+            .unwrap()
+        };
+        let key = idempotency_key_for(&request("Hello")).unwrap();
+        assert!(key.starts_with("llm-chain-"));
+        assert_eq!(key.len(), "llm-chain-".len() + 16);
+        assert_eq!(key, idempotency_key_for(&request("Hello")).unwrap());
+        assert_ne!(key, idempotency_key_for(&request("Goodbye")).unwrap());
+    }
+
+    #[test]
+    fn test_sends_seed_and_reads_system_fingerprint() {
+        let executor = Executor::for_client(
+            async_openai::Client::new(),
+            llm_chain::options!(Seed: 42_u64),
+        );
+        let request = create_chat_completion_request(
+            "gpt-3.5-turbo".to_string(),
+            &Prompt::text("Hello".to_string()),
+            false,
+            None,
+        )
+        .unwrap();
+        let extra_parameters = executor.get_extra_parameters(&executor.cascade(None));
+        let body = request_body(&request, extra_parameters).unwrap();
+        assert_eq!(body["seed"], 42);
+        assert_eq!(body["model"], "gpt-3.5-turbo");
+        assert_eq!(body["messages"][0]["content"], "Hello");
+
+        let response = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-3.5-turbo",
+            "system_fingerprint": "fp_44709d6fcb",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi"},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+        });
+        let (completion, system_fingerprint) = parse_completion(response).unwrap();
+        assert_eq!(completion.choices[0].message.content, "Hi");
+        assert_eq!(system_fingerprint.as_deref(), Some("fp_44709d6fcb"));
+        let output = completion_to_output(completion).with_system_fingerprint(system_fingerprint);
+        match output {
+            Output::Immediate(immediate) => {
+                assert_eq!(immediate.system_fingerprint(), Some("fp_44709d6fcb"))
+            }
+            Output::Stream(_) => panic!("expected an immediate output"),
+        }
+    }
+
+    #[test]
+    fn test_sends_response_format() {
+        let request = create_chat_completion_request(
+            "gpt-3.5-turbo".to_string(),
+            &Prompt::text("Hello".to_string()),
+            false,
+            None,
+        )
+        .unwrap();
+        let body_for = |format: ResponseFormat| {
+            let executor = Executor::for_client(
+                async_openai::Client::new(),
+                llm_chain::options!(ResponseFormat: format),
+            );
+            let extra_parameters = executor.get_extra_parameters(&executor.cascade(None));
+            request_body(&request, extra_parameters).unwrap()
+        };
+        assert_eq!(
+            body_for(ResponseFormat::JsonObject)["response_format"],
+            serde_json::json!({"type": "json_object"})
+        );
+        let schema =
+            serde_json::json!({"type": "object", "properties": {"answer": {"type": "string"}}});
+        let body = body_for(ResponseFormat::JsonSchema(schema.clone()));
+        assert_eq!(body["response_format"]["type"], "json_schema");
+        assert_eq!(body["response_format"]["json_schema"]["schema"], schema);
+        assert!(body_for(ResponseFormat::Text)
+            .get("response_format")
+            .is_none());
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The default amount of time a rate limited key is skipped for.
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// A pool of OpenAI API keys that an `Executor` spreads its requests across.
+///
+/// Keys are picked using smooth weighted round-robin, so a key with weight 2 receives twice as many
+/// requests as a key with weight 1, without the requests being bunched together. A key that gets
+/// rate limited is skipped until its cooldown has passed. If every key is cooling down, the one that
+/// becomes available first is used.
+///
+/// Note that the `async_openai` client retries rate limited requests on its own before reporting
+/// the error, so you may want to configure a shorter backoff on the client for rotation to kick in
+/// quickly.
+///
+/// # Example
+///
+/// ```
+/// use llm_chain_openai::chatgpt::ApiKeyPool;
+///
+/// let pool = ApiKeyPool::weighted(vec![("sk-first", 2), ("sk-second", 1)]);
+/// assert_eq!(pool.next_key().as_deref(), Some("sk-first"));
+/// assert_eq!(pool.next_key().as_deref(), Some("sk-second"));
+/// assert_eq!(pool.next_key().as_deref(), Some("sk-first"));
+/// ```
+pub struct ApiKeyPool {
+    keys: Mutex<Vec<PooledKey>>,
+    cooldown: Duration,
+}
+
+struct PooledKey {
+    key: String,
+    weight: i64,
+    current_weight: i64,
+    cooldown_until: Option<Instant>,
+}
+
+impl ApiKeyPool {
+    /// Creates a pool where every key has the same weight.
+    pub fn new<S: Into<String>>(keys: Vec<S>) -> Self {
+        Self::weighted(keys.into_iter().map(|k| (k, 1)).collect())
+    }
+
+    /// Creates a pool from keys and their weights.
+    pub fn weighted<S: Into<String>>(keys: Vec<(S, u32)>) -> Self {
+        let keys = keys
+            .into_iter()
+            .map(|(key, weight)| PooledKey {
+                key: key.into(),
+                weight: weight.max(1) as i64,
+                current_weight: 0,
+                cooldown_until: None,
+            })
+            .collect();
+        Self {
+            keys: Mutex::new(keys),
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+
+    /// Sets how long a rate limited key is skipped for.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Picks the key to use for the next request, or `None` if the pool is empty.
+    pub fn next_key(&self) -> Option<String> {
+        let mut keys = self.keys.lock().unwrap();
+        let now = Instant::now();
+        let is_available = |k: &PooledKey| k.cooldown_until.map_or(true, |until| until <= now);
+
+        if !keys.iter().any(is_available) {
+            return keys
+                .iter()
+                .min_by_key(|k| k.cooldown_until)
+                .map(|k| k.key.clone());
+        }
+
+        let mut total = 0;
+        let mut selected: Option<(usize, i64)> = None;
+        for (idx, key) in keys.iter_mut().enumerate() {
+            if !is_available(key) {
+                continue;
+            }
+            key.cooldown_until = None;
+            key.current_weight += key.weight;
+            total += key.weight;
+            if selected.map_or(true, |(_, best)| key.current_weight > best) {
+                selected = Some((idx, key.current_weight));
+            }
+        }
+        let (idx, _) = selected?;
+        let selected = &mut keys[idx];
+        selected.current_weight -= total;
+        Some(selected.key.clone())
+    }
+
+    /// Marks `key` as rate limited, so it is skipped until its cooldown has passed.
+    pub fn mark_rate_limited(&self, key: &str) {
+        let mut keys = self.keys.lock().unwrap();
+        if let Some(k) = keys.iter_mut().find(|k| k.key == key) {
+            k.cooldown_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
@@ -1,7 +1,12 @@
 //! This module implements chains for the ChatGPT model from OpenAI.
+mod azure;
 mod executor;
+mod key_pool;
 mod model;
 mod prompt;
+mod sse;
 
+pub use azure::{AzureError, AzureExecutor, DEFAULT_AZURE_API_VERSION};
 pub use executor::{Error, Executor};
+pub use key_pool::ApiKeyPool;
 pub use model::Model;
@@ -46,10 +46,14 @@ pub fn format_chat_messages(
     messages.iter().map(format_chat_message).collect()
 }
 
+/// Builds the request for `prompt`, limiting the answer to `max_tokens` if set.
+///
+/// A `max_tokens` too large for the API is left unset, so the model's own limit applies.
 pub fn create_chat_completion_request(
     model: String,
     prompt: &Prompt,
     is_streaming: bool,
+    max_tokens: Option<usize>,
 ) -> Result<CreateChatCompletionRequest, StringTemplateError> {
     let messages = format_chat_messages(prompt.to_chat())?;
     Ok(CreateChatCompletionRequest {
@@ -60,7 +64,7 @@ pub fn create_chat_completion_request(
         n: Some(1),
         stream: Some(is_streaming),
         stop: None,
-        max_tokens: None, // We should consider something here
+        max_tokens: max_tokens.and_then(|max_tokens| max_tokens.try_into().ok()),
         presence_penalty: None,
         frequency_penalty: None,
         logit_bias: None,
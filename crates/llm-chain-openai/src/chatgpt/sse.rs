@@ -0,0 +1,130 @@
+use async_openai::error::OpenAIError;
+use async_openai::types::{ChatCompletionResponseStream, CreateChatCompletionStreamResponse};
+use futures::{Stream, StreamExt};
+
+/// Turns the server-sent events of a streaming response into a stream of completion chunks.
+///
+/// The raw bytes are buffered until an event is complete, so a character or a line break split
+/// across network chunks is decoded correctly.
+pub(super) fn parse_event_stream<S, B>(bytes: S) -> ChatCompletionResponseStream
+where
+    S: Stream<Item = Result<B, reqwest::Error>> + Send + Unpin + 'static,
+    B: AsRef<[u8]> + Send + 'static,
+{
+    let events = futures::stream::unfold(
+        (bytes, Vec::new(), false),
+        |(mut bytes, mut buffer, done)| async move {
+            if done {
+                return None;
+            }
+            loop {
+                if let Some((end, separator_len)) = find_event_end(&buffer) {
+                    let event: Vec<u8> = buffer.drain(..end + separator_len).collect();
+                    let event = String::from_utf8_lossy(&event[..end]);
+                    let data = event
+                        .lines()
+                        .filter_map(|line| line.strip_prefix("data:"))
+                        .map(str::trim)
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    if data.is_empty() {
+                        continue;
+                    }
+                    if data == "[DONE]" {
+                        return None;
+                    }
+                    let chunk = serde_json::from_str::<CreateChatCompletionStreamResponse>(&data)
+                        .map_err(OpenAIError::JSONDeserialize);
+                    return Some((chunk, (bytes, buffer, false)));
+                }
+                match bytes.next().await {
+                    Some(Ok(chunk)) => buffer.extend_from_slice(chunk.as_ref()),
+                    Some(Err(e)) => {
+                        return Some((Err(OpenAIError::Reqwest(e)), (bytes, buffer, true)))
+                    }
+                    None => return None,
+                }
+            }
+        },
+    );
+    Box::pin(events)
+}
+
+/// Returns where the first complete event in `buffer` ends, and the length of the blank line
+/// ending it.
+fn find_event_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    let find = |separator: &[u8]| {
+        buffer
+            .windows(separator.len())
+            .position(|window| window == separator)
+            .map(|end| (end, separator.len()))
+    };
+    match (find(b"\n\n"), find(b"\r\n\r\n")) {
+        (Some(lf), Some(crlf)) => Some(if lf.0 < crlf.0 { lf } else { crlf }),
+        (lf, crlf) => lf.or(crlf),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(content: &str) -> String {
+        format!(
+            "data: {{\"id\":\"chunk\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"gpt-3.5-turbo\",\"choices\":[{{\"index\":0,\"delta\":{{\"content\":\"{}\"}},\"finish_reason\":null}}]}}",
+            content
+        )
+    }
+
+    /// Parses `body` delivered in chunks split at `splits`, returning the streamed contents.
+    async fn parse_split(body: &str, splits: &[usize]) -> Vec<String> {
+        let bytes = body.as_bytes();
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        for &end in splits.iter().chain([bytes.len()].iter()) {
+            chunks.push(Ok::<_, reqwest::Error>(bytes[start..end].to_vec()));
+            start = end;
+        }
+        parse_event_stream(futures::stream::iter(chunks))
+            .map(|chunk| {
+                chunk.unwrap().choices[0]
+                    .delta
+                    .content
+                    .clone()
+                    .unwrap_or_default()
+            })
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_decodes_characters_split_across_chunks() {
+        let body = format!("{}\n\n{}\n\ndata: [DONE]\n\n", event("é"), event("日本"));
+        let split_e = body.find('é').unwrap() + 1;
+        let split_ja = body.find('日').unwrap() + 2;
+        assert_eq!(
+            parse_split(&body, &[split_e, split_ja]).await,
+            ["é", "日本"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_splits_events_on_crlf_split_across_chunks() {
+        let body = format!(
+            "{}\r\n\r\n{}\r\n\r\ndata: [DONE]\r\n\r\n",
+            event("a"),
+            event("b")
+        );
+        let first_end = body.find("\r\n\r\n").unwrap();
+        for split in first_end + 1..first_end + 4 {
+            assert_eq!(parse_split(&body, &[split]).await, ["a", "b"]);
+        }
+    }
+
+    #[test]
+    fn test_finds_first_event_end() {
+        assert_eq!(find_event_end(b"data: a\r\n\r\ndata: b\n\n"), Some((7, 4)));
+        assert_eq!(find_event_end(b"data: a\n\ndata: b\r\n\r\n"), Some((7, 2)));
+        assert_eq!(find_event_end(b"data: a\r\n"), None);
+    }
+}
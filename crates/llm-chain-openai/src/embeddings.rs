@@ -1,16 +1,46 @@
+use std::ops::Range;
 use std::sync::Arc;
+use std::time::Instant;
 
 use async_openai::{
     error::OpenAIError,
-    types::{CreateEmbeddingRequest, EmbeddingInput},
+    types::{CreateEmbeddingRequest, CreateEmbeddingResponse, EmbeddingInput},
 };
 use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use llm_chain::executor::{RequestHook, RequestMetrics};
 use llm_chain::traits::{self, EmbeddingsError};
 use thiserror::Error;
 
+/// The most texts the embeddings API accepts in one request.
+const DEFAULT_BATCH_SIZE: usize = 2048;
+
+/// The most tokens the embedding models accept in one text.
+const DEFAULT_MAX_INPUT_TOKENS: usize = 8191;
+
+type OversizedInputCallback = Box<dyn Fn(usize, usize) + Send + Sync>;
+
+/// What `embed_texts` and `embed_query` do with a text that has more tokens than the model accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizedInputStrategy {
+    /// Sends the text as is, so the API rejects the request and the whole call fails.
+    #[default]
+    Error,
+    /// Embeds only as many tokens from the start of the text as the model accepts.
+    Truncate,
+    /// Splits the text into pieces the model accepts, embeds each, and averages their vectors.
+    SplitAndAverage,
+}
+
 pub struct Embeddings {
     client: Arc<async_openai::Client>,
     model: String,
+    batch_size: usize,
+    embedding_concurrency: usize,
+    on_request: Option<RequestHook>,
+    max_input_tokens: usize,
+    oversized_input: OversizedInputStrategy,
+    on_oversized_input: Option<OversizedInputCallback>,
 }
 
 #[derive(Debug, Error)]
@@ -20,6 +50,8 @@ pub enum OpenAIEmbeddingsError {
     Client(#[from] OpenAIError),
     #[error("Request to OpenAI embeddings API was successful but response is empty")]
     EmptyResponse,
+    #[error("Unable to tokenize the input for model {0}")]
+    Tokenizer(String),
 }
 
 impl EmbeddingsError for OpenAIEmbeddingsError {}
@@ -28,27 +60,31 @@ impl EmbeddingsError for OpenAIEmbeddingsError {}
 impl traits::Embeddings for Embeddings {
     type Error = OpenAIEmbeddingsError;
 
+    /// Embeds the texts in batches of `batch_size`, sending up to `embedding_concurrency` requests
+    /// at once. The embeddings are returned in the order of `texts`.
+    ///
+    /// Texts longer than the model accepts are handled according to `with_oversized_input`.
     async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
-        self.client
-            .embeddings()
-            .create(CreateEmbeddingRequest {
-                model: self.model.clone(),
-                user: None,
-                input: EmbeddingInput::from(texts),
-            })
-            .await
-            .map(|r| r.data.into_iter().map(|e| e.embedding).collect())
-            .map_err(|e| e.into())
+        if self.oversized_input == OversizedInputStrategy::Error {
+            return self.embed_in_batches(texts).await;
+        }
+        let (pieces, spans) = self.fit_to_limit(texts)?;
+        let vectors = self.embed_in_batches(pieces).await?;
+        Ok(spans
+            .into_iter()
+            .map(|span| average(&vectors[span]))
+            .collect())
     }
 
+    /// Queries longer than the model accepts are handled according to `with_oversized_input`.
     async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
-        self.client
-            .embeddings()
-            .create(CreateEmbeddingRequest {
-                model: self.model.clone(),
-                user: None,
-                input: EmbeddingInput::from(query),
-            })
+        if self.oversized_input != OversizedInputStrategy::Error {
+            return traits::Embeddings::embed_texts(self, vec![query])
+                .await?
+                .pop()
+                .ok_or(OpenAIEmbeddingsError::EmptyResponse);
+        }
+        self.create(EmbeddingInput::from(query))
             .await
             .map(|r| r.data.into_iter())?
             .map(|e| e.embedding)
@@ -62,6 +98,12 @@ impl Default for Embeddings {
         Self {
             client: async_openai::Client::default().into(),
             model: "text-embedding-ada-002".to_string(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            embedding_concurrency: 1,
+            on_request: None,
+            max_input_tokens: DEFAULT_MAX_INPUT_TOKENS,
+            oversized_input: OversizedInputStrategy::default(),
+            on_oversized_input: None,
         }
     }
 }
@@ -71,6 +113,224 @@ impl Embeddings {
         Self {
             client: client.into(),
             model: model.to_string(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            embedding_concurrency: 1,
+            on_request: None,
+            max_input_tokens: DEFAULT_MAX_INPUT_TOKENS,
+            oversized_input: OversizedInputStrategy::default(),
+            on_oversized_input: None,
         }
     }
+
+    /// Sets how many texts are embedded per request. Defaults to 2048, the most the API accepts.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sets how many embedding requests `embed_texts` sends at once. Defaults to 1.
+    ///
+    /// Embedding a large corpus is bound by request latency, so sending several batches at once
+    /// indexes it several times faster. Keep it within your account's rate limits.
+    pub fn with_embedding_concurrency(mut self, embedding_concurrency: usize) -> Self {
+        self.embedding_concurrency = embedding_concurrency;
+        self
+    }
+
+    /// Sets what embedding does with texts over `max_input_tokens`. Defaults to
+    /// [`OversizedInputStrategy::Error`].
+    pub fn with_oversized_input(mut self, strategy: OversizedInputStrategy) -> Self {
+        self.oversized_input = strategy;
+        self
+    }
+
+    /// Sets the most tokens per text before the oversized input strategy applies. Defaults to
+    /// 8191, the limit of OpenAI's embedding models.
+    pub fn with_max_input_tokens(mut self, max_input_tokens: usize) -> Self {
+        self.max_input_tokens = max_input_tokens;
+        self
+    }
+
+    /// Sets a function called with the index and token count of every text that is truncated or
+    /// split, so the affected documents can be logged.
+    pub fn with_on_oversized_input<F>(mut self, on_oversized_input: F) -> Self
+    where
+        F: Fn(usize, usize) + Send + Sync + 'static,
+    {
+        self.on_oversized_input = Some(Box::new(on_oversized_input));
+        self
+    }
+
+    /// Invokes `on_request` after every request to the API with the model, token usage, latency
+    /// and outcome of the request.
+    pub fn with_on_request<F>(mut self, on_request: F) -> Self
+    where
+        F: Fn(&RequestMetrics) + Send + Sync + 'static,
+    {
+        self.on_request = Some(Arc::new(on_request));
+        self
+    }
+
+    async fn embed_in_batches(
+        &self,
+        texts: Vec<String>,
+    ) -> Result<Vec<Vec<f32>>, OpenAIEmbeddingsError> {
+        let batches: Vec<Vec<String>> = texts
+            .chunks(self.batch_size.max(1))
+            .map(|batch| batch.to_vec())
+            .collect();
+        let embeddings: Vec<Vec<Vec<f32>>> = futures::stream::iter(batches)
+            .map(|batch| self.embed_batch(batch))
+            .buffered(self.embedding_concurrency.max(1))
+            .try_collect()
+            .await?;
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+
+    /// Shortens or splits the texts that are over `max_input_tokens`, according to the oversized
+    /// input strategy.
+    ///
+    /// Returns the texts to embed, and for each of the original texts the range of those whose
+    /// vectors make up its embedding.
+    fn fit_to_limit(
+        &self,
+        texts: Vec<String>,
+    ) -> Result<(Vec<String>, Vec<Range<usize>>), OpenAIEmbeddingsError> {
+        let tokenizer_error = || OpenAIEmbeddingsError::Tokenizer(self.model.clone());
+        let bpe = tiktoken_rs::get_bpe_from_model(&self.model).map_err(|_| tokenizer_error())?;
+        let max_tokens = self.max_input_tokens.max(1);
+        let mut pieces = vec![];
+        let mut spans = vec![];
+        for (index, text) in texts.into_iter().enumerate() {
+            let start = pieces.len();
+            let tokens = bpe.encode_ordinary(&text);
+            if tokens.len() <= max_tokens {
+                pieces.push(text);
+            } else {
+                if let Some(on_oversized_input) = &self.on_oversized_input {
+                    on_oversized_input(index, tokens.len());
+                }
+                let mut piece_start = 0;
+                while piece_start < tokens.len() {
+                    let end = piece_end(&bpe, &tokens, piece_start, max_tokens);
+                    pieces.push(
+                        bpe.decode(tokens[piece_start..end].to_vec())
+                            .map_err(|_| tokenizer_error())?,
+                    );
+                    if self.oversized_input == OversizedInputStrategy::Truncate {
+                        break;
+                    }
+                    piece_start = end;
+                }
+            }
+            spans.push(start..pieces.len());
+        }
+        Ok((pieces, spans))
+    }
+
+    async fn embed_batch(
+        &self,
+        texts: Vec<String>,
+    ) -> Result<Vec<Vec<f32>>, OpenAIEmbeddingsError> {
+        self.create(EmbeddingInput::from(texts))
+            .await
+            .map(|r| r.data.into_iter().map(|e| e.embedding).collect())
+            .map_err(|e| e.into())
+    }
+
+    /// Sends an embeddings request, reporting it to the `on_request` hook.
+    async fn create(&self, input: EmbeddingInput) -> Result<CreateEmbeddingResponse, OpenAIError> {
+        let started = Instant::now();
+        let res = self
+            .client
+            .embeddings()
+            .create(CreateEmbeddingRequest {
+                model: self.model.clone(),
+                user: None,
+                input,
+            })
+            .await;
+        if let Some(on_request) = &self.on_request {
+            on_request(&RequestMetrics {
+                model: self.model.clone(),
+                prompt_tokens: res.as_ref().ok().map(|res| res.usage.prompt_tokens),
+                completion_tokens: None,
+                latency: started.elapsed(),
+                success: res.is_ok(),
+            });
+        }
+        res
+    }
+}
+
+/// Returns where the piece of `tokens` starting at `start` ends.
+///
+/// A token can hold part of a multi-byte character, so the piece is cut at the last token boundary
+/// within `max_tokens` that doesn't split a character. If a single character takes more than
+/// `max_tokens` tokens, the piece is extended to the end of that character instead.
+fn piece_end(
+    bpe: &tiktoken_rs::CoreBPE,
+    tokens: &[usize],
+    start: usize,
+    max_tokens: usize,
+) -> usize {
+    let limit = (start + max_tokens).min(tokens.len());
+    let decodes = |end: &usize| bpe.decode(tokens[start..*end].to_vec()).is_ok();
+    (start + 1..=limit)
+        .rev()
+        .find(decodes)
+        .or_else(|| (limit + 1..=tokens.len()).find(decodes))
+        .unwrap_or(tokens.len())
+}
+
+/// Returns the element-wise mean of the vectors.
+fn average(vectors: &[Vec<f32>]) -> Vec<f32> {
+    if let [vector] = vectors {
+        return vector.clone();
+    }
+    let mut sum = vec![0.0; vectors.first().map_or(0, Vec::len)];
+    for vector in vectors {
+        for (total, value) in sum.iter_mut().zip(vector) {
+            *total += value;
+        }
+    }
+    sum.iter()
+        .map(|total| total / vectors.len() as f32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fits_oversized_inputs_to_limit() {
+        let embeddings = Embeddings::default()
+            .with_max_input_tokens(2)
+            .with_oversized_input(OversizedInputStrategy::SplitAndAverage);
+        let (pieces, spans) = embeddings
+            .fit_to_limit(vec!["hi".to_string(), "one two three".to_string()])
+            .unwrap();
+        assert_eq!(pieces, vec!["hi", "one two", " three"]);
+        assert_eq!(spans, vec![0..1, 1..3]);
+        assert_eq!(average(&[vec![1.0, 2.0], vec![3.0, 4.0]]), vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_fits_non_ascii_inputs_to_limit() {
+        let text = "🦀 日本語のテキスト 🦀".to_string();
+        let embeddings = Embeddings::default()
+            .with_max_input_tokens(2)
+            .with_oversized_input(OversizedInputStrategy::SplitAndAverage);
+        let (pieces, spans) = embeddings.fit_to_limit(vec![text.clone()]).unwrap();
+        assert!(pieces.len() > 1);
+        assert_eq!(pieces.concat(), text);
+        assert_eq!(spans, vec![0..pieces.len()]);
+
+        let embeddings = embeddings.with_oversized_input(OversizedInputStrategy::Truncate);
+        let (pieces, _) = embeddings.fit_to_limit(vec![text.clone()]).unwrap();
+        assert_eq!(pieces.len(), 1);
+        assert!(!pieces[0].is_empty());
+        assert!(text.starts_with(&pieces[0]));
+    }
 }
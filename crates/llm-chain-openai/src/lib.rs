@@ -23,3 +23,4 @@
 
 pub mod chatgpt;
 pub mod embeddings;
+pub mod moderation;
@@ -0,0 +1,62 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use async_openai::types::{CreateModerationRequest, ModerationInput};
+use async_trait::async_trait;
+use llm_chain::moderation::{Moderation, Moderator};
+
+/// A [`Moderator`] that uses OpenAI's moderation endpoint.
+///
+/// Use it with `llm_chain::moderation::ModeratedExecutor` to screen completions before they reach
+/// the user.
+pub struct OpenAIModerator {
+    client: Arc<async_openai::Client>,
+}
+
+impl Default for OpenAIModerator {
+    fn default() -> Self {
+        Self {
+            client: async_openai::Client::default().into(),
+        }
+    }
+}
+
+impl OpenAIModerator {
+    pub fn for_client(client: async_openai::Client) -> Self {
+        Self {
+            client: client.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Moderator for OpenAIModerator {
+    async fn moderate(&self, text: &str) -> Result<Moderation, Box<dyn Error + Send + Sync>> {
+        let response = self
+            .client
+            .moderations()
+            .create(CreateModerationRequest {
+                input: ModerationInput::String(text.to_string()),
+                model: None,
+            })
+            .await?;
+        let Some(result) = response.results.into_iter().next() else {
+            return Err(
+                "Request to OpenAI moderation API was successful but response is empty".into(),
+            );
+        };
+        let scores = result.category_scores;
+        Ok(Moderation {
+            flagged: result.flagged,
+            category_scores: vec![
+                ("hate".to_string(), scores.hate),
+                ("hate/threatening".to_string(), scores.hate_threatening),
+                ("self-harm".to_string(), scores.self_harm),
+                ("sexual".to_string(), scores.sexual),
+                ("sexual/minors".to_string(), scores.sexual_minors),
+                ("violence".to_string(), scores.violence),
+                ("violence/graphic".to_string(), scores.violence_graphic),
+            ],
+        })
+    }
+}
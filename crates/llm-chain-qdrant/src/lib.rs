@@ -89,10 +89,9 @@ where
                 .into(),
             )?;
         if let Kind::StringValue(page_content) = page_content {
-            Ok(Document {
-                page_content,
-                metadata,
-            })
+            let mut document = Document::new(page_content);
+            document.metadata = metadata;
+            Ok(document)
         } else {
             Err(ConversionError::InvalidPageContent {
                 point_id: scored_point.id,
@@ -218,6 +217,20 @@ where
         query: String,
         limit: u32,
     ) -> Result<Vec<Document<M>>, Self::Error> {
+        Ok(self
+            .similarity_search_with_score(query, limit)
+            .await?
+            .into_iter()
+            .map(|(doc, _)| doc)
+            .collect())
+    }
+
+    /// The score is the one computed by Qdrant for the collection's distance metric.
+    async fn similarity_search_with_score(
+        &self,
+        query: String,
+        limit: u32,
+    ) -> Result<Vec<(Document<M>, f32)>, Self::Error> {
         let embedded_query = self.embeddings.embed_query(query).await?;
         let res = self
             .client
@@ -246,8 +259,9 @@ where
 
         let mut out = vec![];
         for r in res.result.into_iter() {
+            let score = r.score;
             let val = self.try_document_from_scored_point(r)?;
-            out.push(val);
+            out.push((val, score));
         }
         Ok(out)
     }
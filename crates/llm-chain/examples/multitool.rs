@@ -12,7 +12,7 @@ use thiserror::Error;
 
 /// Your custom tool's implementation:
 #[derive(Debug, Error)]
-#[error("MyTool custom error")]
+#[error("MyTool received invalid YAML: {0}")]
 struct MyToolError(#[from] serde_yaml::Error);
 
 impl ToolError for MyToolError {}
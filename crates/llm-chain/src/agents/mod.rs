@@ -1 +1,2 @@
 pub mod self_ask_with_search;
+pub mod router;
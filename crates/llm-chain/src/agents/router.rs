@@ -0,0 +1,321 @@
+//! An agent that picks the most suitable sub-agent for a query and delegates to it.
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::{
+    options::Options,
+    prompt::Prompt,
+    tools::Tool,
+    traits::{Executor, ExecutorError},
+};
+
+use super::self_ask_with_search;
+
+/// The error type returned by sub-agents.
+pub type SubAgentError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An agent that a [`RouterAgent`] can delegate queries to.
+#[async_trait]
+pub trait SubAgent {
+    /// Answers `query`, returning the final answer.
+    async fn run(&self, query: &str) -> Result<String, SubAgentError>;
+}
+
+#[async_trait]
+impl<E, T> SubAgent for self_ask_with_search::Agent<E, T>
+where
+    E: Executor + Send + Sync,
+    T: Tool + Send + Sync,
+    T::Input: From<String>,
+    T::Output: Into<String>,
+    T::Error: Send + Sync + 'static,
+{
+    async fn run(&self, query: &str) -> Result<String, SubAgentError> {
+        let (finish, _) = self_ask_with_search::Agent::run(self, query).await?;
+        Ok(self.answer(&finish))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RouterAgentError {
+    #[error(transparent)]
+    ExecutorError(#[from] ExecutorError),
+    #[error("Model response was empty or contained no choices")]
+    NoChoicesReturned,
+    #[error("The model did not pick a known route: {0}")]
+    NoRoute(String),
+    #[error("Sub-agent \"{route}\" failed: {source}")]
+    SubAgentFailed {
+        route: String,
+        source: SubAgentError,
+    },
+}
+
+/// The answer produced by a [`RouterAgent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouterOutput {
+    /// The name of the sub-agent that answered the query.
+    pub route: String,
+    /// The sub-agent's answer.
+    pub output: String,
+}
+
+struct Route {
+    name: String,
+    description: String,
+    agent: Box<dyn SubAgent + Send + Sync>,
+}
+
+/// An agent that asks the model which of its sub-agents is best suited to a query, then delegates to it.
+///
+/// Each sub-agent is registered under a name together with a description of what it handles. If the
+/// model's answer doesn't name a known route, the default route is used if one was set.
+pub struct RouterAgent<E: Executor> {
+    executor: E,
+    routes: Vec<Route>,
+    default_route: Option<String>,
+}
+
+impl<E> RouterAgent<E>
+where
+    E: Executor,
+{
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            routes: vec![],
+            default_route: None,
+        }
+    }
+
+    /// Adds a sub-agent under `name`. The description tells the model which queries it should handle.
+    pub fn with_route<A>(mut self, name: &str, description: &str, agent: A) -> Self
+    where
+        A: SubAgent + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            name: name.to_string(),
+            description: description.to_string(),
+            agent: Box::new(agent),
+        });
+        self
+    }
+
+    /// Sets the route used when the model's choice is ambiguous.
+    pub fn with_default_route(mut self, name: &str) -> Self {
+        self.default_route = Some(name.to_string());
+        self
+    }
+
+    fn routing_prompt(&self, query: &str) -> Prompt {
+        let routes = self
+            .routes
+            .iter()
+            .map(|route| format!("- {}: {}", route.name, route.description))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Prompt::text(format!(
+            "Pick the destination best suited to answer the question below.
+
+Destinations:
+{}
+
+Question: {}
+
+Respond with only the name of the destination.
+Destination:",
+            routes, query
+        ))
+    }
+
+    /// Finds the route named in the model's answer, preferring an exact match.
+    fn find_route(&self, answer: &str) -> Option<&Route> {
+        let answer = answer
+            .trim()
+            .trim_matches(|c: char| c == '"' || c == '`' || c == '.');
+        self.routes
+            .iter()
+            .find(|route| route.name.eq_ignore_ascii_case(answer))
+            .or_else(|| {
+                let answer = answer.to_lowercase();
+                let mut matching = self
+                    .routes
+                    .iter()
+                    .filter(|route| answer.contains(&route.name.to_lowercase()));
+                match (matching.next(), matching.next()) {
+                    (Some(route), None) => Some(route),
+                    _ => None,
+                }
+            })
+    }
+
+    /// Asks the model which route should handle `query` and returns its name.
+    pub async fn route(&self, query: &str) -> Result<String, RouterAgentError> {
+        let output = self
+            .executor
+            .execute(Options::empty(), &self.routing_prompt(query))
+            .await?
+            .to_immediate()
+            .await?
+            .as_content()
+            .extract_last_body()
+            .cloned()
+            .ok_or(RouterAgentError::NoChoicesReturned)?;
+        match (self.find_route(&output), &self.default_route) {
+            (Some(route), _) => Ok(route.name.clone()),
+            (None, Some(default_route)) => Ok(default_route.clone()),
+            (None, None) => Err(RouterAgentError::NoRoute(output)),
+        }
+    }
+
+    /// Routes `query` to the most suitable sub-agent and returns its answer.
+    pub async fn run(&self, query: &str) -> Result<RouterOutput, RouterAgentError> {
+        let name = self.route(query).await?;
+        let route = self
+            .routes
+            .iter()
+            .find(|route| route.name == name)
+            .ok_or_else(|| RouterAgentError::NoRoute(name.clone()))?;
+        let output =
+            route
+                .agent
+                .run(query)
+                .await
+                .map_err(|source| RouterAgentError::SubAgentFailed {
+                    route: name.clone(),
+                    source,
+                })?;
+        Ok(RouterOutput {
+            route: name,
+            output,
+        })
+    }
+}
+
+#[async_trait]
+impl<E> SubAgent for RouterAgent<E>
+where
+    E: Executor + Send + Sync,
+{
+    async fn run(&self, query: &str) -> Result<String, SubAgentError> {
+        Ok(RouterAgent::run(self, query).await?.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::{RouterAgent, RouterAgentError, SubAgent, SubAgentError};
+    use crate::{
+        options::Options,
+        output::Output,
+        prompt::Prompt,
+        tokens::{PromptTokensError, TokenCollection, TokenCount, Tokenizer, TokenizerError},
+        traits::{Executor, ExecutorCreationError, ExecutorError},
+    };
+
+    struct MockTokenizer;
+
+    impl Tokenizer for MockTokenizer {
+        fn tokenize_str(&self, _: &str) -> Result<TokenCollection, TokenizerError> {
+            todo!()
+        }
+
+        fn to_string(&self, _: TokenCollection) -> Result<String, TokenizerError> {
+            todo!()
+        }
+    }
+
+    /// An executor that always answers with the same text.
+    struct FixedExecutor(&'static str);
+
+    #[async_trait]
+    impl Executor for FixedExecutor {
+        type StepTokenizer<'a> = MockTokenizer;
+
+        fn new_with_options(_: Options) -> Result<Self, ExecutorCreationError> {
+            todo!()
+        }
+
+        async fn execute(&self, _: &Options, _: &Prompt) -> Result<Output, ExecutorError> {
+            Ok(Output::new_immediate(Prompt::text(self.0.to_string())))
+        }
+
+        fn tokens_used(&self, _: &Options, _: &Prompt) -> Result<TokenCount, PromptTokensError> {
+            todo!()
+        }
+
+        fn max_tokens_allowed(&self, _: &Options) -> i32 {
+            todo!()
+        }
+
+        fn answer_prefix(&self, _: &Prompt) -> Option<String> {
+            None
+        }
+
+        fn get_tokenizer(&self, _: &Options) -> Result<MockTokenizer, TokenizerError> {
+            todo!()
+        }
+    }
+
+    /// A sub-agent that answers with its own name.
+    struct NamedAgent(&'static str);
+
+    #[async_trait]
+    impl SubAgent for NamedAgent {
+        async fn run(&self, _: &str) -> Result<String, SubAgentError> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    fn router(answer: &'static str) -> RouterAgent<FixedExecutor> {
+        RouterAgent::new(FixedExecutor(answer))
+            .with_route(
+                "search",
+                "Questions about current events",
+                NamedAgent("search"),
+            )
+            .with_route(
+                "web search",
+                "Questions about websites",
+                NamedAgent("web search"),
+            )
+            .with_route("math", "Arithmetic", NamedAgent("math"))
+    }
+
+    fn found(router: &RouterAgent<FixedExecutor>, answer: &str) -> Option<String> {
+        router.find_route(answer).map(|route| route.name.clone())
+    }
+
+    #[test]
+    fn test_finds_route() {
+        let router = router("");
+        // "web search" also contains "search", but an exact match wins.
+        assert_eq!(found(&router, "Web Search"), Some("web search".into()));
+        assert_eq!(found(&router, " \"math\". "), Some("math".into()));
+        assert_eq!(
+            found(&router, "The best destination is math."),
+            Some("math".into())
+        );
+        assert_eq!(found(&router, "math or search"), None);
+        assert_eq!(found(&router, "weather"), None);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_default_route() {
+        for answer in ["math or search", "weather"] {
+            let output = router(answer)
+                .with_default_route("search")
+                .run("What happened today?")
+                .await
+                .unwrap();
+            assert_eq!(output.route, "search");
+            assert_eq!(output.output, "search");
+
+            let err = router(answer).route("What happened today?").await;
+            assert!(matches!(err, Err(RouterAgentError::NoRoute(output)) if output == answer));
+        }
+    }
+}
@@ -1,12 +1,22 @@
 use crate::{
-    options::Options,
+    options::{Opt, Options},
+    output::{Output, StreamSegment},
     parameters,
-    prompt::{PromptTemplate, StringTemplateError},
-    tools::{Tool, ToolError},
+    prompt::{Prompt, PromptTemplate, StringTemplateError},
+    retry::RetryBudget,
+    tokens::Tokenizer,
+    tools::{new_call_id, Tool, ToolError},
     traits::{Executor, ExecutorError},
     Parameters,
 };
-use std::time::{Duration, Instant};
+use chrono::{DateTime, FixedOffset, Local};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use thiserror::Error;
 
 /// TODO: This prompt has some issues:
@@ -54,37 +64,265 @@ So the final answer is: No
 Question: {{input}}
 Are followup questions needed here:{{agent_scratchpad}}";
 
-#[derive(Debug, PartialEq, Eq)]
+/// The key the final answer is stored under in [`AgentFinish::return_values`] by default.
+pub const DEFAULT_OUTPUT_KEY: &str = "output";
+
+/// The tool name recorded for a follow up question.
+///
+/// It doesn't name a tool the agent looks up: every follow up is answered by the search tool the
+/// [`Agent`] was created with, whatever its own name.
+pub const INTERMEDIATE_ANSWER_TOOL: &str = "Intermediate Answer";
+
+/// The tool name recorded for a question to the user, see
+/// [`SelfAskWithSearchAgentOutputParser::with_ask_user_prefix`].
+pub const ASK_USER_TOOL: &str = "Ask User";
+
+/// The tool name recorded for a final answer that was rejected by `min_steps_before_finish`.
+const REJECTED_FINISH_TOOL: &str = "Rejected Finish";
+
+/// The feedback given to the model when it tries to finish too early.
+const REJECTED_FINISH_OBSERVATION: &str = "You haven't gathered enough information yet. Ask a follow up question before giving the final answer.";
+
+const ASK_USER_INSTRUCTION: &str =
+    "If you need more information from the user, write \"{prefix} <question>\" instead of a follow up.";
+
+const STEP_REFERENCES_INSTRUCTION: &str = "You can refer to the answer of an earlier follow up as {% raw %}{{result_N}}{% endraw %}, where N is the number of the follow up.";
+
+const CURRENT_TIME_INSTRUCTION: &str = "The current date and time is {date} (UTC{utc_offset}).";
+
+const CITATION_INSTRUCTION: &str = "Cite the intermediate answers your final answer relies on as [N], where N is the number of the follow up, e.g. \"So the final answer is: Joseph Ball [2]\".";
+
+const MISSING_CITATIONS_OBSERVATION: &str = "Your final answer must cite the intermediate answers it relies on as [N], where N is the number of the follow up. Give the final answer again with citations.";
+
+/// The prompts the agent sends to the model, which can be changed to try out variants or to use
+/// another language.
+///
+/// The defaults are written for the default output parser. If the prompt asks for differently worded
+/// follow ups or final answers, use `Agent::with_output_parser` to recognize them.
+#[derive(Debug, Clone)]
+pub struct AgentPrompts {
+    /// The main prompt, usually a few examples of questions answered with follow ups.
+    ///
+    /// This is a template, which must contain the `{{input}}` placeholder for the question and end
+    /// with the `{{agent_scratchpad}}` placeholder for the steps taken so far.
+    pub prompt: String,
+    /// Tells the model how to ask the user a question, see [`Agent::with_ask_user_prefix`].
+    ///
+    /// `{prefix}` is replaced with the ask user prefix.
+    pub ask_user_instruction: String,
+    /// Tells the model how to refer to earlier answers, see `Agent::with_step_references`.
+    ///
+    /// This is part of the prompt template, so the `{{result_N}}` syntax must be wrapped in a
+    /// `{% raw %}` block.
+    pub step_references_instruction: String,
+    /// Tells the model the current time, see `Agent::with_inject_current_time`.
+    ///
+    /// `{date}` is replaced with the date and time, e.g. "Friday, June 2, 2023 14:05", and
+    /// `{utc_offset}` with the offset from UTC, e.g. "+02:00".
+    pub current_time_instruction: String,
+    /// The feedback given to the model when a final answer is rejected, see
+    /// `Agent::with_min_steps_before_finish`.
+    pub rejected_finish_observation: String,
+    /// Tells the model how to cite the steps its final answer relies on, see
+    /// `Agent::with_required_citations`.
+    pub citation_instruction: String,
+    /// The feedback given to the model when its final answer cites no step, see
+    /// `Agent::with_required_citations`.
+    pub missing_citations_observation: String,
+}
+
+impl Default for AgentPrompts {
+    fn default() -> Self {
+        Self {
+            prompt: PROMPT.to_string(),
+            ask_user_instruction: ASK_USER_INSTRUCTION.to_string(),
+            step_references_instruction: STEP_REFERENCES_INSTRUCTION.to_string(),
+            current_time_instruction: CURRENT_TIME_INSTRUCTION.to_string(),
+            rejected_finish_observation: REJECTED_FINISH_OBSERVATION.to_string(),
+            citation_instruction: CITATION_INSTRUCTION.to_string(),
+            missing_citations_observation: MISSING_CITATIONS_OBSERVATION.to_string(),
+        }
+    }
+}
+
+/// Returns the current time, used to tell the model what day it is.
+pub type Clock = Arc<dyn Fn() -> DateTime<FixedOffset> + Send + Sync>;
+
+/// Renders the observation of a tool call for the scratchpad, see
+/// [`Agent::with_observation_formatter`].
+pub type ObservationFormatter =
+    Arc<dyn Fn(&AgentAction, &serde_yaml::Value) -> String + Send + Sync>;
+
+/// How often a step re-prompts the model after an empty response by default.
+const DEFAULT_EMPTY_RESPONSE_RETRIES: u32 = 2;
+
+/// How many tokens the model may generate per step by default. A step is a follow up question or a
+/// final answer, so this leaves plenty of room while stopping runaway generations.
+pub const DEFAULT_MAX_TOKENS_PER_STEP: usize = 1024;
+
+/// The share of the context window the initial prompt may take up when the prompt size precheck is enabled.
+const PROMPT_SIZE_PRECHECK_FRACTION: f64 = 0.8;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AgentAction {
     pub tool: String,
     pub tool_input: serde_yaml::Value,
     pub log: String,
+    /// Identifies the tool call, so its observation can be matched to it. Generated when the
+    /// action is taken if the model didn't provide one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_id: Option<String>,
 }
 #[derive(Debug, PartialEq)]
 pub struct AgentFinish {
     pub return_values: Parameters,
     pub log: String,
+    /// The index of the output parser that understood the final response.
+    /// Set by the agent, parsers should leave it at 0.
+    pub parser_index: usize,
+    /// The steps the final answer cites, numbered from 1.
+    /// Only filled in by agents that require citations, parsers should leave it empty.
+    pub citations: Vec<usize>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct AgentIntermediateStep {
     pub action: AgentAction,
     pub observation: serde_yaml::Value,
+    /// The index of the output parser that understood the model's response.
+    pub parser_index: usize,
+    /// The reasoning the model gave before taking the action, if the output parser extracts it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thought: Option<String>,
 }
 
 pub enum AgentIntermediateStepOutput {
     Step(AgentIntermediateStep),
     Finish(AgentFinish),
+    /// The model asked the user a question. The run is paused until the user answers.
+    AwaitUser {
+        question: String,
+        action: AgentAction,
+        parser_index: usize,
+    },
+    /// The tool asked for information missing from its input, see [`ToolError::need_more_info`].
+    /// The run is paused until the question is answered.
+    ToolNeedsMoreInfo {
+        question: String,
+        /// The action that invoked the tool, with the input it was invoked with.
+        action: AgentAction,
+        parser_index: usize,
+    },
+}
+
+/// A complete record of an agent run, made by [`Agent::run_traced`].
+///
+/// The trace serializes to JSON, e.g. to debug a run, to compare runs in regression tests or to
+/// build evaluation datasets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunTrace {
+    pub query: String,
+    /// Every call to the model, in order, including those whose response couldn't be parsed.
+    pub steps: Vec<StepTrace>,
+    /// The final answer, if the run finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    /// The error the run failed with, if it failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_seconds: f64,
+}
+
+/// One step of a [`RunTrace`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StepTrace {
+    /// The prompt sent to the model.
+    pub prompt: String,
+    /// The model's response, as returned.
+    pub output: String,
+    /// What the response was parsed as, or `None` if no parser understood it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision: Option<TracedDecision>,
+    /// What the agent observed after the step: the tool's output, or why a final answer was
+    /// rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observation: Option<serde_yaml::Value>,
+    /// How long the model took to respond, including retries.
+    pub model_seconds: f64,
+    /// How long the tool took, if it was called.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_seconds: Option<f64>,
+    /// The number of tokens in the prompt, if the executor can count them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<i32>,
+    /// The number of tokens in the response, if the executor can count them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<usize>,
+}
+
+/// The decision recorded in a [`StepTrace`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TracedDecision {
+    Action {
+        tool: String,
+        tool_input: serde_yaml::Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thought: Option<String>,
+    },
+    Finish {
+        answer: String,
+    },
+    AskUser {
+        question: String,
+    },
+}
+
+/// Returns the step being recorded in `trace`, if the run is traced.
+fn traced_step<'t>(trace: &'t mut Option<&mut RunTrace>) -> Option<&'t mut StepTrace> {
+    trace
+        .as_deref_mut()
+        .and_then(|trace| trace.steps.last_mut())
+}
+
+/// An event reported by [`Agent::run_with_callback`] while the agent runs.
+pub enum AgentEvent<'a> {
+    /// The agent took a step.
+    Step(&'a AgentIntermediateStep),
+    /// A piece of the final answer, sent as the model generates it.
+    FinalAnswerToken(String),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum AgentDecision {
     Action(AgentAction),
     Finish(AgentFinish),
+    /// The model wants to ask the user a question before continuing.
+    AskUser(AgentAction),
 }
 pub trait AgentOutputParser {
     type Error;
     fn parse(&self, text: String) -> Result<AgentDecision, Self::Error>;
+
+    /// Extracts the reasoning preceding an action from the action's `log`.
+    ///
+    /// Parsers that don't separate reasoning from actions return `None`.
+    fn extract_thought(&self, _log: &str) -> Option<String> {
+        None
+    }
+
+    /// Finds where the final answer starts in a response that is still being generated.
+    ///
+    /// Returns `None` until the partial response is sure to end in a final answer. Parsers that
+    /// can't tell return `None`, and their final answers aren't streamed.
+    fn final_answer_start(&self, _partial_text: &str) -> Option<usize> {
+        None
+    }
+
+    /// The key the parser stores the final answer under in [`AgentFinish::return_values`].
+    fn output_key(&self) -> &str {
+        DEFAULT_OUTPUT_KEY
+    }
 }
 
 #[derive(Debug, Error)]
@@ -106,17 +344,32 @@ where
     StringTemplateError(#[from] StringTemplateError),
     #[error("Model response was empty or contained no choices")]
     NoChoicesReturned,
+    #[error("Unable to write checkpoint: {0}")]
+    CheckpointError(#[from] std::io::Error),
+    #[error("The agent asked the user a question, use `run_interactive` to answer it: {0}")]
+    UserInputRequired(String),
+    #[error("The initial prompt uses {tokens_used} tokens, which leaves too little of the {max_tokens} token context window")]
+    PromptTooLarge { tokens_used: i32, max_tokens: i32 },
     #[error("Max number of iterations or timeout exceeded. Elapsed: {time_elapsed_seconds}s, {iterations_elapsed} iterations")]
     RuntimeExceeded {
         time_elapsed_seconds: f64,
         iterations_elapsed: u32,
     },
+    #[error("The agent tried to use the search tool after reaching the limit of {0} tool calls")]
+    ToolCallsExceeded(u32),
 }
 
+/// The final answer and steps of a run, or the error it failed with.
+pub type AgentRunResult<T> =
+    Result<(AgentFinish, Vec<AgentIntermediateStep>), SelfAskWithSearchAgentError<T>>;
+
 pub struct SelfAskWithSearchAgentOutputParser {
     followup_prefix: String,
     intermediate_answer_prefix: String,
     acceptable_finish_prefixes: Vec<String>,
+    ask_user_prefix: Option<String>,
+    thought_prefix: Option<String>,
+    output_key: String,
 }
 
 impl SelfAskWithSearchAgentOutputParser {
@@ -132,8 +385,36 @@ impl SelfAskWithSearchAgentOutputParser {
                 .iter()
                 .map(|s| s.to_string())
                 .collect(),
+            ask_user_prefix: None,
+            thought_prefix: None,
+            output_key: DEFAULT_OUTPUT_KEY.into(),
         }
     }
+
+    /// Sets the key the final answer is stored under in [`AgentFinish::return_values`], for
+    /// callers expecting a key other than [`DEFAULT_OUTPUT_KEY`].
+    pub fn with_output_key(mut self, output_key: &str) -> Self {
+        self.output_key = output_key.into();
+        self
+    }
+
+    /// Lets the model ask the user a question by starting a line with `prefix`.
+    ///
+    /// The rest of the line is returned as an [`AgentDecision::AskUser`].
+    pub fn with_ask_user_prefix(mut self, prefix: &str) -> Self {
+        self.ask_user_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Extracts the model's reasoning from the text between `prefix` and the follow up question.
+    ///
+    /// The prompt should ask the model to explain itself on a line starting with `prefix`, e.g.
+    /// "Thought:", before each follow up. The reasoning is stored in
+    /// [`AgentIntermediateStep::thought`].
+    pub fn with_thought_prefix(mut self, prefix: &str) -> Self {
+        self.thought_prefix = Some(prefix.into());
+        self
+    }
 }
 
 impl Default for SelfAskWithSearchAgentOutputParser {
@@ -157,7 +438,21 @@ pub struct ParserError(String);
 impl AgentOutputParser for SelfAskWithSearchAgentOutputParser {
     type Error = ParserError;
     fn parse(&self, text: String) -> Result<AgentDecision, Self::Error> {
-        if let Some(followup_idx) = text.find(&self.followup_prefix) {
+        if let Some(question_idx) = self
+            .ask_user_prefix
+            .as_ref()
+            .and_then(|prefix| text.find(prefix.as_str()).map(|idx| idx + prefix.len()))
+        {
+            let rest = &text[question_idx..];
+            let line_end = question_idx + rest.find('\n').unwrap_or(rest.len());
+            let question = text[question_idx..line_end].trim().to_owned();
+            Ok(AgentDecision::AskUser(AgentAction {
+                tool: ASK_USER_TOOL.into(),
+                tool_input: question.into(),
+                log: text[..line_end].to_owned(),
+                call_id: None,
+            }))
+        } else if let Some(followup_idx) = text.find(&self.followup_prefix) {
             let (followup_question, log) = if let Some(intermediate_answer_idx) =
                 text.find(&self.intermediate_answer_prefix)
             {
@@ -193,9 +488,10 @@ impl AgentOutputParser for SelfAskWithSearchAgentOutputParser {
                 (followup_question, log)
             };
             Ok(AgentDecision::Action(AgentAction {
-                tool: "Intermediate Answer".into(),
+                tool: INTERMEDIATE_ANSWER_TOOL.into(),
                 tool_input: followup_question.into(),
                 log,
+                call_id: None,
             }))
         } else if let Some((idx, prefix)) = self
             .acceptable_finish_prefixes
@@ -204,21 +500,91 @@ impl AgentOutputParser for SelfAskWithSearchAgentOutputParser {
         {
             let final_answer = text.chars().skip(idx + prefix.len()).collect::<String>();
             Ok(AgentDecision::Finish(AgentFinish {
-                return_values: parameters!("output" => final_answer.trim()),
+                return_values: Parameters::new()
+                    .with(self.output_key.as_str(), final_answer.trim()),
                 log: text,
+                parser_index: 0,
+                citations: vec![],
             }))
         } else {
             Err(ParserError(text))
         }
     }
+
+    fn extract_thought(&self, log: &str) -> Option<String> {
+        let prefix = self.thought_prefix.as_ref()?;
+        let start = log.find(prefix.as_str())? + prefix.len();
+        let rest = &log[start..];
+        let end = rest.find(&self.followup_prefix).unwrap_or(rest.len());
+        let thought = rest[..end].trim();
+        (!thought.is_empty()).then(|| thought.to_owned())
+    }
+
+    fn output_key(&self) -> &str {
+        &self.output_key
+    }
+
+    fn final_answer_start(&self, partial_text: &str) -> Option<usize> {
+        // `parse` prefers follow ups and questions to the user over final answers.
+        let start = self
+            .acceptable_finish_prefixes
+            .iter()
+            .find_map(|prefix| partial_text.find(prefix).map(|idx| idx + prefix.len()))?;
+        let mut other_prefixes =
+            std::iter::once(&self.followup_prefix).chain(&self.ask_user_prefix);
+        if other_prefixes.any(|prefix| partial_text.contains(prefix.as_str())) {
+            return None;
+        }
+        Some(start)
+    }
+}
+
+/// The result of an interactive agent run.
+pub enum AgentRunState {
+    /// The agent reached a final answer.
+    Finished(AgentFinish, Vec<AgentIntermediateStep>),
+    /// The agent asked the user a question. Pass the answer to [`Agent::resume`] to continue.
+    AwaitingUser(PausedRun),
+}
+
+/// An agent run that is waiting for the user to answer a question.
+pub struct PausedRun {
+    /// The question the agent asked.
+    pub question: String,
+    query: String,
+    action: AgentAction,
+    parser_index: usize,
+    intermediate_steps: Vec<AgentIntermediateStep>,
+    iterations: u32,
+    tool_calls: u32,
+    elapsed: Duration,
+    /// Whether the question was asked by the tool, which is invoked again once it is answered.
+    tool_call_pending: bool,
+}
+
+impl PausedRun {
+    /// The steps the agent has taken so far.
+    pub fn intermediate_steps(&self) -> &[AgentIntermediateStep] {
+        &self.intermediate_steps
+    }
 }
 
 #[derive(Default)]
 pub struct EarlyStoppingConfig {
     pub max_iterations: Option<u32>,
+    /// The maximum number of times the search tool may be used.
+    ///
+    /// Turns where the agent doesn't use the tool only count towards `max_iterations`.
+    pub max_tool_calls: Option<u32>,
     pub max_time_elapsed_seconds: Option<f64>,
 }
 
+/// An agent that answers a question by asking itself follow up questions and answering them with a
+/// search tool.
+///
+/// The agent uses a single tool, so actions are not routed by name: every follow up question the
+/// model asks is passed to the search tool, and recorded as an action of
+/// [`INTERMEDIATE_ANSWER_TOOL`]. The tool doesn't need to be registered under that name anywhere.
 pub struct Agent<E, T>
 where
     E: Executor,
@@ -231,7 +597,19 @@ where
     early_stopping_config: EarlyStoppingConfig,
     observation_prefix: String,
     llm_prefix: String,
-    output_parser: SelfAskWithSearchAgentOutputParser,
+    output_parsers: Vec<Box<dyn AgentOutputParser<Error = ParserError> + Send + Sync>>,
+    ask_user_prefix: Option<String>,
+    precheck_prompt_size: bool,
+    min_steps_before_finish: Option<u32>,
+    checkpoint_writer: Option<Mutex<Box<dyn Write + Send>>>,
+    inject_current_time: bool,
+    clock: Clock,
+    observation_formatter: Option<ObservationFormatter>,
+    step_references: bool,
+    require_citations: bool,
+    empty_response_retries: u32,
+    max_tokens_per_step: Option<usize>,
+    prompts: AgentPrompts,
 }
 
 impl<E, T> Agent<E, T>
@@ -248,11 +626,244 @@ where
             early_stopping_config,
             observation_prefix: "Intermediate answer: ".to_string(),
             llm_prefix: "".to_string(),
-            output_parser: SelfAskWithSearchAgentOutputParser::default(),
+            output_parsers: vec![Box::new(SelfAskWithSearchAgentOutputParser::default())],
+            ask_user_prefix: None,
+            precheck_prompt_size: false,
+            min_steps_before_finish: None,
+            checkpoint_writer: None,
+            inject_current_time: false,
+            clock: Arc::new(|| Local::now().fixed_offset()),
+            observation_formatter: None,
+            step_references: false,
+            require_citations: false,
+            empty_response_retries: DEFAULT_EMPTY_RESPONSE_RETRIES,
+            max_tokens_per_step: Some(DEFAULT_MAX_TOKENS_PER_STEP),
+            prompts: AgentPrompts::default(),
         }
     }
 
-    fn should_continue(&self, iterations_elapsed: u32, time_elapsed_seconds: f64) -> bool {
+    /// Replaces the prompts sent to the model.
+    pub fn with_prompts(mut self, prompts: AgentPrompts) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    /// Checks the size of the initial prompt before the first model call.
+    ///
+    /// If the prompt already takes up most of the context window, the run fails with
+    /// [`SelfAskWithSearchAgentError::PromptTooLarge`] instead of spending an API call. The check is
+    /// skipped if the executor can't count tokens.
+    pub fn with_prompt_size_precheck(mut self, precheck_prompt_size: bool) -> Self {
+        self.precheck_prompt_size = precheck_prompt_size;
+        self
+    }
+
+    /// Rejects final answers given before the search tool was used at least `min_steps` times.
+    ///
+    /// A rejected answer is fed back to the model with a note that it hasn't gathered enough information
+    /// yet, and the run continues. Rejected answers count towards the early stopping limits.
+    pub fn with_min_steps_before_finish(mut self, min_steps: u32) -> Self {
+        self.min_steps_before_finish = Some(min_steps);
+        self
+    }
+
+    /// Starts the prompt with the current date, time and timezone, so the model can reason about
+    /// relative dates such as "last week".
+    pub fn with_inject_current_time(mut self, inject_current_time: bool) -> Self {
+        self.inject_current_time = inject_current_time;
+        self
+    }
+
+    /// Replaces the clock used by `with_inject_current_time`, which defaults to the local time.
+    pub fn with_clock<F>(mut self, clock: F) -> Self
+    where
+        F: Fn() -> DateTime<FixedOffset> + Send + Sync + 'static,
+    {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Replaces how the observation of each step is written to the scratchpad, e.g. to fence JSON
+    /// results or label them with the tool's name.
+    ///
+    /// The formatted observation still follows the observation prefix. By default string
+    /// observations are written as is.
+    pub fn with_observation_formatter<F>(mut self, formatter: F) -> Self
+    where
+        F: Fn(&AgentAction, &serde_yaml::Value) -> String + Send + Sync + 'static,
+    {
+        self.observation_formatter = Some(Arc::new(formatter));
+        self
+    }
+
+    /// Sets how often a step re-prompts the model when it returns an empty or blank response,
+    /// which is usually a transient failure. Defaults to 2.
+    ///
+    /// These retries don't take from the retry budget, which is only used once they are used up.
+    pub fn with_empty_response_retries(mut self, retries: u32) -> Self {
+        self.empty_response_retries = retries;
+        self
+    }
+
+    /// Caps how many tokens the model may generate in each step, independently of any budget for
+    /// the whole run. Defaults to [`DEFAULT_MAX_TOKENS_PER_STEP`].
+    ///
+    /// The cap is passed to the executor as the `MaxTokens` option of each planning call, overriding
+    /// any `MaxTokens` set on the executor. `None` leaves the limit to the executor's own options.
+    pub fn with_max_tokens_per_step(mut self, max_tokens: Option<usize>) -> Self {
+        self.max_tokens_per_step = max_tokens;
+        self
+    }
+
+    /// Lets follow up questions refer to earlier answers as `{{result_N}}`, where N counts the steps
+    /// from 1.
+    ///
+    /// References are replaced with the answers before the search tool is called, which saves the
+    /// model from copying long values by hand.
+    pub fn with_step_references(mut self, step_references: bool) -> Self {
+        self.step_references = step_references;
+        self
+    }
+
+    /// Asks the model to cite the steps its final answer relies on as `[N]`, where N counts the
+    /// steps from 1.
+    ///
+    /// A final answer that cites no existing step is fed back to the model with a request for
+    /// citations, and the run continues. Answers given before any step was taken have nothing to
+    /// cite and are accepted. The citations are stored in [`AgentFinish::citations`].
+    pub fn with_required_citations(mut self, require_citations: bool) -> Self {
+        self.require_citations = require_citations;
+        self
+    }
+
+    /// Writes each step to `writer` as soon as it is taken, one JSON object per line.
+    ///
+    /// Load the steps with [`load_checkpoint`] and pass them to [`Agent::resume_from`] to continue
+    /// an interrupted run. The writer is shared by all runs of the agent, so use one agent per run
+    /// when checkpointing.
+    pub fn with_checkpoint_writer<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.checkpoint_writer = Some(Mutex::new(Box::new(writer)));
+        self
+    }
+
+    /// Adds a step to the run, writing it to the checkpoint if there is one.
+    fn record_step(
+        &self,
+        intermediate_steps: &mut Vec<AgentIntermediateStep>,
+        step: AgentIntermediateStep,
+    ) -> Result<(), std::io::Error> {
+        if let Some(writer) = &self.checkpoint_writer {
+            let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+            serde_json::to_writer(&mut *writer, &step)?;
+            writer.write_all(b"\n")?;
+            writer.flush()?;
+        }
+        intermediate_steps.push(step);
+        Ok(())
+    }
+
+    /// Records a final answer the agent may not give yet, with `observation` as the feedback.
+    fn reject_finish(
+        &self,
+        intermediate_steps: &mut Vec<AgentIntermediateStep>,
+        finish: AgentFinish,
+        observation: &str,
+    ) -> Result<(), std::io::Error> {
+        self.record_step(
+            intermediate_steps,
+            AgentIntermediateStep {
+                action: AgentAction {
+                    tool: REJECTED_FINISH_TOOL.into(),
+                    tool_input: serde_yaml::Value::Null,
+                    log: finish.log,
+                    call_id: None,
+                },
+                observation: observation.into(),
+                parser_index: finish.parser_index,
+                thought: None,
+            },
+        )
+    }
+
+    /// Whether enough productive steps were taken for the agent to be allowed to finish.
+    fn may_finish(&self, intermediate_steps: &[AgentIntermediateStep]) -> bool {
+        let Some(min_steps) = self.min_steps_before_finish else {
+            return true;
+        };
+        let productive_steps = intermediate_steps
+            .iter()
+            .filter(|step| step.action.tool == INTERMEDIATE_ANSWER_TOOL)
+            .count();
+        productive_steps >= min_steps as usize
+    }
+
+    /// Replaces the parser used to interpret the model's output.
+    pub fn with_output_parser(mut self, output_parser: SelfAskWithSearchAgentOutputParser) -> Self {
+        self.ask_user_prefix = output_parser.ask_user_prefix.clone();
+        self.output_parsers = vec![Box::new(output_parser)];
+        self
+    }
+
+    /// Replaces the parsers used to interpret the model's output.
+    ///
+    /// The parsers are tried in order and the first one that understands the response is used, which
+    /// lets one agent cope with models that format their answers differently. The index of that parser
+    /// is recorded in each step and in the final answer.
+    ///
+    /// The prompt keeps telling the model how to ask the user a question, if it did before. Use
+    /// [`Agent::with_ask_user_prefix`] if the new parsers expect a different prefix.
+    pub fn with_output_parsers(
+        mut self,
+        output_parsers: Vec<Box<dyn AgentOutputParser<Error = ParserError> + Send + Sync>>,
+    ) -> Self {
+        self.output_parsers = output_parsers;
+        self
+    }
+
+    /// Tells the model to ask the user a question by starting a line with `prefix`, or not to ask
+    /// the user anything if `None`.
+    ///
+    /// This only changes the prompt, the output parsers must recognize the prefix too, see
+    /// [`SelfAskWithSearchAgentOutputParser::with_ask_user_prefix`]. [`Agent::with_output_parser`]
+    /// sets it from the parser.
+    pub fn with_ask_user_prefix(mut self, prefix: Option<&str>) -> Self {
+        self.ask_user_prefix = prefix.map(str::to_owned);
+        self
+    }
+
+    /// Returns the final answer in `finish`, stored under the output key of the parser that
+    /// produced it.
+    pub fn answer(&self, finish: &AgentFinish) -> String {
+        let output_key = self
+            .output_parsers
+            .get(finish.parser_index)
+            .map_or(DEFAULT_OUTPUT_KEY, |parser| parser.output_key());
+        finish.return_values.get(output_key).unwrap_or_default()
+    }
+
+    /// Parses the model's response with the first parser that understands it.
+    fn parse_output(&self, output: String) -> Result<(usize, AgentDecision), ParserError> {
+        let mut last_error = ParserError(output.clone());
+        for (idx, parser) in self.output_parsers.iter().enumerate() {
+            match parser.parse(output.clone()) {
+                Ok(decision) => return Ok((idx, decision)),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn should_continue(
+        &self,
+        iterations_elapsed: u32,
+        tool_calls_elapsed: u32,
+        time_elapsed_seconds: f64,
+    ) -> bool {
+        if let Some(max_tool_calls) = self.early_stopping_config.max_tool_calls {
+            if tool_calls_elapsed > max_tool_calls {
+                return false;
+            }
+        }
         match (
             self.early_stopping_config.max_iterations,
             self.early_stopping_config.max_time_elapsed_seconds,
@@ -272,37 +883,126 @@ where
     /// Ask a model for a decision on what to do next, e.x. which tool to use
     ///
     /// Perform the action
+    /// Fails without using the tool if `tool_calls_elapsed` has reached the tool call limit.
+    /// With a `callback`, the final answer is streamed to it and model calls are only retried after
+    /// empty responses.
     async fn take_next_step(
         &self,
         intermediate_steps: &Vec<AgentIntermediateStep>,
         query: &str,
+        tool_calls_elapsed: u32,
+        retry_budget: &RetryBudget,
+        mut callback: Option<&mut (dyn FnMut(AgentEvent<'_>) + Send + '_)>,
+        mut trace: Option<&mut RunTrace>,
     ) -> Result<AgentIntermediateStepOutput, SelfAskWithSearchAgentError<<T as Tool>::Error>> {
-        let output = self.plan(intermediate_steps, query).await?;
+        let prompt = self.build_prompt(intermediate_steps, query)?;
+        let model_start = Instant::now();
+        let mut empty_responses = 0;
+        let output = loop {
+            let result = match callback.as_deref_mut() {
+                Some(callback) => self.plan_streaming(&prompt, callback).await,
+                None => retry_budget.retry(|| self.plan(&prompt)).await,
+            };
+            match result {
+                Err(SelfAskWithSearchAgentError::NoChoicesReturned)
+                    if empty_responses < self.empty_response_retries =>
+                {
+                    empty_responses += 1;
+                }
+                result => break result?,
+            }
+        };
+        if let Some(trace) = trace.as_deref_mut() {
+            let step = self.trace_step(&prompt, &output, model_start.elapsed());
+            trace.steps.push(step);
+        }
 
-        let decision = self.output_parser.parse(output)?;
+        let (parser_index, decision) = self.parse_output(output)?;
+        if let Some(step) = traced_step(&mut trace) {
+            step.decision = Some(match &decision {
+                AgentDecision::Action(action) => TracedDecision::Action {
+                    tool: action.tool.clone(),
+                    tool_input: action.tool_input.clone(),
+                    thought: self.output_parsers[parser_index].extract_thought(&action.log),
+                },
+                AgentDecision::Finish(finish) => TracedDecision::Finish {
+                    answer: finish
+                        .return_values
+                        .get(self.output_parsers[parser_index].output_key())
+                        .unwrap_or_default(),
+                },
+                AgentDecision::AskUser(action) => TracedDecision::AskUser {
+                    question: action.tool_input.as_str().unwrap_or_default().to_owned(),
+                },
+            });
+        }
         match decision {
-            AgentDecision::Action(action) => {
-                let observation = self
-                    .search_tool
-                    .invoke_typed(
-                        &action
-                            .tool_input
-                            .as_str()
-                            .ok_or(SelfAskWithSearchAgentError::ToolInputNotString(
-                                action.tool_input.clone(),
-                            ))?
-                            .to_string()
-                            .into(),
-                    )
+            AgentDecision::Action(mut action) => {
+                if let Some(max_tool_calls) = self.early_stopping_config.max_tool_calls {
+                    if tool_calls_elapsed >= max_tool_calls {
+                        return Err(SelfAskWithSearchAgentError::ToolCallsExceeded(
+                            max_tool_calls,
+                        ));
+                    }
+                }
+                if action.call_id.is_none() {
+                    action.call_id = Some(new_call_id());
+                }
+                let mut tool_input = action
+                    .tool_input
+                    .as_str()
+                    .ok_or(SelfAskWithSearchAgentError::ToolInputNotString(
+                        action.tool_input.clone(),
+                    ))?
+                    .to_string();
+                if self.step_references {
+                    tool_input = resolve_step_references(&tool_input, intermediate_steps);
+                }
+                let typed_input: T::Input = tool_input.clone().into();
+                let tool_start = Instant::now();
+                let observation = match retry_budget
+                    .retry(|| self.search_tool.invoke_typed(&typed_input))
                     .await
-                    .map_err(SelfAskWithSearchAgentError::SearchToolError)?;
+                {
+                    Ok(observation) => observation,
+                    Err(e) => {
+                        let question = e.need_more_info().map(str::to_owned);
+                        return match question {
+                            Some(question) => Ok(AgentIntermediateStepOutput::ToolNeedsMoreInfo {
+                                question,
+                                action: AgentAction {
+                                    tool_input: tool_input.into(),
+                                    ..action
+                                },
+                                parser_index,
+                            }),
+                            None => Err(SelfAskWithSearchAgentError::SearchToolError(e)),
+                        };
+                    }
+                };
+                let observation = serde_yaml::to_value(Into::<String>::into(observation))?;
+                if let Some(step) = traced_step(&mut trace) {
+                    step.observation = Some(observation.clone());
+                    step.tool_seconds = Some(tool_start.elapsed().as_secs_f64());
+                }
 
+                let thought = self.output_parsers[parser_index].extract_thought(&action.log);
                 Ok(AgentIntermediateStepOutput::Step(AgentIntermediateStep {
                     action,
-                    observation: serde_yaml::to_value(Into::<String>::into(observation))?,
+                    observation,
+                    parser_index,
+                    thought,
                 }))
             }
-            AgentDecision::Finish(finish) => Ok(AgentIntermediateStepOutput::Finish(finish)),
+            AgentDecision::Finish(finish) => Ok(AgentIntermediateStepOutput::Finish(AgentFinish {
+                parser_index,
+                ..finish
+            })),
+            AgentDecision::AskUser(action) => Ok(AgentIntermediateStepOutput::AwaitUser {
+                question: action.tool_input.as_str().unwrap_or_default().to_owned(),
+                action,
+                parser_index,
+            }),
         }
     }
 
@@ -313,31 +1013,60 @@ where
     ) -> String {
         let mut scratchpad = "".to_string();
         for intermediate_step in intermediate_steps {
+            let observation = match &self.observation_formatter {
+                Some(formatter) => {
+                    formatter(&intermediate_step.action, &intermediate_step.observation)
+                }
+                None => intermediate_step
+                    .observation
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            };
             scratchpad += &intermediate_step.action.log;
             scratchpad += &format!(
                 "\n{}{}\n{}",
-                self.observation_prefix,
-                intermediate_step.observation.as_str().unwrap_or_default(),
-                self.llm_prefix
+                self.observation_prefix, observation, self.llm_prefix
             );
         }
         scratchpad
     }
 
+    /// Records a call to the model with `prompt` that returned `output`, counting the tokens of the
+    /// prompt and the response if the executor can.
+    fn trace_step(&self, prompt: &Prompt, output: &str, model_duration: Duration) -> StepTrace {
+        let options = self.step_options(false);
+        let prompt_tokens = self
+            .executor
+            .tokens_used(&options, prompt)
+            .ok()
+            .map(|count| self.executor.max_tokens_allowed(&options) - count.tokens_remaining());
+        let completion_tokens = self
+            .executor
+            .get_tokenizer(&options)
+            .and_then(|tokenizer| tokenizer.tokenize_str(output))
+            .ok()
+            .map(|tokens| tokens.len());
+        StepTrace {
+            prompt: prompt.to_string(),
+            output: output.to_owned(),
+            model_seconds: model_duration.as_secs_f64(),
+            prompt_tokens,
+            completion_tokens,
+            ..Default::default()
+        }
+    }
+
     /// Ask a model for a decision on what to do next, e.x. which tool to use
     ///
-    /// Fills in the prompt template then calls the model to complete it
+    /// Calls the model to complete `prompt`, the filled in prompt template
     async fn plan(
         &self,
-        intermediate_steps: &Vec<AgentIntermediateStep>,
-        query: &str,
+        prompt: &Prompt,
     ) -> Result<String, SelfAskWithSearchAgentError<<T as Tool>::Error>> {
-        let scratchpad = self.build_agent_scratchpad(intermediate_steps);
-        let template_parameters = parameters!("input" => query, "agent_scratchpad" => scratchpad);
-        let prompt = PromptTemplate::Text(PROMPT.into()).format(&template_parameters)?;
         let plan = self
             .executor
-            .execute(Options::empty(), &prompt)
+            .execute(&self.step_options(false), prompt)
             .await
             .map_err(SelfAskWithSearchAgentError::ExecutorError)?;
         plan.to_immediate()
@@ -345,10 +1074,133 @@ where
             .map_err(SelfAskWithSearchAgentError::ExecutorError)?
             .as_content()
             .extract_last_body()
+            .filter(|body| !body.trim().is_empty())
             .cloned()
             .ok_or(SelfAskWithSearchAgentError::NoChoicesReturned)
     }
 
+    /// Returns the options of a planning call.
+    fn step_options(&self, stream: bool) -> Options {
+        let mut options = Options::builder();
+        if let Some(max_tokens) = self.max_tokens_per_step {
+            options.add_option(Opt::MaxTokens(max_tokens));
+        }
+        if stream {
+            options.add_option(Opt::Stream(true));
+        }
+        options.build()
+    }
+
+    /// Like `plan`, but asks the executor to stream and sends the final answer to `callback` as it
+    /// is generated.
+    ///
+    /// Nothing is sent if the executor doesn't stream.
+    async fn plan_streaming(
+        &self,
+        prompt: &Prompt,
+        callback: &mut (dyn FnMut(AgentEvent<'_>) + Send),
+    ) -> Result<String, SelfAskWithSearchAgentError<<T as Tool>::Error>> {
+        let plan = self
+            .executor
+            .execute(&self.step_options(true), prompt)
+            .await
+            .map_err(SelfAskWithSearchAgentError::ExecutorError)?;
+        let mut stream = match plan {
+            Output::Stream(stream) => stream,
+            Output::Immediate(immediate) => {
+                return immediate
+                    .as_content()
+                    .extract_last_body()
+                    .filter(|body| !body.trim().is_empty())
+                    .cloned()
+                    .ok_or(SelfAskWithSearchAgentError::NoChoicesReturned)
+            }
+        };
+        let mut text = String::new();
+        let mut answer_start = None;
+        let mut forwarded = None;
+        while let Some(segment) = stream.next().await {
+            match segment {
+                StreamSegment::Content(content) => text.push_str(&content),
+                StreamSegment::Role(_) => continue,
+                StreamSegment::Err(e) => return Err(SelfAskWithSearchAgentError::ExecutorError(e)),
+            }
+            if answer_start.is_none() {
+                answer_start = self
+                    .output_parsers
+                    .iter()
+                    .find_map(|parser| parser.final_answer_start(&text));
+            }
+            let Some(start) = answer_start else {
+                continue;
+            };
+            // Whitespace between the prefix and the answer is not part of the answer.
+            let from = forwarded.unwrap_or_else(|| text.len() - text[start..].trim_start().len());
+            if from < text.len() {
+                callback(AgentEvent::FinalAnswerToken(text[from..].to_owned()));
+                forwarded = Some(text.len());
+            }
+        }
+        if text.trim().is_empty() {
+            return Err(SelfAskWithSearchAgentError::NoChoicesReturned);
+        }
+        Ok(text)
+    }
+
+    /// Fills in the prompt template with the query and the steps taken so far
+    fn build_prompt(
+        &self,
+        intermediate_steps: &Vec<AgentIntermediateStep>,
+        query: &str,
+    ) -> Result<Prompt, SelfAskWithSearchAgentError<<T as Tool>::Error>> {
+        let scratchpad = self.build_agent_scratchpad(intermediate_steps);
+        let template_parameters = parameters!("input" => query, "agent_scratchpad" => scratchpad);
+        let prompts = &self.prompts;
+        let mut template = match &self.ask_user_prefix {
+            Some(prefix) => format!(
+                "{}\n\n{}",
+                prompts.ask_user_instruction.replace("{prefix}", prefix),
+                prompts.prompt
+            ),
+            None => prompts.prompt.clone(),
+        };
+        if self.step_references {
+            template = format!("{}\n\n{}", prompts.step_references_instruction, template);
+        }
+        if self.require_citations {
+            template = format!("{}\n\n{}", prompts.citation_instruction, template);
+        }
+        if self.inject_current_time {
+            let now = (self.clock)();
+            let instruction = prompts
+                .current_time_instruction
+                .replace("{date}", &now.format("%A, %B %-d, %Y %H:%M").to_string())
+                .replace("{utc_offset}", &now.format("%:z").to_string());
+            template = format!("{}\n\n{}", instruction, template);
+        }
+        Ok(PromptTemplate::Text(template.as_str().into()).format(&template_parameters)?)
+    }
+
+    /// Fails if the initial prompt takes up too much of the context window
+    fn check_prompt_size(
+        &self,
+        query: &str,
+    ) -> Result<(), SelfAskWithSearchAgentError<<T as Tool>::Error>> {
+        let prompt = self.build_prompt(&vec![], query)?;
+        let Ok(token_count) = self.executor.tokens_used(Options::empty(), &prompt) else {
+            return Ok(());
+        };
+        let max_tokens = self.executor.max_tokens_allowed(Options::empty());
+        let tokens_used = max_tokens - token_count.tokens_remaining();
+        if tokens_used as f64 > max_tokens as f64 * PROMPT_SIZE_PRECHECK_FRACTION {
+            return Err(SelfAskWithSearchAgentError::PromptTooLarge {
+                tokens_used,
+                max_tokens,
+            });
+        }
+        Ok(())
+    }
+
     pub async fn run(
         &self,
         query: &str,
@@ -356,21 +1208,453 @@ where
         (AgentFinish, Vec<AgentIntermediateStep>),
         SelfAskWithSearchAgentError<<T as Tool>::Error>,
     > {
+        self.run_with_retry_budget(query, &RetryBudget::new(0))
+            .await
+    }
+
+    /// Runs the agent, retrying failed model and tool calls while `retry_budget` allows.
+    ///
+    /// The budget is shared by every call in the run, and may be shared between runs, so a single
+    /// misbehaving run can't retry without bound.
+    pub async fn run_with_retry_budget(
+        &self,
+        query: &str,
+        retry_budget: &RetryBudget,
+    ) -> Result<
+        (AgentFinish, Vec<AgentIntermediateStep>),
+        SelfAskWithSearchAgentError<<T as Tool>::Error>,
+    > {
+        match self.start(query, retry_budget).await? {
+            AgentRunState::Finished(finish, intermediate_steps) => Ok((finish, intermediate_steps)),
+            AgentRunState::AwaitingUser(paused) => Err(
+                SelfAskWithSearchAgentError::UserInputRequired(paused.question),
+            ),
+        }
+    }
+
+    /// Like `run`, but also returns the steps taken when the run fails, e.g. with
+    /// `RuntimeExceeded`, to help find out why it didn't finish.
+    ///
+    /// The steps are returned whether the run succeeds or not.
+    pub async fn run_collect(
+        &self,
+        query: &str,
+    ) -> (
+        Result<AgentFinish, SelfAskWithSearchAgentError<<T as Tool>::Error>>,
+        Vec<AgentIntermediateStep>,
+    ) {
+        if self.precheck_prompt_size {
+            if let Err(e) = self.check_prompt_size(query) {
+                return (Err(e), vec![]);
+            }
+        }
         let mut intermediate_steps = vec![];
+        let result = self
+            .run_from(
+                query.to_owned(),
+                &mut intermediate_steps,
+                0,
+                0,
+                Duration::from_nanos(0),
+                &RetryBudget::new(0),
+                None,
+                None,
+            )
+            .await;
+        match result {
+            Ok(AgentRunState::Finished(finish, steps)) => (Ok(finish), steps),
+            Ok(AgentRunState::AwaitingUser(paused)) => (
+                Err(SelfAskWithSearchAgentError::UserInputRequired(
+                    paused.question,
+                )),
+                paused.intermediate_steps,
+            ),
+            Err(e) => (Err(e), intermediate_steps),
+        }
+    }
+
+    /// Like `run`, but also returns a [`RunTrace`] of everything that happened in the run: each
+    /// prompt, the model's raw response and what it was parsed as, the tool's input and output,
+    /// timings and token counts.
+    ///
+    /// The trace is returned whether the run succeeds or not. Counting tokens tokenizes every prompt
+    /// and response, so this is slower than `run`.
+    pub async fn run_traced(
+        &self,
+        query: &str,
+    ) -> (
+        Result<AgentFinish, SelfAskWithSearchAgentError<<T as Tool>::Error>>,
+        RunTrace,
+    ) {
+        let start = Instant::now();
+        let mut trace = RunTrace {
+            query: query.to_owned(),
+            ..Default::default()
+        };
+        let result = self.run_recording(query, &mut trace).await;
+        match &result {
+            Ok(finish) => trace.answer = Some(self.answer(finish)),
+            Err(e) => trace.error = Some(e.to_string()),
+        }
+        trace.duration_seconds = start.elapsed().as_secs_f64();
+        (result, trace)
+    }
+
+    async fn run_recording(
+        &self,
+        query: &str,
+        trace: &mut RunTrace,
+    ) -> Result<AgentFinish, SelfAskWithSearchAgentError<<T as Tool>::Error>> {
+        if self.precheck_prompt_size {
+            self.check_prompt_size(query)?;
+        }
+        let state = self
+            .run_from(
+                query.to_owned(),
+                &mut vec![],
+                0,
+                0,
+                Duration::from_nanos(0),
+                &RetryBudget::new(0),
+                None,
+                Some(trace),
+            )
+            .await?;
+        match state {
+            AgentRunState::Finished(finish, _) => Ok(finish),
+            AgentRunState::AwaitingUser(paused) => Err(
+                SelfAskWithSearchAgentError::UserInputRequired(paused.question),
+            ),
+        }
+    }
+
+    /// Runs the agent over many queries, with at most `concurrency` runs in flight at once.
+    ///
+    /// Results are returned in the same order as `queries`. A failing query doesn't stop the batch,
+    /// its error is returned in its place.
+    pub async fn run_batch(
+        &self,
+        queries: Vec<String>,
+        concurrency: usize,
+    ) -> Vec<
+        Result<
+            (AgentFinish, Vec<AgentIntermediateStep>),
+            SelfAskWithSearchAgentError<<T as Tool>::Error>,
+        >,
+    > {
+        futures::stream::iter(queries)
+            .map(|query| async move { self.run(&query).await })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Like `run_batch`, but takes the queries as a stream and yields each query with its result
+    /// as soon as the run finishes.
+    ///
+    /// At most `concurrency` runs are in flight, and queries are only pulled from `queries` when a
+    /// run finishes, so a slow consumer holds back new runs instead of results piling up. Results
+    /// come in the order the runs finish, not the order of `queries`.
+    pub fn run_batch_stream<'a, S>(
+        &'a self,
+        queries: S,
+        concurrency: usize,
+    ) -> impl Stream<Item = (String, AgentRunResult<<T as Tool>::Error>)> + 'a
+    where
+        S: Stream<Item = String> + 'a,
+    {
+        queries
+            .map(move |query| async move {
+                let result = self.run(&query).await;
+                (query, result)
+            })
+            .buffer_unordered(concurrency.max(1))
+    }
+
+    /// Runs the agent, reporting each step to `callback` as it is taken and streaming the final
+    /// answer to it as [`AgentEvent::FinalAnswerToken`]s.
+    ///
+    /// Only the final answer is streamed, the model's responses for intermediate steps are not. The
+    /// executor must support the `Stream` option and the output parser must implement
+    /// [`AgentOutputParser::final_answer_start`] for tokens to be sent. A final answer rejected by
+    /// `with_min_steps_before_finish` may be streamed before it is rejected, in which case a step
+    /// for the rejection follows it.
+    pub async fn run_with_callback<F>(
+        &self,
+        query: &str,
+        mut callback: F,
+    ) -> Result<
+        (AgentFinish, Vec<AgentIntermediateStep>),
+        SelfAskWithSearchAgentError<<T as Tool>::Error>,
+    >
+    where
+        F: FnMut(AgentEvent<'_>) + Send,
+    {
+        if self.precheck_prompt_size {
+            self.check_prompt_size(query)?;
+        }
+        let state = self
+            .run_from(
+                query.to_owned(),
+                &mut vec![],
+                0,
+                0,
+                Duration::from_nanos(0),
+                &RetryBudget::new(0),
+                Some(&mut callback),
+                None,
+            )
+            .await?;
+        match state {
+            AgentRunState::Finished(finish, intermediate_steps) => Ok((finish, intermediate_steps)),
+            AgentRunState::AwaitingUser(paused) => Err(
+                SelfAskWithSearchAgentError::UserInputRequired(paused.question),
+            ),
+        }
+    }
+
+    /// Runs the agent, pausing if the model asks the user a question or the tool needs information
+    /// missing from its input.
+    ///
+    /// The time spent waiting for the user does not count towards the early stopping limits.
+    pub async fn run_interactive(
+        &self,
+        query: &str,
+    ) -> Result<AgentRunState, SelfAskWithSearchAgentError<<T as Tool>::Error>> {
+        self.start(query, &RetryBudget::new(0)).await
+    }
+
+    async fn start(
+        &self,
+        query: &str,
+        retry_budget: &RetryBudget,
+    ) -> Result<AgentRunState, SelfAskWithSearchAgentError<<T as Tool>::Error>> {
+        if self.precheck_prompt_size {
+            self.check_prompt_size(query)?;
+        }
+        self.run_from(
+            query.to_owned(),
+            &mut vec![],
+            0,
+            0,
+            Duration::from_nanos(0),
+            retry_budget,
+            None,
+            None,
+        )
+        .await
+    }
+
+    /// Continues a paused run, using `answer` as the answer to the agent's question.
+    ///
+    /// If the question was asked by the tool rather than the model, the tool is invoked again with
+    /// the question and answer added to its input, and may ask another question. Each invocation
+    /// counts as a tool call towards the early stopping limits.
+    pub async fn resume(
+        &self,
+        paused: PausedRun,
+        answer: &str,
+    ) -> Result<AgentRunState, SelfAskWithSearchAgentError<<T as Tool>::Error>> {
+        let mut intermediate_steps = paused.intermediate_steps;
+        let mut tool_calls = paused.tool_calls;
+        let step = if paused.tool_call_pending {
+            let tool_input = input_with_answer(
+                paused.action.tool_input.as_str().unwrap_or_default(),
+                &paused.question,
+                answer,
+            );
+            let action = AgentAction {
+                tool_input: tool_input.as_str().into(),
+                ..paused.action
+            };
+            tool_calls += 1;
+            let observation = match self.search_tool.invoke_typed(&tool_input.into()).await {
+                Ok(observation) => serde_yaml::to_value(Into::<String>::into(observation))?,
+                Err(e) => {
+                    let question = e.need_more_info().map(str::to_owned);
+                    return match question {
+                        Some(question) => Ok(AgentRunState::AwaitingUser(PausedRun {
+                            question,
+                            action,
+                            intermediate_steps,
+                            tool_calls,
+                            ..paused
+                        })),
+                        None => Err(SelfAskWithSearchAgentError::SearchToolError(e)),
+                    };
+                }
+            };
+            let thought = self.output_parsers[paused.parser_index].extract_thought(&action.log);
+            AgentIntermediateStep {
+                action,
+                observation,
+                parser_index: paused.parser_index,
+                thought,
+            }
+        } else {
+            AgentIntermediateStep {
+                action: paused.action,
+                observation: answer.into(),
+                parser_index: paused.parser_index,
+                thought: None,
+            }
+        };
+        self.record_step(&mut intermediate_steps, step)?;
+        self.run_from(
+            paused.query,
+            &mut intermediate_steps,
+            paused.iterations,
+            tool_calls,
+            paused.elapsed,
+            &RetryBudget::new(0),
+            None,
+            None,
+        )
+        .await
+    }
+
+    /// Continues a run of `query` from steps loaded with [`load_checkpoint`].
+    ///
+    /// Each checkpointed step counts as one iteration towards the early stopping limits, and each
+    /// use of the search tool as one tool call.
+    pub async fn resume_from(
+        &self,
+        mut checkpoint: Vec<AgentIntermediateStep>,
+        query: &str,
+    ) -> Result<AgentRunState, SelfAskWithSearchAgentError<<T as Tool>::Error>> {
+        let iterations = checkpoint.len() as u32;
+        let tool_calls = checkpoint
+            .iter()
+            .filter(|step| {
+                !matches!(
+                    step.action.tool.as_str(),
+                    REJECTED_FINISH_TOOL | ASK_USER_TOOL
+                )
+            })
+            .count() as u32;
+        self.run_from(
+            query.to_owned(),
+            &mut checkpoint,
+            iterations,
+            tool_calls,
+            Duration::from_nanos(0),
+            &RetryBudget::new(0),
+            None,
+            None,
+        )
+        .await
+    }
 
-        let mut iterations = 0;
+    #[allow(clippy::too_many_arguments)]
+    async fn run_from(
+        &self,
+        query: String,
+        intermediate_steps: &mut Vec<AgentIntermediateStep>,
+        mut iterations: u32,
+        mut tool_calls: u32,
+        elapsed: Duration,
+        retry_budget: &RetryBudget,
+        mut callback: Option<&mut (dyn FnMut(AgentEvent<'_>) + Send)>,
+        mut trace: Option<&mut RunTrace>,
+    ) -> Result<AgentRunState, SelfAskWithSearchAgentError<<T as Tool>::Error>> {
         let start = Instant::now();
-        let mut full_duration = Duration::from_nanos(0);
-        while self.should_continue(iterations, full_duration.as_secs_f64()) {
-            let decision = self.take_next_step(&intermediate_steps, query).await?;
-            full_duration = start.elapsed();
+        let mut full_duration = elapsed;
+        while self.should_continue(iterations, tool_calls, full_duration.as_secs_f64()) {
+            let decision = self
+                .take_next_step(
+                    intermediate_steps,
+                    &query,
+                    tool_calls,
+                    retry_budget,
+                    callback.as_deref_mut(),
+                    trace.as_deref_mut(),
+                )
+                .await?;
+            full_duration = elapsed + start.elapsed();
             iterations += 1;
             match decision {
-                AgentIntermediateStepOutput::Step(step) => intermediate_steps.push(step),
-                AgentIntermediateStepOutput::Finish(finish) => {
-                    return Ok((finish, intermediate_steps))
+                AgentIntermediateStepOutput::Step(step) => {
+                    tool_calls += 1;
+                    self.record_step(intermediate_steps, step)?
+                }
+                AgentIntermediateStepOutput::Finish(finish)
+                    if !self.may_finish(intermediate_steps) =>
+                {
+                    self.reject_finish(
+                        intermediate_steps,
+                        finish,
+                        &self.prompts.rejected_finish_observation,
+                    )?;
+                    if let Some(step) = traced_step(&mut trace) {
+                        step.observation =
+                            Some(self.prompts.rejected_finish_observation.as_str().into());
+                    }
+                }
+                AgentIntermediateStepOutput::Finish(mut finish) => {
+                    if self.require_citations {
+                        let answer = self.answer(&finish);
+                        finish.citations = parse_citations(&answer, intermediate_steps.len());
+                    }
+                    if self.require_citations
+                        && finish.citations.is_empty()
+                        && !intermediate_steps.is_empty()
+                    {
+                        self.reject_finish(
+                            intermediate_steps,
+                            finish,
+                            &self.prompts.missing_citations_observation,
+                        )?;
+                        if let Some(step) = traced_step(&mut trace) {
+                            step.observation =
+                                Some(self.prompts.missing_citations_observation.as_str().into());
+                        }
+                    } else {
+                        return Ok(AgentRunState::Finished(
+                            finish,
+                            std::mem::take(intermediate_steps),
+                        ));
+                    }
+                }
+                AgentIntermediateStepOutput::AwaitUser {
+                    question,
+                    action,
+                    parser_index,
+                } => {
+                    return Ok(AgentRunState::AwaitingUser(PausedRun {
+                        question,
+                        query,
+                        action,
+                        parser_index,
+                        intermediate_steps: std::mem::take(intermediate_steps),
+                        iterations,
+                        tool_calls,
+                        elapsed: full_duration,
+                        tool_call_pending: false,
+                    }))
+                }
+                AgentIntermediateStepOutput::ToolNeedsMoreInfo {
+                    question,
+                    action,
+                    parser_index,
+                } => {
+                    return Ok(AgentRunState::AwaitingUser(PausedRun {
+                        question,
+                        query,
+                        action,
+                        parser_index,
+                        intermediate_steps: std::mem::take(intermediate_steps),
+                        iterations,
+                        tool_calls: tool_calls + 1,
+                        elapsed: full_duration,
+                        tool_call_pending: true,
+                    }))
                 }
             }
+            if let (Some(callback), Some(step)) =
+                (callback.as_deref_mut(), intermediate_steps.last())
+            {
+                callback(AgentEvent::Step(step));
+            }
         }
         Err(SelfAskWithSearchAgentError::RuntimeExceeded {
             time_elapsed_seconds: full_duration.as_secs_f64(),
@@ -379,29 +1663,398 @@ where
     }
 }
 
+/// Replaces each `{{result_N}}` in `input` with the observation of the N-th step.
+///
+/// References to steps that don't exist are left untouched.
+fn resolve_step_references(input: &str, intermediate_steps: &[AgentIntermediateStep]) -> String {
+    let mut resolved = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find("{{") {
+        resolved.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        let observation = after_open.find("}}").and_then(|end| {
+            let idx: usize = after_open[..end]
+                .trim()
+                .strip_prefix("result_")?
+                .parse()
+                .ok()?;
+            let step = intermediate_steps.get(idx.checked_sub(1)?)?;
+            Some((end, step.observation.as_str().unwrap_or_default()))
+        });
+        match observation {
+            Some((end, observation)) => {
+                resolved.push_str(observation);
+                rest = &after_open[end + 2..];
+            }
+            None => {
+                resolved.push_str("{{");
+                rest = after_open;
+            }
+        }
+    }
+    resolved.push_str(rest);
+    resolved
+}
+
+/// Adds the answer to a question the tool asked to the input it was invoked with.
+fn input_with_answer(input: &str, question: &str, answer: &str) -> String {
+    format!("{}\n\nQuestion: {}\nAnswer: {}", input, question, answer)
+}
+
+/// Returns the step numbers cited in `answer` as `[N]` or `[N, M]`, in order of first citation.
+///
+/// Numbers that don't refer to one of the `step_count` steps are ignored.
+fn parse_citations(answer: &str, step_count: usize) -> Vec<usize> {
+    let mut citations = vec![];
+    let mut rest = answer;
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+        let numbers: Result<Vec<usize>, _> = rest[..end]
+            .split(',')
+            .map(|number| number.trim().parse())
+            .collect();
+        let Ok(numbers) = numbers else {
+            continue;
+        };
+        for number in numbers {
+            if (1..=step_count).contains(&number) && !citations.contains(&number) {
+                citations.push(number);
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    citations
+}
+
+/// Reads the steps written by an agent with a checkpoint writer.
+///
+/// A partially written last line, as left behind by a crash, is ignored.
+pub fn load_checkpoint<R: BufRead>(
+    reader: R,
+) -> Result<Vec<AgentIntermediateStep>, serde_json::Error> {
+    let lines = reader
+        .lines()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(serde_json::Error::io)?;
+    let lines: Vec<&String> = lines.iter().filter(|l| !l.trim().is_empty()).collect();
+    let mut steps = vec![];
+    for (idx, line) in lines.iter().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(step) => steps.push(step),
+            Err(e) if e.is_eof() && idx == lines.len() - 1 => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(steps)
+}
+
 #[cfg(test)]
 mod tests {
 
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
     use async_trait::async_trait;
 
     use thiserror::Error;
 
     use crate::{
         agents::self_ask_with_search::{AgentIntermediateStep, EarlyStoppingConfig},
-        options::Options,
+        options::{Opt, OptDiscriminants, Options},
         output::Output,
         parameters,
         prompt::Prompt,
-        tokens::{TokenCollection, Tokenizer},
-        tools::{Tool, ToolError},
+        tokens::{TokenCollection, Tokenizer, TokenizerError},
+        tools::{Format, Tool, ToolDescription, ToolError},
         traits::{Executor, ExecutorError},
     };
 
     use super::{
-        Agent, AgentAction, AgentDecision, AgentFinish, AgentOutputParser,
-        SelfAskWithSearchAgentOutputParser,
+        input_with_answer, load_checkpoint, parse_citations, resolve_step_references, Agent,
+        AgentAction, AgentDecision, AgentFinish, AgentOutputParser, RunTrace,
+        SelfAskWithSearchAgentError, SelfAskWithSearchAgentOutputParser, StepTrace, TracedDecision,
+        DEFAULT_MAX_TOKENS_PER_STEP,
     };
 
+    /// Uses one token per character.
+    struct CharTokenizer;
+
+    impl Tokenizer for CharTokenizer {
+        fn tokenize_str(&self, doc: &str) -> Result<TokenCollection, TokenizerError> {
+            Ok(doc.chars().map(|c| c as usize).collect::<Vec<_>>().into())
+        }
+
+        fn to_string(&self, tokens: TokenCollection) -> Result<String, TokenizerError> {
+            tokens
+                .as_usize()?
+                .into_iter()
+                .map(|t| char::from_u32(t as u32).ok_or(TokenizerError::ToStringError))
+                .collect()
+        }
+    }
+
+    /// An executor that responds to a prompt with `respond(question, scratchpad)`, where the
+    /// question and scratchpad are taken from the end of the prompt. It fails when `respond`
+    /// returns `None`.
+    struct ScriptedExecutor {
+        respond: fn(&str, &str) -> Option<String>,
+    }
+
+    #[async_trait]
+    impl Executor for ScriptedExecutor {
+        type StepTokenizer<'a> = CharTokenizer;
+
+        fn new_with_options(_: Options) -> Result<Self, crate::traits::ExecutorCreationError> {
+            todo!()
+        }
+
+        async fn execute(&self, _: &Options, prompt: &Prompt) -> Result<Output, ExecutorError> {
+            let prompt = prompt.to_text();
+            let (question, scratchpad) = prompt
+                .rsplit("Question: ")
+                .next()
+                .unwrap()
+                .split_once('\n')
+                .unwrap();
+            let response = (self.respond)(question, scratchpad)
+                .ok_or_else(|| ExecutorError::InnerError("Mocked executor error".into()))?;
+            Ok(Output::new_immediate(Prompt::text(response)))
+        }
+
+        fn tokens_used(
+            &self,
+            _: &Options,
+            prompt: &Prompt,
+        ) -> Result<crate::tokens::TokenCount, crate::tokens::PromptTokensError> {
+            let tokens = prompt.to_text().chars().count() as i32;
+            Ok(crate::tokens::TokenCount::new(4096, tokens))
+        }
+
+        fn answer_prefix(&self, _prompt: &Prompt) -> Option<String> {
+            None
+        }
+
+        fn max_tokens_allowed(&self, _: &Options) -> i32 {
+            4096
+        }
+
+        fn get_tokenizer(&self, _: &Options) -> Result<CharTokenizer, TokenizerError> {
+            Ok(CharTokenizer)
+        }
+    }
+
+    #[derive(Debug, Error)]
+    #[error("Mocked search error")]
+    struct SearchError;
+
+    impl ToolError for SearchError {}
+
+    impl From<serde_yaml::Error> for SearchError {
+        fn from(_: serde_yaml::Error) -> Self {
+            Self
+        }
+    }
+
+    /// Answers every follow up with "Answer to <question>" after a short delay.
+    struct SlowSearch;
+
+    #[async_trait]
+    impl Tool for SlowSearch {
+        type Input = String;
+        type Output = String;
+        type Error = SearchError;
+
+        async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(format!("Answer to {}", input))
+        }
+
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "Intermediate Answer",
+                "Answers follow up questions",
+                "Use this to answer follow up questions",
+                Format::new(vec![]),
+                Format::new(vec![]),
+            )
+        }
+    }
+
+    fn scripted_agent(
+        respond: fn(&str, &str) -> Option<String>,
+    ) -> Agent<ScriptedExecutor, SlowSearch> {
+        Agent::new(
+            ScriptedExecutor { respond },
+            SlowSearch,
+            EarlyStoppingConfig {
+                max_iterations: Some(5),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_runs_batch_in_input_order() {
+        // "slow" needs a follow up first, so it finishes after the queries queued behind it.
+        let agent = scripted_agent(|question, scratchpad| match question {
+            "fail" => None,
+            "slow" if !scratchpad.contains("Intermediate answer:") => {
+                Some("Follow up: How slow?".into())
+            }
+            _ => Some(format!(
+                "So the final answer is: {}",
+                question.to_uppercase()
+            )),
+        });
+        let queries = vec!["slow".into(), "fail".into(), "fast".into()];
+        let results = agent.run_batch(queries, 3).await;
+
+        assert_eq!(results.len(), 3);
+        let (finish, steps) = results[0].as_ref().unwrap();
+        assert_eq!(agent.answer(finish), "SLOW");
+        assert_eq!(steps.len(), 1);
+        assert!(matches!(
+            results[1],
+            Err(SelfAskWithSearchAgentError::ExecutorError(_))
+        ));
+        let (finish, _) = results[2].as_ref().unwrap();
+        assert_eq!(agent.answer(finish), "FAST");
+    }
+
+    #[tokio::test]
+    async fn test_traces_prompt_sent_to_model() {
+        static CLOCK_READS: AtomicU32 = AtomicU32::new(0);
+        let agent = scripted_agent(|_, _| Some("So the final answer is: Ali".into()))
+            .with_inject_current_time(true)
+            .with_clock(|| {
+                let minute = 30 + CLOCK_READS.fetch_add(1, Ordering::SeqCst);
+                chrono::DateTime::parse_from_rfc3339(&format!("2023-06-01T09:{}:00+02:00", minute))
+                    .unwrap()
+            });
+        let (result, trace) = agent.run_traced("Who lived longer?").await;
+        result.unwrap();
+        assert_eq!(CLOCK_READS.load(Ordering::SeqCst), 1);
+        assert!(trace.steps[0]
+            .prompt
+            .starts_with("The current date and time is Thursday, June 1, 2023 09:30 (UTC+02:00)."));
+        assert_eq!(
+            trace.steps[0].prompt_tokens,
+            Some(trace.steps[0].prompt.chars().count() as i32)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_records_which_parser_matched() {
+        let agent = scripted_agent(|_, scratchpad| {
+            if scratchpad.contains("Intermediate answer:") {
+                Some("So the final answer is: Berlin".into())
+            } else {
+                Some("Next question: Where is the Reichstag?".into())
+            }
+        })
+        .with_output_parser(
+            SelfAskWithSearchAgentOutputParser::default()
+                .with_ask_user_prefix("Question for user:"),
+        )
+        .with_output_parsers(vec![
+            Box::new(SelfAskWithSearchAgentOutputParser::default()),
+            Box::new(SelfAskWithSearchAgentOutputParser::new(
+                "Next question:",
+                "Intermediate Answer:",
+                &["Answer:"],
+            )),
+        ]);
+        let prompt = agent.build_prompt(&vec![], "Where?").unwrap();
+        assert!(prompt.to_text().contains("Question for user:"));
+
+        let (finish, steps) = agent.run("Where?").await.unwrap();
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].parser_index, 1);
+        assert_eq!(steps[0].action.tool_input, "Where is the Reichstag?");
+        assert_eq!(finish.parser_index, 0);
+        assert_eq!(agent.answer(&finish), "Berlin");
+
+        let agent = agent.with_ask_user_prefix(None);
+        let prompt = agent.build_prompt(&vec![], "Where?").unwrap();
+        assert!(!prompt.to_text().contains("Question for user:"));
+    }
+
+    #[test]
+    fn test_resolves_step_references() {
+        let steps = vec![AgentIntermediateStep {
+            action: AgentAction {
+                tool: "Intermediate Answer".into(),
+                tool_input: "Who founded craigslist?".into(),
+                log: "Follow up: Who founded craigslist?".into(),
+                call_id: None,
+            },
+            observation: "Craig Newmark".into(),
+            parser_index: 0,
+            thought: None,
+        }];
+        assert_eq!(
+            resolve_step_references("When was {{ result_1 }} born? {{result_2}}", &steps),
+            "When was Craig Newmark born? {{result_2}}"
+        );
+    }
+
+    #[test]
+    fn test_parses_citations() {
+        assert_eq!(
+            parse_citations("Joseph Ball [2], son of [a] [3, 1] [9] [2]", 3),
+            vec![2, 3, 1]
+        );
+        assert_eq!(parse_citations("Joseph Ball [4]", 3), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_loads_checkpoint_ignoring_partial_line() {
+        let step = AgentIntermediateStep {
+            action: AgentAction {
+                tool: "Intermediate Answer".into(),
+                tool_input: "How old was Alan Turing when he died?".into(),
+                log: "Follow up: How old was Alan Turing when he died?".into(),
+                call_id: None,
+            },
+            observation: "Alan Turing was 41 years old when he died.".into(),
+            parser_index: 0,
+            thought: None,
+        };
+        let line = serde_json::to_string(&step).unwrap();
+        let checkpoint = format!("{}\n{}", line, &line[..line.len() / 2]);
+        let steps = load_checkpoint(checkpoint.as_bytes()).unwrap();
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].action, step.action);
+        assert_eq!(steps[0].observation, step.observation);
+    }
+
+    #[test]
+    fn test_serializes_run_trace() {
+        let trace = RunTrace {
+            query: "Who won?".into(),
+            steps: vec![StepTrace {
+                output: "So the final answer is: Ali".into(),
+                decision: Some(TracedDecision::Finish {
+                    answer: "Ali".into(),
+                }),
+                ..Default::default()
+            }],
+            answer: Some("Ali".into()),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&trace).unwrap();
+        assert_eq!(
+            json["steps"][0]["decision"],
+            serde_json::json!({"type": "finish", "answer": "Ali"})
+        );
+        assert!(json.get("error").is_none());
+        let parsed: RunTrace = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.steps[0].output, "So the final answer is: Ali");
+    }
+
     #[test]
     fn test_parses_followup() {
         let parser = SelfAskWithSearchAgentOutputParser::default();
@@ -415,11 +2068,43 @@ mod tests {
             AgentDecision::Action(AgentAction {
                 tool: "Intermediate Answer".into(),
                 tool_input: "my follow up question abc?".into(),
-                log: text.into()
+                log: text.into(),
+                call_id: None,
             })
         );
     }
 
+    #[test]
+    fn test_extracts_thought() {
+        let parser = SelfAskWithSearchAgentOutputParser::default().with_thought_prefix("Thought:");
+        let text = "Yes.
+Thought: I need to know who founded craigslist first.
+Follow up: Who founded craigslist?";
+        let AgentDecision::Action(action) = parser.parse(text.into()).unwrap() else {
+            panic!("expected an action");
+        };
+        assert_eq!(
+            parser.extract_thought(&action.log).as_deref(),
+            Some("I need to know who founded craigslist first.")
+        );
+        assert_eq!(
+            SelfAskWithSearchAgentOutputParser::default().extract_thought(&action.log),
+            None
+        );
+    }
+
+    #[test]
+    fn test_finds_final_answer_start_in_partial_text() {
+        let parser = SelfAskWithSearchAgentOutputParser::default();
+        assert_eq!(parser.final_answer_start("Yes.\nSo the final"), None);
+        let text = "No.\nSo the final answer is: Ha";
+        assert_eq!(parser.final_answer_start(text), Some(text.len() - 3));
+        assert_eq!(
+            parser.final_answer_start("So the final answer is: maybe\nFollow up: Who"),
+            None
+        );
+    }
+
     #[test]
     fn test_parses_follow_up_trims_trailing_whitespace() {
         let parser = SelfAskWithSearchAgentOutputParser::default();
@@ -434,7 +2119,8 @@ mod tests {
             AgentDecision::Action(AgentAction {
                 tool: "Intermediate Answer".into(),
                 tool_input: "my follow up question abc?".into(),
-                log: text.trim_end().into()
+                log: text.trim_end().into(),
+                call_id: None,
             })
         );
     }
@@ -451,7 +2137,9 @@ mod tests {
             decision,
             AgentDecision::Finish(AgentFinish {
                 return_values: parameters!("output" => "yes abc!"),
-                log: text.into()
+                log: text.into(),
+                parser_index: 0,
+                citations: vec![],
             })
         );
     }
@@ -469,7 +2157,28 @@ mod tests {
             decision,
             AgentDecision::Finish(AgentFinish {
                 return_values: parameters!("output" => "yes abc!"),
-                log: text.into()
+                log: text.into(),
+                parser_index: 0,
+                citations: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parses_ask_user() {
+        let parser = SelfAskWithSearchAgentOutputParser::default()
+            .with_ask_user_prefix("Question for user:");
+        let text = "Yes.
+Question for user: Which Jaws movie do you mean?
+Intermediate answer: The first one.";
+        let decision = parser.parse(text.into()).unwrap();
+        assert_eq!(
+            decision,
+            AgentDecision::AskUser(AgentAction {
+                tool: "Ask User".into(),
+                tool_input: "Which Jaws movie do you mean?".into(),
+                log: "Yes.\nQuestion for user: Which Jaws movie do you mean?".into(),
+                call_id: None,
             })
         );
     }
@@ -486,11 +2195,34 @@ mod tests {
             decision,
             AgentDecision::Finish(AgentFinish {
                 return_values: parameters!("output" => "Mad Max: Fury road"),
-                log: text.into()
+                log: text.into(),
+                parser_index: 0,
+                citations: vec![],
             })
         );
     }
 
+    #[test]
+    fn test_adds_answer_to_tool_input() {
+        assert_eq!(
+            input_with_answer("Book a flight to Paris", "From which city?", "Berlin"),
+            "Book a flight to Paris\n\nQuestion: From which city?\nAnswer: Berlin"
+        );
+    }
+
+    #[test]
+    fn test_parses_final_answer_under_output_key() {
+        let parser = SelfAskWithSearchAgentOutputParser::default().with_output_key("answer");
+        let decision = parser
+            .parse("So the final answer is: Paris".into())
+            .unwrap();
+        let AgentDecision::Finish(finish) = decision else {
+            panic!("Expected a final answer");
+        };
+        assert_eq!(finish.return_values, parameters!("answer" => "Paris"));
+        assert_eq!(parser.output_key(), "answer");
+    }
+
     #[test]
     fn test_builds_agent_sratchpad() {
         #[derive(Clone)]
@@ -592,6 +2324,7 @@ mod tests {
             mock_search,
             EarlyStoppingConfig {
                 max_iterations: None,
+                max_tool_calls: None,
                 max_time_elapsed_seconds: None,
             },
         );
@@ -603,16 +2336,22 @@ mod tests {
                     log: "Yes.
 Follow up: How old was Muhammad Ali when he died?"
                         .into(),
+                    call_id: None,
                 },
                 observation: "Muhammad Ali was 74 years old when he died.".into(),
+                parser_index: 0,
+                thought: None,
             },
             AgentIntermediateStep {
                 action: AgentAction {
                     tool: "Intermediate Answer".into(),
                     tool_input: "How old was Alan Turing when he died?".into(),
                     log: "Follow up: How old was Alan Turing when he died?".into(),
+                    call_id: None,
                 },
                 observation: "Alan Turing was 41 years old when he died.".into(),
+                parser_index: 0,
+                thought: None,
             },
         ];
 
@@ -626,4 +2365,72 @@ Intermediate answer: Alan Turing was 41 years old when he died.\n";
 
         assert_eq!(scratchpad, expected_scratchpad);
     }
+
+    #[test]
+    fn test_injects_current_time() {
+        let agent = scripted_agent(|_, _| None);
+        let prompt = agent.build_prompt(&vec![], "Who won?").unwrap();
+        assert!(prompt.to_text().starts_with("Question:"));
+
+        let agent = agent.with_inject_current_time(true).with_clock(|| {
+            chrono::DateTime::parse_from_rfc3339("2023-06-01T09:30:00+02:00").unwrap()
+        });
+        let prompt = agent.build_prompt(&vec![], "Who won?").unwrap();
+        assert!(prompt.to_text().starts_with(
+            "The current date and time is Thursday, June 1, 2023 09:30 (UTC+02:00).\n\nQuestion:"
+        ));
+    }
+
+    #[test]
+    fn test_formats_observations_with_hook() {
+        let agent =
+            scripted_agent(|_, _| None).with_observation_formatter(|action, observation| {
+                format!(
+                    "[{}] {}",
+                    action.call_id.as_deref().unwrap_or_default(),
+                    observation.as_str().unwrap_or_default()
+                )
+            });
+        let intermediate_steps = vec![AgentIntermediateStep {
+            action: AgentAction {
+                tool: "Intermediate Answer".into(),
+                tool_input: "How old was Alan Turing when he died?".into(),
+                log: "Follow up: How old was Alan Turing when he died?".into(),
+                call_id: Some("call-1".into()),
+            },
+            observation: "Alan Turing was 41 years old when he died.".into(),
+            parser_index: 0,
+            thought: None,
+        }];
+
+        let scratchpad = agent.build_agent_scratchpad(&intermediate_steps);
+        assert_eq!(
+            scratchpad,
+            "Follow up: How old was Alan Turing when he died?
+Intermediate answer: [call-1] Alan Turing was 41 years old when he died.\n"
+        );
+    }
+
+    #[test]
+    fn test_caps_tokens_per_step() {
+        let agent = scripted_agent(|_, _| None);
+        assert!(matches!(
+            agent.step_options(false).get(OptDiscriminants::MaxTokens),
+            Some(Opt::MaxTokens(DEFAULT_MAX_TOKENS_PER_STEP))
+        ));
+
+        let agent = agent.with_max_tokens_per_step(None);
+        assert!(agent
+            .step_options(false)
+            .get(OptDiscriminants::MaxTokens)
+            .is_none());
+
+        let agent = agent.with_max_tokens_per_step(Some(256));
+        let options = agent.step_options(true);
+        assert!(matches!(
+            options.get(OptDiscriminants::MaxTokens),
+            Some(Opt::MaxTokens(256))
+        ));
+        assert!(options.get(OptDiscriminants::Stream).is_some());
+    }
 }
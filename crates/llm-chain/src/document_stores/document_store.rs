@@ -18,6 +18,16 @@ where
     async fn next_id(&self) -> Result<T, Self::Error>;
 
     async fn insert(&mut self, documents: &HashMap<T, Document<M>>) -> Result<(), Self::Error>;
+
+    /// Replaces the document stored under `id`, which must already exist.
+    async fn replace(&mut self, id: &T, document: &Document<M>) -> Result<(), Self::Error>;
+
+    /// Deletes the expired documents and returns their ids.
+    ///
+    /// Stores that don't track expiry keep every document.
+    async fn purge_expired(&mut self) -> Result<Vec<T>, Self::Error> {
+        Ok(vec![])
+    }
 }
 
 pub trait DocumentStoreError {}
@@ -1,9 +1,12 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::document_stores::document_store::*;
-use crate::schema::Document;
+use crate::document_stores::keyword_index::KeywordIndex;
+use crate::schema::{Document, ExpiryClock};
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
 
@@ -14,6 +17,8 @@ where
 {
     page_content: String,
     metadata: Option<M>,
+    binary_content: Option<Vec<u8>>,
+    expires_at: Option<DateTime<Utc>>,
 }
 
 impl<M> From<&InMemoryDocument<M>> for Document<M>
@@ -32,6 +37,8 @@ where
         Document {
             page_content: val.page_content.clone(),
             metadata,
+            binary_content: val.binary_content.clone(),
+            expires_at: val.expires_at,
         }
     }
 }
@@ -52,6 +59,8 @@ where
         InMemoryDocument {
             page_content: val.page_content.clone(),
             metadata,
+            binary_content: val.binary_content.clone(),
+            expires_at: val.expires_at,
         }
     }
 }
@@ -62,6 +71,8 @@ pub enum InMemoryDocumentStoreError {
     Serde(#[from] serde_json::Error),
     #[error("Key \"{0}\" already exists!")]
     KeyConflict(String),
+    #[error("Key \"{0}\" does not exist!")]
+    KeyNotFound(String),
 }
 
 impl DocumentStoreError for InMemoryDocumentStoreError {}
@@ -71,6 +82,10 @@ where
     M: Serialize + DeserializeOwned + Send + Sync,
 {
     map: HashMap<usize, InMemoryDocument<M>>,
+    index: KeywordIndex,
+    /// One more than the largest id ever inserted, so ids of purged documents aren't reused.
+    next_id: usize,
+    clock: ExpiryClock,
 }
 
 impl<M> InMemoryDocumentStore<M>
@@ -80,8 +95,45 @@ where
     pub fn new() -> Self {
         InMemoryDocumentStore {
             map: HashMap::new(),
+            index: KeywordIndex::default(),
+            next_id: 0,
+            clock: Arc::new(Utc::now),
         }
     }
+
+    /// Replaces the clock used to decide which documents have expired, which defaults to the
+    /// system time.
+    pub fn with_clock<F>(mut self, clock: F) -> Self
+    where
+        F: Fn() -> DateTime<Utc> + Send + Sync + 'static,
+    {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Returns up to `limit` documents containing words of `query`, ranked by their BM25 score, with
+    /// their ids.
+    ///
+    /// Words are matched exactly, ignoring case and punctuation, see [`KeywordIndex`]. Documents are
+    /// indexed as they are added, so searching doesn't scan the store. Expired documents are not
+    /// returned.
+    pub fn keyword_search(&self, query: &str, limit: usize) -> Vec<(usize, Document<M>)> {
+        self.index
+            .search(query, usize::MAX)
+            .into_iter()
+            .filter_map(|(id, _)| {
+                let document = self.map.get(&id).filter(|d| !self.is_expired(d))?;
+                Some((id, document.into()))
+            })
+            .take(limit)
+            .collect()
+    }
+
+    fn is_expired(&self, document: &InMemoryDocument<M>) -> bool {
+        document
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= (self.clock)())
+    }
 }
 
 impl<M> Default for InMemoryDocumentStore<M>
@@ -100,12 +152,14 @@ where
 {
     type Error = InMemoryDocumentStoreError;
 
+    /// Expired documents are returned until they are purged, so callers can tell them apart from
+    /// missing documents with [`Document::is_expired`].
     async fn get(&self, id: &usize) -> Result<Option<Document<M>>, Self::Error> {
         Ok(self.map.get(id).map(|m| m.into()))
     }
 
     async fn next_id(&self) -> Result<usize, Self::Error> {
-        Ok(self.map.len())
+        Ok(self.next_id)
     }
 
     async fn insert(&mut self, documents: &HashMap<usize, Document<M>>) -> Result<(), Self::Error> {
@@ -114,9 +168,93 @@ where
                 return Err(InMemoryDocumentStoreError::KeyConflict(key.to_string()));
             } else {
                 self.map.insert(key.clone(), value.into());
+                self.index.insert(*key, &value.page_content);
+                self.next_id = self.next_id.max(key + 1);
             }
         }
 
         Ok(())
     }
+
+    async fn replace(&mut self, id: &usize, document: &Document<M>) -> Result<(), Self::Error> {
+        match self.map.get_mut(id) {
+            Some(existing) => {
+                *existing = document.into();
+                self.index.insert(*id, &document.page_content);
+                Ok(())
+            }
+            None => Err(InMemoryDocumentStoreError::KeyNotFound(id.to_string())),
+        }
+    }
+
+    async fn purge_expired(&mut self) -> Result<Vec<usize>, Self::Error> {
+        let expired: Vec<usize> = self
+            .map
+            .iter()
+            .filter(|(_, document)| self.is_expired(document))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &expired {
+            self.map.remove(id);
+            self.index.remove(*id);
+        }
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[tokio::test]
+    async fn test_hides_and_purges_expired_documents() {
+        let now = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+        let mut store = InMemoryDocumentStore::<()>::new().with_clock(move || now);
+        store
+            .insert(&HashMap::from([
+                (0, Document::new("stale".into()).with_expires_at(now)),
+                (
+                    1,
+                    Document::new("fresh".into()).with_expires_at(now + Duration::hours(1)),
+                ),
+                (2, Document::new("forever".into())),
+            ]))
+            .await
+            .unwrap();
+
+        assert!(store.keyword_search("stale", 1).is_empty());
+        assert!(store.get(&0).await.unwrap().unwrap().is_expired(now));
+        assert_eq!(store.get(&1).await.unwrap().unwrap().page_content, "fresh");
+        assert_eq!(store.purge_expired().await.unwrap(), vec![0]);
+        assert!(store.get(&0).await.unwrap().is_none());
+        assert_eq!(store.next_id().await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_ranks_keyword_matches() {
+        let mut store = InMemoryDocumentStore::<()>::new();
+        store
+            .insert(&HashMap::from([
+                (0, Document::new("Rust is fast.".into())),
+                (1, Document::new("Rust, rust and more RUST".into())),
+                (2, Document::new("Python is slow".into())),
+            ]))
+            .await
+            .unwrap();
+        let ids = |results: Vec<(usize, Document<()>)>| -> Vec<usize> {
+            results.into_iter().map(|(id, _)| id).collect()
+        };
+
+        assert_eq!(ids(store.keyword_search("rust", 10)), vec![1, 0]);
+        // Matching both words beats repeating one of them.
+        assert_eq!(ids(store.keyword_search("is rust?", 1)), vec![0]);
+        assert!(store.keyword_search("java", 10).is_empty());
+
+        store
+            .replace(&1, &Document::new("Go is simple".into()))
+            .await
+            .unwrap();
+        assert_eq!(ids(store.keyword_search("rust", 10)), vec![0]);
+    }
 }
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Controls how quickly repeated terms stop adding to a document's score.
+const K1: f32 = 1.2;
+/// Controls how much long documents are penalized.
+const B: f32 = 0.75;
+
+/// An index of the words in each document of a store, for keyword search.
+///
+/// Documents are ranked with BM25, which favours documents where the query's words are frequent,
+/// rare across the store, and make up more of a shorter document. Words are matched exactly,
+/// ignoring case and punctuation.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct KeywordIndex {
+    /// The number of times each term appears in each document.
+    term_frequencies: HashMap<usize, HashMap<String, u32>>,
+    /// The number of documents each term appears in.
+    document_frequencies: HashMap<String, usize>,
+    /// The total number of terms in all documents.
+    total_terms: usize,
+}
+
+/// Splits text into lowercase words.
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+}
+
+impl KeywordIndex {
+    /// Indexes `text` as the content of `document_id`, replacing what it was indexed with before.
+    pub fn insert(&mut self, document_id: usize, text: &str) {
+        self.remove(document_id);
+        let mut frequencies: HashMap<String, u32> = HashMap::new();
+        for term in tokenize(text) {
+            *frequencies.entry(term).or_default() += 1;
+        }
+        for term in frequencies.keys() {
+            *self.document_frequencies.entry(term.clone()).or_default() += 1;
+        }
+        self.total_terms += frequencies.values().sum::<u32>() as usize;
+        self.term_frequencies.insert(document_id, frequencies);
+    }
+
+    /// Removes `document_id` from the index, if it was indexed.
+    pub fn remove(&mut self, document_id: usize) {
+        let Some(frequencies) = self.term_frequencies.remove(&document_id) else {
+            return;
+        };
+        for term in frequencies.keys() {
+            if let Some(count) = self.document_frequencies.get_mut(term) {
+                *count -= 1;
+                if *count == 0 {
+                    self.document_frequencies.remove(term);
+                }
+            }
+        }
+        self.total_terms -= frequencies.values().sum::<u32>() as usize;
+    }
+
+    /// Returns the ids of up to `limit` documents containing at least one word of `query` with
+    /// their BM25 scores, best first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(usize, f32)> {
+        let document_count = self.term_frequencies.len() as f32;
+        if document_count == 0.0 {
+            return vec![];
+        }
+        let average_length = self.total_terms as f32 / document_count;
+        let mut terms: Vec<String> = tokenize(query).collect();
+        terms.sort();
+        terms.dedup();
+
+        let mut scores: Vec<(usize, f32)> = self
+            .term_frequencies
+            .iter()
+            .filter_map(|(&document_id, frequencies)| {
+                let length = frequencies.values().sum::<u32>() as f32;
+                let score: f32 = terms
+                    .iter()
+                    .filter_map(|term| {
+                        let frequency = *frequencies.get(term)? as f32;
+                        let containing = self.document_frequencies[term] as f32;
+                        let idf =
+                            (1.0 + (document_count - containing + 0.5) / (containing + 0.5)).ln();
+                        let length_norm = 1.0 - B + B * length / average_length;
+                        Some(idf * frequency * (K1 + 1.0) / (frequency + K1 * length_norm))
+                    })
+                    .sum();
+                (score > 0.0).then_some((document_id, score))
+            })
+            .collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scores.truncate(limit);
+        scores
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(results: &[(usize, f32)]) -> Vec<usize> {
+        results.iter().map(|&(id, _)| id).collect()
+    }
+
+    #[test]
+    fn test_ranks_matching_documents() {
+        let mut index = KeywordIndex::default();
+        index.insert(0, "cat cat cat");
+        index.insert(1, "A cat and a dog.");
+        index.insert(2, "dog");
+
+        let results = index.search("Cat", 10);
+        assert_eq!(ids(&results), [0, 1]);
+        assert!(results[0].1 > results[1].1);
+        assert_eq!(ids(&index.search("cat", 1)), [0]);
+        assert!(index.search("bird", 10).is_empty());
+    }
+
+    #[test]
+    fn test_keeps_index_in_sync_on_update_and_removal() {
+        let mut index = KeywordIndex::default();
+        index.insert(0, "cat");
+        index.insert(1, "dog");
+
+        index.insert(0, "dog dog");
+        assert!(index.search("cat", 10).is_empty());
+        assert_eq!(ids(&index.search("dog", 10)), [0, 1]);
+        assert_eq!(index.total_terms, 3);
+
+        index.remove(1);
+        assert_eq!(ids(&index.search("dog", 10)), [0]);
+        index.remove(0);
+        index.remove(0);
+        assert!(index.search("dog", 10).is_empty());
+        assert!(index.document_frequencies.is_empty());
+        assert_eq!(index.total_terms, 0);
+    }
+}
@@ -1,2 +1,3 @@
 pub mod document_store;
 pub mod in_memory_document_store;
+pub mod keyword_index;
@@ -0,0 +1,272 @@
+//! Utilities for working with [`Embeddings`] implementations.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::traits::{Embeddings, EmbeddingsError};
+
+type FallbackCallback = Box<dyn Fn(&dyn std::error::Error) + Send + Sync>;
+
+/// Embeddings that fall back to a backup model when the primary one fails.
+///
+/// This lets indexing and search keep working, at lower quality, while an embeddings API is
+/// unavailable. The backup can be any [`Embeddings`] implementation, such as a small model running
+/// locally.
+///
+/// Vectors from different models generally don't live in the same space, so the backup must produce
+/// vectors of the same dimension as the primary, and documents embedded by the backup won't be found
+/// reliably by queries embedded by the primary (and the other way around). Reindex those documents
+/// once the primary is available again.
+///
+/// The dimension of the primary's vectors is learned from its first successful call. Backup vectors
+/// of a different dimension are rejected, as no vector store could mix them with the primary's.
+pub struct FallbackEmbeddings<P, B> {
+    primary: P,
+    backup: B,
+    on_fallback: Option<FallbackCallback>,
+    /// The dimension of the primary's vectors, or 0 until it is known.
+    dimension: AtomicUsize,
+}
+
+impl<P, B> FallbackEmbeddings<P, B>
+where
+    P: Embeddings,
+    B: Embeddings,
+{
+    pub fn new(primary: P, backup: B) -> Self {
+        Self {
+            primary,
+            backup,
+            on_fallback: None,
+            dimension: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets a function called with the primary's error whenever the backup is used.
+    pub fn with_on_fallback<F>(mut self, on_fallback: F) -> Self
+    where
+        F: Fn(&dyn std::error::Error) + Send + Sync + 'static,
+    {
+        self.on_fallback = Some(Box::new(on_fallback));
+        self
+    }
+
+    fn notify(&self, error: &P::Error) {
+        if let Some(on_fallback) = &self.on_fallback {
+            on_fallback(error);
+        }
+    }
+
+    /// Returns the expected and actual dimensions if backup vectors don't have the dimension of
+    /// the primary's. Any dimension is accepted until the primary's is known.
+    fn dimension_mismatch(&self, vecs: &[Vec<f32>]) -> Option<(usize, usize)> {
+        let expected = self.dimension.load(Ordering::Relaxed);
+        vecs.iter()
+            .map(Vec::len)
+            .find(|&actual| expected != 0 && actual != expected)
+            .map(|actual| (expected, actual))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FallbackEmbeddingsError<P, B>
+where
+    P: std::fmt::Debug + std::error::Error,
+    B: std::fmt::Debug + std::error::Error,
+{
+    #[error("Primary embeddings failed: {primary}; backup embeddings failed: {backup}")]
+    BothFailed { primary: P, backup: B },
+    #[error("Primary embeddings failed: {primary}; backup embeddings returned vectors of dimension {actual} instead of {expected}")]
+    DimensionMismatch {
+        primary: P,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl<P, B> EmbeddingsError for FallbackEmbeddingsError<P, B>
+where
+    P: std::fmt::Debug + std::error::Error,
+    B: std::fmt::Debug + std::error::Error,
+{
+}
+
+#[async_trait]
+impl<P, B> Embeddings for FallbackEmbeddings<P, B>
+where
+    P: Embeddings + Send + Sync,
+    B: Embeddings + Send + Sync,
+{
+    type Error = FallbackEmbeddingsError<P::Error, B::Error>;
+
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+        match self.primary.embed_texts(texts.clone()).await {
+            Ok(vecs) => {
+                if let Some(vec) = vecs.first() {
+                    self.dimension.store(vec.len(), Ordering::Relaxed);
+                }
+                Ok(vecs)
+            }
+            Err(primary) => {
+                self.notify(&primary);
+                match self.backup.embed_texts(texts).await {
+                    Ok(vecs) => match self.dimension_mismatch(&vecs) {
+                        Some((expected, actual)) => {
+                            Err(FallbackEmbeddingsError::DimensionMismatch {
+                                primary,
+                                expected,
+                                actual,
+                            })
+                        }
+                        None => Ok(vecs),
+                    },
+                    Err(backup) => Err(FallbackEmbeddingsError::BothFailed { primary, backup }),
+                }
+            }
+        }
+    }
+
+    async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+        match self.primary.embed_query(query.clone()).await {
+            Ok(vec) => {
+                self.dimension.store(vec.len(), Ordering::Relaxed);
+                Ok(vec)
+            }
+            Err(primary) => {
+                self.notify(&primary);
+                match self.backup.embed_query(query).await {
+                    Ok(vec) => match self.dimension_mismatch(std::slice::from_ref(&vec)) {
+                        Some((expected, actual)) => {
+                            Err(FallbackEmbeddingsError::DimensionMismatch {
+                                primary,
+                                expected,
+                                actual,
+                            })
+                        }
+                        None => Ok(vec),
+                    },
+                    Err(backup) => Err(FallbackEmbeddingsError::BothFailed { primary, backup }),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    #[derive(Debug, Error)]
+    #[error("{0} is down")]
+    struct DownError(&'static str);
+
+    impl EmbeddingsError for DownError {}
+
+    /// Embeds every text as `dimension` copies of `value`, or fails while `down` is set.
+    struct FixedEmbeddings {
+        name: &'static str,
+        value: f32,
+        dimension: usize,
+        down: Arc<AtomicBool>,
+    }
+
+    impl FixedEmbeddings {
+        fn new(name: &'static str, value: f32, dimension: usize) -> Self {
+            Self {
+                name,
+                value,
+                dimension,
+                down: Arc::new(AtomicBool::new(false)),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Embeddings for FixedEmbeddings {
+        type Error = DownError;
+
+        async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+            if self.down.load(Ordering::Relaxed) {
+                return Err(DownError(self.name));
+            }
+            Ok(vec![vec![self.value; self.dimension]; texts.len()])
+        }
+
+        async fn embed_query(&self, _query: String) -> Result<Vec<f32>, Self::Error> {
+            Ok(self.embed_texts(vec![String::new()]).await?.remove(0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_when_primary_fails() {
+        let primary = FixedEmbeddings::new("primary", 1.0, 2);
+        let primary_down = primary.down.clone();
+        let backup = FixedEmbeddings::new("backup", 2.0, 2);
+        let backup_down = backup.down.clone();
+        let fallbacks = Arc::new(AtomicUsize::new(0));
+        let counter = fallbacks.clone();
+        let embeddings = FallbackEmbeddings::new(primary, backup).with_on_fallback(move |error| {
+            assert_eq!(error.to_string(), "primary is down");
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+
+        let texts = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(
+            embeddings.embed_texts(texts.clone()).await.unwrap(),
+            vec![vec![1.0, 1.0]; 2]
+        );
+        primary_down.store(true, Ordering::Relaxed);
+        assert_eq!(
+            embeddings.embed_texts(texts.clone()).await.unwrap(),
+            vec![vec![2.0, 2.0]; 2]
+        );
+        assert_eq!(
+            embeddings.embed_query("a".to_string()).await.unwrap(),
+            vec![2.0, 2.0]
+        );
+        assert_eq!(fallbacks.load(Ordering::Relaxed), 2);
+
+        backup_down.store(true, Ordering::Relaxed);
+        assert!(matches!(
+            embeddings.embed_texts(texts).await,
+            Err(FallbackEmbeddingsError::BothFailed {
+                primary: DownError("primary"),
+                backup: DownError("backup"),
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_backup_vectors_of_another_dimension() {
+        let primary = FixedEmbeddings::new("primary", 1.0, 2);
+        let primary_down = primary.down.clone();
+        let embeddings = FallbackEmbeddings::new(primary, FixedEmbeddings::new("backup", 2.0, 3));
+
+        // Until the primary's dimension is known, the backup's vectors are returned as they are.
+        primary_down.store(true, Ordering::Relaxed);
+        assert_eq!(
+            embeddings.embed_query("a".to_string()).await.unwrap(),
+            vec![2.0; 3]
+        );
+
+        primary_down.store(false, Ordering::Relaxed);
+        embeddings.embed_query("a".to_string()).await.unwrap();
+        primary_down.store(true, Ordering::Relaxed);
+        assert!(matches!(
+            embeddings.embed_texts(vec!["a".to_string()]).await,
+            Err(FallbackEmbeddingsError::DimensionMismatch {
+                expected: 2,
+                actual: 3,
+                ..
+            })
+        ));
+        assert!(matches!(
+            embeddings.embed_query("a".to_string()).await,
+            Err(FallbackEmbeddingsError::DimensionMismatch { .. })
+        ));
+    }
+}
@@ -1,5 +1,8 @@
 //! Utilities for working with executors
 //!
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::time::Instant;
+
 /// A macro that creates a new executor for a specified model.
 ///
 /// This macro makes it easy to create a new executor for a specific model without having to
@@ -69,3 +72,87 @@ macro_rules! executor {
         llm_chain_local::Executor::new_with_options($options)
     }};
 }
+
+/// The callback invoked by a [`Heartbeat`] with the time elapsed since the call started.
+pub type HeartbeatCallback = Arc<dyn Fn(Duration) + Send + Sync>;
+
+/// Invokes a callback periodically while an executor call is in flight.
+///
+/// This lets a UI show progress for calls that don't stream their output.
+#[derive(Clone)]
+pub struct Heartbeat {
+    interval: Duration,
+    callback: HeartbeatCallback,
+}
+
+impl Heartbeat {
+    /// Creates a heartbeat that calls `callback` every `interval`.
+    pub fn new<F>(interval: Duration, callback: F) -> Self
+    where
+        F: Fn(Duration) + Send + Sync + 'static,
+    {
+        Self {
+            interval,
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Drives `future` to completion, invoking the callback every interval until it finishes.
+    pub async fn run<F: Future>(&self, future: F) -> F::Output {
+        let start = Instant::now();
+        let mut ticks = tokio::time::interval_at(start + self.interval, self.interval);
+        tokio::pin!(future);
+        loop {
+            tokio::select! {
+                output = &mut future => return output,
+                _ = ticks.tick() => (self.callback)(start.elapsed()),
+            }
+        }
+    }
+}
+
+/// What an executor reports about each request it sends to a [`RequestHook`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestMetrics {
+    /// The model the request was sent to.
+    pub model: String,
+    /// The number of tokens in the prompt, if the provider reported it.
+    pub prompt_tokens: Option<u32>,
+    /// The number of tokens in the completion, if the provider reported it. Streamed completions
+    /// and embeddings don't report it.
+    pub completion_tokens: Option<u32>,
+    /// How long the request took. For streamed completions, until the stream started.
+    pub latency: Duration,
+    /// Whether the provider accepted the request.
+    pub success: bool,
+}
+
+/// A callback invoked after every request an executor or embeddings client sends, e.g. to feed
+/// request metrics to a dashboard.
+///
+/// It is called on the request's task, so it should be quick, such as updating counters or sending
+/// the metrics to a channel.
+pub type RequestHook = Arc<dyn Fn(&RequestMetrics) + Send + Sync>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_heartbeat_ticks_while_in_flight() {
+        let beats = Arc::new(AtomicUsize::new(0));
+        let counter = beats.clone();
+        let heartbeat = Heartbeat::new(Duration::from_millis(10), move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let output = heartbeat
+            .run(async {
+                tokio::time::sleep(Duration::from_millis(55)).await;
+                42
+            })
+            .await;
+        assert_eq!(output, 42);
+        assert!(beats.load(Ordering::SeqCst) >= 3);
+    }
+}
@@ -0,0 +1,171 @@
+//! Selects the few-shot examples shown in a prompt by their similarity to the query.
+//!
+//! A fixed set of examples has to cover every kind of query. A [`DynamicFewShotSelector`] instead
+//! keeps a pool of examples in a vector store and picks the ones closest to each query, so the
+//! model is shown the most relevant demonstrations.
+
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    schema::Document,
+    traits::{Embeddings, VectorStore},
+    Parameters,
+};
+
+/// The parameter [`DynamicFewShotSelector::with_examples`] puts the examples in.
+pub const EXAMPLES_KEY: &str = "examples";
+
+/// An input with the output the model should give for it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FewShotExample {
+    pub input: String,
+    pub output: String,
+}
+
+impl FewShotExample {
+    pub fn new(input: &str, output: &str) -> Self {
+        Self {
+            input: input.to_string(),
+            output: output.to_string(),
+        }
+    }
+}
+
+/// Picks the examples most similar to a query from a vector store.
+///
+/// Examples are stored as documents whose content is the example's input, so they are matched by
+/// how similar their input is to the query. The selected examples are put in the `examples`
+/// parameter, to be used as `{{examples}}` in a prompt template.
+///
+/// # Example
+///
+/// ```no_run
+/// # use llm_chain::{few_shot::{DynamicFewShotSelector, FewShotExample}, parameters, prompt};
+/// # use llm_chain::traits::{Embeddings, VectorStore};
+/// # async fn run<E, V>(store: V)
+/// # where
+/// #     E: Embeddings,
+/// #     V: VectorStore<E, FewShotExample>,
+/// # {
+/// let selector = DynamicFewShotSelector::new(store, 3);
+/// selector
+///     .add_examples(vec![FewShotExample::new("happy", "sad"), FewShotExample::new("tall", "short")])
+///     .await
+///     .unwrap();
+/// let template = prompt!("Give the antonym of every input.\n\n{{examples}}\n\nInput: {{text}}\nOutput:");
+/// let parameters = selector.with_examples(&parameters!("sunny")).await.unwrap();
+/// # }
+/// ```
+pub struct DynamicFewShotSelector<E, V>
+where
+    E: Embeddings,
+    V: VectorStore<E, FewShotExample>,
+{
+    store: V,
+    k: u32,
+    input_label: String,
+    output_label: String,
+    _embeddings: PhantomData<E>,
+}
+
+impl<E, V> DynamicFewShotSelector<E, V>
+where
+    E: Embeddings,
+    V: VectorStore<E, FewShotExample>,
+{
+    /// Creates a selector that picks the `k` examples in `store` most similar to the query.
+    pub fn new(store: V, k: u32) -> Self {
+        Self {
+            store,
+            k,
+            input_label: "Input".to_string(),
+            output_label: "Output".to_string(),
+            _embeddings: PhantomData,
+        }
+    }
+
+    /// Sets the labels the examples are formatted with. Defaults to `Input` and `Output`.
+    pub fn with_labels(mut self, input_label: &str, output_label: &str) -> Self {
+        self.input_label = input_label.to_string();
+        self.output_label = output_label.to_string();
+        self
+    }
+
+    /// Adds examples to the pool, returning their ids in the store.
+    pub async fn add_examples(
+        &self,
+        examples: Vec<FewShotExample>,
+    ) -> Result<Vec<String>, V::Error> {
+        let documents = examples
+            .into_iter()
+            .map(|example| {
+                let mut document = Document::new(example.input.clone());
+                document.metadata = Some(example);
+                document
+            })
+            .collect();
+        self.store.add_documents(documents).await
+    }
+
+    /// Returns the examples most similar to `query`, most similar first.
+    pub async fn select(&self, query: &str) -> Result<Vec<FewShotExample>, V::Error> {
+        Ok(self
+            .store
+            .similarity_search(query.to_string(), self.k)
+            .await?
+            .into_iter()
+            .filter_map(|document| document.metadata)
+            .collect())
+    }
+
+    /// Formats examples for a prompt, one labelled input and output per example.
+    pub fn format_examples(&self, examples: &[FewShotExample]) -> String {
+        format_examples(examples, &self.input_label, &self.output_label)
+    }
+
+    /// Copies the parameters, adding the examples most similar to their text under
+    /// [`EXAMPLES_KEY`].
+    ///
+    /// If the parameters have no text, no examples are selected and the key is set to an empty
+    /// string, so templates using it still render.
+    pub async fn with_examples(&self, parameters: &Parameters) -> Result<Parameters, V::Error> {
+        let examples = match parameters.get_text() {
+            Some(query) => self.select(&query).await?,
+            None => vec![],
+        };
+        Ok(parameters.with(EXAMPLES_KEY, self.format_examples(&examples)))
+    }
+}
+
+fn format_examples(examples: &[FewShotExample], input_label: &str, output_label: &str) -> String {
+    examples
+        .iter()
+        .map(|example| {
+            format!(
+                "{}: {}\n{}: {}",
+                input_label, example.input, output_label, example.output
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats_examples() {
+        let examples = vec![
+            FewShotExample::new("happy", "sad"),
+            FewShotExample::new("tall", "short"),
+        ];
+        assert_eq!(
+            format_examples(&examples, "Word", "Antonym"),
+            "Word: happy\nAntonym: sad\n\nWord: tall\nAntonym: short"
+        );
+        assert_eq!(format_examples(&[], "Input", "Output"), "");
+    }
+}
@@ -25,13 +25,19 @@
 pub mod agents;
 pub mod chains;
 pub mod document_stores;
+pub mod embeddings;
 pub mod executor;
+pub mod few_shot;
 pub mod frame;
+pub mod moderation;
 pub mod options;
 pub mod output;
 pub mod parameters;
 pub mod parsing;
 pub mod prompt;
+pub mod rag;
+pub mod rerank;
+pub mod retry;
 pub mod schema;
 pub mod serialization;
 pub mod step;
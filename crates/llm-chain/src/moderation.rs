@@ -0,0 +1,205 @@
+//! Screens model output for policy violations before it is returned.
+//!
+//! [`ModeratedExecutor`] wraps another executor and runs every completion through a [`Moderator`],
+//! such as a moderation API or a local classifier. Flagged completions are blocked or annotated,
+//! see [`ModerationAction`].
+
+use std::error::Error;
+
+use async_trait::async_trait;
+
+use crate::{
+    options::Options,
+    output::Output,
+    prompt::{Data, Prompt},
+    tokens::{PromptTokensError, TokenCount, TokenizerError},
+    traits::{Executor, ExecutorCreationError, ExecutorError},
+};
+
+/// The verdict of a [`Moderator`] on a piece of text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Moderation {
+    /// Whether the moderator considers the text a violation.
+    pub flagged: bool,
+    /// The score of each category the moderator checks, from 0 to 1, higher meaning more likely
+    /// to be a violation.
+    pub category_scores: Vec<(String, f32)>,
+}
+
+/// Checks text for policy violations.
+#[async_trait]
+pub trait Moderator {
+    async fn moderate(&self, text: &str) -> Result<Moderation, Box<dyn Error + Send + Sync>>;
+}
+
+/// What [`ModeratedExecutor`] does with a flagged completion.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModerationAction {
+    /// Replaces the completion with the given refusal.
+    Block { refusal: String },
+    /// Keeps the completion, appending a note listing the flagged categories.
+    Annotate,
+}
+
+impl Default for ModerationAction {
+    fn default() -> Self {
+        Self::Block {
+            refusal: "I'm sorry, but I can't help with that.".to_string(),
+        }
+    }
+}
+
+/// An executor that screens the completions of another executor with a [`Moderator`].
+///
+/// Completions are moderated as a whole, so streamed outputs are collected before they are
+/// returned. Prompts are not moderated.
+pub struct ModeratedExecutor<E, M> {
+    executor: E,
+    moderator: M,
+    threshold: Option<f32>,
+    action: ModerationAction,
+}
+
+impl<E, M> ModeratedExecutor<E, M>
+where
+    E: Executor,
+    M: Moderator,
+{
+    /// Wraps `executor`, blocking the completions that `moderator` flags.
+    pub fn new(executor: E, moderator: M) -> Self {
+        Self {
+            executor,
+            moderator,
+            threshold: None,
+            action: ModerationAction::default(),
+        }
+    }
+
+    /// Also flags completions where any category scores at least `threshold`, even if the
+    /// moderator didn't flag them, to apply a stricter policy than the moderator's own.
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// Sets what happens to flagged completions. Defaults to blocking them with a generic refusal.
+    pub fn with_action(mut self, action: ModerationAction) -> Self {
+        self.action = action;
+        self
+    }
+}
+
+/// Returns the categories scoring at least `threshold` if `moderation` is flagged or any category
+/// reaches the threshold, or `None` if it passes.
+fn flagged_categories(moderation: &Moderation, threshold: Option<f32>) -> Option<Vec<String>> {
+    let over_threshold: Vec<String> = moderation
+        .category_scores
+        .iter()
+        .filter(|(_, score)| threshold.is_some_and(|threshold| *score >= threshold))
+        .map(|(category, _)| category.clone())
+        .collect();
+    (moderation.flagged || !over_threshold.is_empty()).then_some(over_threshold)
+}
+
+#[async_trait]
+impl<E, M> Executor for ModeratedExecutor<E, M>
+where
+    E: Executor + Send + Sync,
+    M: Moderator + Default + Send + Sync,
+{
+    type StepTokenizer<'a>
+        = E::StepTokenizer<'a>
+    where
+        Self: 'a;
+
+    /// Creates the inner executor with `options` and the default moderator.
+    fn new_with_options(options: Options) -> Result<Self, ExecutorCreationError> {
+        Ok(Self::new(E::new_with_options(options)?, M::default()))
+    }
+
+    async fn execute(&self, options: &Options, prompt: &Prompt) -> Result<Output, ExecutorError> {
+        let data = self
+            .executor
+            .execute(options, prompt)
+            .await?
+            .to_immediate()
+            .await?
+            .as_content();
+        let Some(text) = data.extract_last_body() else {
+            return Ok(Output::new_immediate(data));
+        };
+        let moderation = self
+            .moderator
+            .moderate(text)
+            .await
+            .map_err(ExecutorError::InnerError)?;
+        let Some(categories) = flagged_categories(&moderation, self.threshold) else {
+            return Ok(Output::new_immediate(data));
+        };
+        Ok(Output::new_immediate(match &self.action {
+            ModerationAction::Block { refusal } => Data::Text(refusal.clone()),
+            ModerationAction::Annotate => {
+                let note = if categories.is_empty() {
+                    "[This response was flagged by moderation]".to_string()
+                } else {
+                    format!(
+                        "[This response was flagged by moderation for: {}]",
+                        categories.join(", ")
+                    )
+                };
+                data.map(|body| format!("{}\n\n{}", body, note))
+            }
+        }))
+    }
+
+    fn tokens_used(
+        &self,
+        options: &Options,
+        prompt: &Prompt,
+    ) -> Result<TokenCount, PromptTokensError> {
+        self.executor.tokens_used(options, prompt)
+    }
+
+    fn max_tokens_allowed(&self, options: &Options) -> i32 {
+        self.executor.max_tokens_allowed(options)
+    }
+
+    fn answer_prefix(&self, prompt: &Prompt) -> Option<String> {
+        self.executor.answer_prefix(prompt)
+    }
+
+    fn get_tokenizer(&self, options: &Options) -> Result<Self::StepTokenizer<'_>, TokenizerError> {
+        self.executor.get_tokenizer(options)
+    }
+
+    fn configured_model(&self) -> Option<String> {
+        self.executor.configured_model()
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ExecutorError>
+    where
+        Self: Sync,
+    {
+        self.executor.list_models().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_by_moderator_or_threshold() {
+        let moderation = |flagged| Moderation {
+            flagged,
+            category_scores: vec![("hate".to_string(), 0.2), ("violence".to_string(), 0.6)],
+        };
+        assert_eq!(flagged_categories(&moderation(false), None), None);
+        assert_eq!(flagged_categories(&moderation(true), None), Some(vec![]));
+        assert_eq!(
+            flagged_categories(&moderation(false), Some(0.5)),
+            Some(vec!["violence".to_string()])
+        );
+        assert_eq!(flagged_categories(&moderation(false), Some(0.9)), None);
+    }
+}
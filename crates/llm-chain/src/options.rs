@@ -327,6 +327,26 @@ impl TokenBias {
     }
 }
 
+/// How the idempotency key attached to a request is chosen.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum IdempotencyKey {
+    /// Derive the key from a hash of the request, so resubmitting the same request reuses the same key.
+    FromRequest,
+    /// Use the key supplied by the caller.
+    Fixed(String),
+}
+
+/// The format the model is asked to respond in.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ResponseFormat {
+    /// Free-form text. This is what models produce by default.
+    Text,
+    /// A JSON object.
+    JsonObject,
+    /// A JSON object matching the given JSON schema, e.g. one produced by `Describe::json_schema`.
+    JsonSchema(serde_json::Value),
+}
+
 #[derive(EnumDiscriminants, Clone, Debug, Serialize, Deserialize)]
 pub enum Opt {
     /// The name or path of the model used.
@@ -394,6 +414,17 @@ pub enum Opt {
     User(String),
     /// The type of the model.
     ModelType(String),
+    /// An idempotency key sent along with each request, letting the provider deduplicate retries.
+    /// This is used by llm-chain-openai.
+    IdempotencyKey(IdempotencyKey),
+    /// The format the model should respond in.
+    /// This is used by llm-chain-openai, which sends it as the `response_format` parameter and checks
+    /// that non-streamed responses are JSON. Streamed responses are not checked.
+    ResponseFormat(ResponseFormat),
+    /// The seed for the random number generator used when sampling tokens, making output reproducible.
+    /// Determinism is best-effort: it can still vary between model versions, hardware and thread counts.
+    /// This is used by llm-chain-llama, llm-chain-local and llm-chain-openai.
+    Seed(u64),
 }
 
 // Helper function to extract environment variables
@@ -31,7 +31,7 @@ impl Output {
     pub async fn to_immediate(self) -> Result<Immediate, ExecutorError> {
         match self {
             Output::Immediate(x) => Ok(x),
-            Output::Stream(x) => Ok(Immediate(x.into_data().await?)),
+            Output::Stream(x) => Ok(Immediate::new(x.into_data().await?)),
         }
     }
 
@@ -60,29 +60,61 @@ impl Output {
 
     /// Creates a new `Immediate` output from the given data.
     pub fn new_immediate(data: Data<String>) -> Self {
-        Output::Immediate(Immediate(data))
+        Output::Immediate(Immediate::new(data))
+    }
+
+    /// Records the fingerprint of the backend configuration that produced an `Immediate` output.
+    ///
+    /// Streams are returned unchanged.
+    pub fn with_system_fingerprint(self, system_fingerprint: Option<String>) -> Self {
+        match self {
+            Output::Immediate(immediate) => Output::Immediate(Immediate {
+                system_fingerprint,
+                ..immediate
+            }),
+            stream => stream,
+        }
     }
 }
 
 impl fmt::Display for Output {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Output::Immediate(Immediate(data)) => data.fmt(f),
+            Output::Immediate(immediate) => immediate.fmt(f),
             Output::Stream(_) => write!(f, "<OutputStream>"),
         }
     }
 }
 
-pub struct Immediate(Data<String>);
+pub struct Immediate {
+    data: Data<String>,
+    system_fingerprint: Option<String>,
+}
 
 impl Immediate {
+    fn new(data: Data<String>) -> Self {
+        Self {
+            data,
+            system_fingerprint: None,
+        }
+    }
+
     /// Returns a reference to the content if it is immediately available.
     pub fn get_content(&self) -> &Data<String> {
-        &self.0
+        &self.data
     }
 
     pub fn as_content(self) -> Data<String> {
-        self.0
+        self.data
+    }
+
+    /// Returns the fingerprint of the backend configuration that produced the output, if the
+    /// provider reported one.
+    ///
+    /// Completions made with the same seed are only expected to match while the fingerprint is the
+    /// same. OpenAI reports it for non-streamed completions.
+    pub fn system_fingerprint(&self) -> Option<&str> {
+        self.system_fingerprint.as_deref()
     }
 
     pub fn primary_textual_output(&self) -> Option<String> {
@@ -92,6 +124,6 @@ impl Immediate {
 
 impl fmt::Display for Immediate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+        self.data.fmt(f)
     }
 }
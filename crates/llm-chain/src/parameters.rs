@@ -2,10 +2,12 @@
 //!
 //! Parameters are used to pass data between steps of the chain. They are used to fill in the prompt template, and are also filled in by the output of the previous step. Parameters have a special key, `text`, which is used as a default key for simple use cases.
 
+use serde::Serialize;
 use std::{
     collections::{BTreeMap, HashMap},
     fmt::Debug,
 };
+use thiserror::Error;
 
 type Map = BTreeMap<String, Box<dyn ParamFull>>;
 
@@ -92,6 +94,15 @@ impl Param for StringParam {
 
 const TEXT_KEY: &str = "text";
 
+/// An error that can occur when building parameters from a struct.
+#[derive(Debug, Error)]
+pub enum ParametersError {
+    #[error("Unable to serialize parameters: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Parameters must serialize to a map of named fields")]
+    NotAStruct,
+}
+
 impl Parameters {
     /// Creates a new empty set of parameters.
     pub fn new() -> Parameters {
@@ -134,6 +145,41 @@ impl Parameters {
         }
         copy
     }
+    /// Creates a set of parameters from the named fields of a struct.
+    ///
+    /// String fields are used as is, other fields are rendered as JSON. Combined with
+    /// `PromptTemplate::format_struct` this catches misspelled keys that `parameters!` would let through.
+    ///
+    /// # Examples
+    /// ```
+    /// use llm_chain::Parameters;
+    /// #[derive(serde::Serialize)]
+    /// struct Greeting {
+    ///     name: String,
+    ///     times: u32,
+    /// }
+    /// let p = Parameters::from_struct(&Greeting { name: "World".into(), times: 2 }).unwrap();
+    /// assert_eq!(p.get("name").unwrap(), "World");
+    /// assert_eq!(p.get("times").unwrap(), "2");
+    /// ```
+    pub fn from_struct<T: Serialize>(value: &T) -> Result<Parameters, ParametersError> {
+        let serde_json::Value::Object(fields) = serde_json::to_value(value)? else {
+            return Err(ParametersError::NotAStruct);
+        };
+        Ok(Parameters::from_seq(fields.into_iter().map(|(k, v)| {
+            let v = match v {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+            (k, v)
+        })))
+    }
+
+    /// Returns the keys of the parameters, in sorted order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.map.keys().map(|k| k.as_str())
+    }
+
     /// Returns the value of the given key, or `None` if the key does not exist.
     pub fn get(&self, key: &str) -> Option<String> {
         self.map.get(key).map(|param| param.get())
@@ -37,6 +37,14 @@ enum ExtractionErrorImpl {
     /// No YAML content was found to parse.
     #[error("The string to parse was empty")]
     NoneFound,
+
+    /// An error occurred while parsing JSON content.
+    #[error("JSON parsing failed with: {0}")]
+    JsonParseError(#[from] serde_json::Error),
+
+    /// The text contained no balanced JSON object.
+    #[error("No JSON object was found")]
+    NoJsonFound,
 }
 
 impl ExtractionErrorImpl {
@@ -177,6 +185,123 @@ pub fn find_yaml<T: DeserializeOwned>(text: &str) -> Result<Vec<T>, ExtractionEr
     }
 }
 
+/// Finds the first JSON object in a string and deserializes it into the specified type.
+///
+/// Models often wrap JSON in prose or a code block. If the text contains a code block, the object
+/// is looked for in the first one, otherwise in the whole text. The first balanced object is parsed,
+/// so text before and after it is ignored.
+///
+/// With `lenient`, common mistakes are repaired before parsing: single-quoted strings are
+/// rewritten with double quotes, and trailing commas before `}` or `]` are removed.
+///
+/// # Examples
+///
+/// ```
+/// #[derive(serde::Deserialize)]
+/// struct Dummy {
+///    hello: String
+/// }
+/// use llm_chain::parsing::find_json;
+/// let data = "Sure! Here it is: {'hello': 'world',} Let me know if you need anything else.";
+/// assert!(find_json::<Dummy>(data, false).is_err());
+/// let data: Dummy = find_json(data, true).unwrap();
+/// assert_eq!(data.hello, "world");
+/// ```
+pub fn find_json<T: DeserializeOwned>(text: &str, lenient: bool) -> Result<T, ExtractionError> {
+    let text = first_code_block(text).unwrap_or(text);
+    let json = first_json_object(text, lenient).ok_or(ExtractionErrorImpl::NoJsonFound)?;
+    let parsed = if lenient {
+        serde_json::from_str(&repair_json(json))
+    } else {
+        serde_json::from_str(json)
+    };
+    Ok(parsed.map_err(ExtractionErrorImpl::JsonParseError)?)
+}
+
+/// Returns the contents of the first fenced code block in `text`, if it has one.
+fn first_code_block(text: &str) -> Option<&str> {
+    let after_fence = &text[text.find("```")? + 3..];
+    let contents = &after_fence[after_fence.find('\n')? + 1..];
+    Some(&contents[..contents.find("```").unwrap_or(contents.len())])
+}
+
+/// Returns the first object in `text` whose braces are balanced, ignoring braces in strings.
+///
+/// With `lenient`, single quotes delimit strings too.
+fn first_json_object(text: &str, lenient: bool) -> Option<&str> {
+    let start = text.find('{')?;
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (idx, c) in text[start..].char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' => quote = Some(c),
+            '\'' if lenient => quote = Some(c),
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[start..=start + idx]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Rewrites single-quoted strings with double quotes and removes trailing commas.
+fn repair_json(json: &str) -> String {
+    let chars: Vec<char> = json.chars().collect();
+    let mut repaired = String::with_capacity(json.len());
+    let mut quote = None;
+    let mut escaped = false;
+    for (idx, &c) in chars.iter().enumerate() {
+        let Some(q) = quote else {
+            match c {
+                '"' | '\'' => {
+                    quote = Some(c);
+                    repaired.push('"');
+                }
+                ',' if matches!(
+                    chars[idx + 1..].iter().find(|c| !c.is_whitespace()),
+                    Some('}' | ']')
+                ) => {}
+                _ => repaired.push(c),
+            }
+            continue;
+        };
+        if escaped {
+            escaped = false;
+            repaired.push(c);
+        } else if c == '\\' {
+            escaped = true;
+            // `\'` is not a valid escape in JSON, and needs none in a double-quoted string.
+            if !(q == '\'' && chars.get(idx + 1) == Some(&'\'')) {
+                repaired.push(c);
+            }
+        } else if c == q {
+            quote = None;
+            repaired.push('"');
+        } else if c == '"' {
+            repaired.push_str("\\\"");
+        } else {
+            repaired.push(c);
+        }
+    }
+    repaired
+}
+
 /// Extracts labeled text from markdown
 ///
 /// LLMs often generate text that looks something like this
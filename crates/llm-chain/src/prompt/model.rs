@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 
+use std::collections::BTreeSet;
 use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -140,6 +141,7 @@ impl<T> From<ChatMessage<T>> for Data<T> {
 
 use crate::frame::FormatAndExecuteError;
 use crate::output::Output;
+use crate::prompt::string_template::{check_parameters, invalid_parameters};
 use crate::prompt::{StringTemplate, StringTemplateError};
 use crate::step::Step;
 use crate::traits::Executor;
@@ -170,4 +172,37 @@ impl Data<StringTemplate> {
     pub fn format(&self, parameters: &Parameters) -> Result<Data<String>, StringTemplateError> {
         self.try_map(|x| x.format(parameters))
     }
+
+    /// Formats the template, failing if the parameters don't match the template's placeholders.
+    ///
+    /// Unlike `format`, a parameter only has to be used by one of the messages.
+    pub fn format_strict(
+        &self,
+        parameters: &Parameters,
+    ) -> Result<Data<String>, StringTemplateError> {
+        check_parameters(&self.placeholders(), parameters)?;
+        self.format(parameters)
+    }
+
+    /// Formats the template with the named fields of `value`, see `Parameters::from_struct`.
+    ///
+    /// Missing and unexpected fields are reported as with `format_strict`.
+    pub fn format_struct<S: Serialize>(
+        &self,
+        value: &S,
+    ) -> Result<Data<String>, StringTemplateError> {
+        let parameters = Parameters::from_struct(value).map_err(invalid_parameters)?;
+        self.format_strict(&parameters)
+    }
+
+    /// Returns the names of the variables used by the template, in sorted order.
+    pub fn placeholders(&self) -> BTreeSet<String> {
+        match self {
+            Self::Chat(chat) => chat
+                .iter()
+                .flat_map(|msg| msg.body().placeholders())
+                .collect(),
+            Self::Text(text) => text.placeholders(),
+        }
+    }
 }
@@ -8,6 +8,13 @@ pub enum StringTemplateErrorImpl {
     UnableToLoadFile(String),
     #[error("Unable to parse template: {0}")]
     LegacyTemplateError(String),
+    #[error("Parameters don't match the template. Missing: {missing:?}, unexpected: {extra:?}")]
+    MismatchedParameters {
+        missing: Vec<String>,
+        extra: Vec<String>,
+    },
+    #[error("Invalid parameters: {0}")]
+    InvalidParameters(String),
 }
 
 impl From<std::io::Error> for StringTemplateErrorImpl {
@@ -9,6 +9,7 @@ mod io;
 use serde::{Deserialize, Serialize};
 
 use crate::Parameters;
+use std::collections::BTreeSet;
 
 /// A template for a prompt. This is a string that can be formatted with a set of parameters.
 ///
@@ -52,6 +53,35 @@ impl StringTemplate {
     pub fn format(&self, parameters: &Parameters) -> Result<String, error::StringTemplateError> {
         self.0.format(parameters).map_err(|e| e.into())
     }
+    /// Format the template with the given parameters, failing if they don't match the template's placeholders.
+    ///
+    /// The error lists both the placeholders with no parameter and the parameters the template doesn't use.
+    /// # Examples
+    /// ```
+    /// use llm_chain::prompt::StringTemplate;
+    /// use llm_chain::parameters;
+    /// let template = StringTemplate::tera("Hello {{ name }}!");
+    /// assert!(template.format_strict(&parameters!("nmae" => "World")).is_err());
+    /// assert_eq!(template.format_strict(&parameters!("name" => "World")).unwrap(), "Hello World!");
+    /// ```
+    pub fn format_strict(
+        &self,
+        parameters: &Parameters,
+    ) -> Result<String, error::StringTemplateError> {
+        check_parameters(&self.placeholders(), parameters)?;
+        self.format(parameters)
+    }
+
+    /// Returns the names of the variables used by the template, in sorted order.
+    ///
+    /// Only variables printed with `{{ ... }}` are detected, variables introduced by tera tags
+    /// such as `{% for %}` are not.
+    pub fn placeholders(&self) -> BTreeSet<String> {
+        let mut placeholders = BTreeSet::new();
+        self.0.collect_placeholders(&mut placeholders);
+        placeholders
+    }
+
     /// Creates a non-dynmamic prompt template, useful for untrusted inputs.
     pub fn static_string<K: Into<String>>(template: K) -> StringTemplate {
         StringTemplateImpl::static_string(template.into()).into()
@@ -130,6 +160,31 @@ impl StringTemplateImpl {
         }
     }
 
+    fn collect_placeholders(&self, placeholders: &mut BTreeSet<String>) {
+        match self {
+            Self::Static(_) => {}
+            Self::Tera(template) => {
+                let mut rest = template.as_str();
+                while let Some(start) = rest.find("{{") {
+                    rest = &rest[start + 2..];
+                    let name: String = rest
+                        .trim_start()
+                        .chars()
+                        .take_while(|c| c.is_alphanumeric() || *c == '_')
+                        .collect();
+                    if !name.is_empty() {
+                        placeholders.insert(name);
+                    }
+                }
+            }
+            Self::Combined(templates) => {
+                for template in templates {
+                    template.collect_placeholders(placeholders);
+                }
+            }
+        }
+    }
+
     pub fn static_string(template: String) -> Self {
         Self::Static(template)
     }
@@ -158,6 +213,25 @@ impl fmt::Display for StringTemplateImpl {
     }
 }
 
+/// Fails if `parameters` don't have exactly the keys in `placeholders`.
+pub(crate) fn check_parameters(
+    placeholders: &BTreeSet<String>,
+    parameters: &Parameters,
+) -> Result<(), StringTemplateError> {
+    let keys: BTreeSet<String> = parameters.keys().map(|k| k.to_string()).collect();
+    let missing: Vec<String> = placeholders.difference(&keys).cloned().collect();
+    let extra: Vec<String> = keys.difference(placeholders).cloned().collect();
+    if missing.is_empty() && extra.is_empty() {
+        Ok(())
+    } else {
+        Err(StringTemplateErrorImpl::MismatchedParameters { missing, extra }.into())
+    }
+}
+
+pub(crate) fn invalid_parameters<E: std::error::Error>(error: E) -> StringTemplateError {
+    StringTemplateErrorImpl::InvalidParameters(error.to_string()).into()
+}
+
 impl From<&str> for StringTemplate {
     fn from(template: &str) -> Self {
         Self::tera(template.to_string())
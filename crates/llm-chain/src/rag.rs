@@ -0,0 +1,274 @@
+//! Assembles retrieval-augmented generation pipelines from existing components.
+//!
+//! A [`RagPipelineBuilder`] loads documents, splits them into chunks, adds them to a vector store
+//! and wraps the store in a [`VectorStoreTool`], ready to be given to an agent.
+//!
+//! There is no separate embedding step: vector stores are created with the embeddings they use, so
+//! the documents are embedded by the store when they are added to it.
+
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{
+    agents::self_ask_with_search::{Agent, EarlyStoppingConfig},
+    schema::{Document, EmptyMetadata},
+    tokens::{Tokenizer, TokenizerError},
+    tools::{tools::VectorStoreTool, ToolCollection},
+    traits::{Embeddings, Executor, VectorStore},
+};
+
+#[derive(Debug, Error)]
+pub enum RagPipelineError<V> {
+    #[error("Unable to load documents: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Tokenizer(#[from] TokenizerError),
+    #[error(transparent)]
+    VectorStore(V),
+}
+
+struct Splitter {
+    tokenizer: Box<dyn Tokenizer + Send + Sync>,
+    max_tokens_per_chunk: usize,
+    chunk_overlap: usize,
+}
+
+/// Builds a [`VectorStoreTool`] over a set of documents.
+///
+/// # Example
+///
+/// ```no_run
+/// # use llm_chain::{rag::RagPipelineBuilder, tokens::Tokenizer, traits::{Embeddings, VectorStore}};
+/// # async fn run<E, V>(tokenizer: impl Tokenizer + Send + Sync + 'static, store: V)
+/// # where
+/// #     E: Embeddings + Send + Sync,
+/// #     V: VectorStore<E> + Send + Sync + 'static,
+/// # {
+/// let tool = RagPipelineBuilder::new()
+///     .load_dir("docs")
+///     .unwrap()
+///     .split(tokenizer, 256, 32)
+///     .build_tool(store, "the user manual", "how to use the product")
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub struct RagPipelineBuilder<M = EmptyMetadata>
+where
+    M: Serialize + DeserializeOwned,
+{
+    documents: Vec<Document<M>>,
+    splitter: Option<Splitter>,
+}
+
+impl<M> Default for RagPipelineBuilder<M>
+where
+    M: Serialize + DeserializeOwned + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M> RagPipelineBuilder<M>
+where
+    M: Serialize + DeserializeOwned + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            documents: vec![],
+            splitter: None,
+        }
+    }
+
+    /// Loads every UTF-8 text file in `path` and its subdirectories as a document.
+    ///
+    /// Files are loaded in path order. Files that aren't valid UTF-8 are skipped.
+    pub fn load_dir(mut self, path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut files = vec![];
+        collect_files(path.as_ref(), &mut files)?;
+        files.sort();
+        for file in files {
+            match std::fs::read_to_string(&file) {
+                Ok(text) => self.documents.push(Document::new(text)),
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self)
+    }
+
+    /// Adds documents that were loaded elsewhere.
+    pub fn load_documents(mut self, documents: impl IntoIterator<Item = Document<M>>) -> Self {
+        self.documents.extend(documents);
+        self
+    }
+
+    /// Splits the text of each document into chunks of at most `max_tokens_per_chunk` tokens,
+    /// overlapping by `chunk_overlap` tokens. Chunks keep the metadata of their document.
+    ///
+    /// Without a splitter, every document is stored whole.
+    pub fn split<K>(
+        mut self,
+        tokenizer: K,
+        max_tokens_per_chunk: usize,
+        chunk_overlap: usize,
+    ) -> Self
+    where
+        K: Tokenizer + Send + Sync + 'static,
+    {
+        self.splitter = Some(Splitter {
+            tokenizer: Box::new(tokenizer),
+            max_tokens_per_chunk,
+            chunk_overlap,
+        });
+        self
+    }
+
+    /// Returns the documents as they will be added to the store, split into chunks.
+    ///
+    /// Documents with binary content, such as images, are never split.
+    fn into_chunks(self) -> Result<Vec<Document<M>>, TokenizerError> {
+        let Some(splitter) = self.splitter else {
+            return Ok(self.documents);
+        };
+        let mut chunks = vec![];
+        for document in self.documents {
+            if document.binary_content.is_some() {
+                chunks.push(document);
+                continue;
+            }
+            for chunk in splitter.tokenizer.split_text(
+                &document.page_content,
+                splitter.max_tokens_per_chunk,
+                splitter.chunk_overlap,
+            )? {
+                let mut chunk = Document::new(chunk);
+                chunk.metadata = document.metadata.clone();
+                chunk.expires_at = document.expires_at;
+                chunks.push(chunk);
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Adds the documents to `store` and returns a tool that searches it.
+    ///
+    /// `topic` and `topic_context` tell the model what the documents are about, see
+    /// [`VectorStoreTool::new`].
+    pub async fn build_tool<E, V>(
+        self,
+        store: V,
+        topic: &str,
+        topic_context: &str,
+    ) -> Result<VectorStoreTool<E, M, V>, RagPipelineError<V::Error>>
+    where
+        E: Embeddings,
+        V: VectorStore<E, M>,
+    {
+        let chunks = self.into_chunks()?;
+        if !chunks.is_empty() {
+            store
+                .add_documents(chunks)
+                .await
+                .map_err(RagPipelineError::VectorStore)?;
+        }
+        Ok(VectorStoreTool::new(store, topic, topic_context))
+    }
+
+    /// Like [`RagPipelineBuilder::build_tool`], but returns a collection holding the tool.
+    pub async fn build_tool_collection<E, V>(
+        self,
+        store: V,
+        topic: &str,
+        topic_context: &str,
+    ) -> Result<ToolCollection<VectorStoreTool<E, M, V>>, RagPipelineError<V::Error>>
+    where
+        E: Embeddings + Send + Sync + 'static,
+        V: VectorStore<E, M> + Send + Sync + 'static,
+        M: Send + Sync + 'static,
+    {
+        let mut tools = ToolCollection::new();
+        tools.add_tool(self.build_tool(store, topic, topic_context).await?);
+        Ok(tools)
+    }
+
+    /// Like [`RagPipelineBuilder::build_tool`], but returns an agent that answers questions by
+    /// searching the documents.
+    pub async fn build_agent<X, E, V>(
+        self,
+        executor: X,
+        early_stopping_config: EarlyStoppingConfig,
+        store: V,
+        topic: &str,
+        topic_context: &str,
+    ) -> Result<Agent<X, VectorStoreTool<E, M, V>>, RagPipelineError<V::Error>>
+    where
+        X: Executor,
+        E: Embeddings + Send + Sync + 'static,
+        V: VectorStore<E, M> + Send + Sync + 'static,
+        M: Send + Sync + 'static,
+    {
+        let tool = self.build_tool(store, topic, topic_context).await?;
+        Ok(Agent::new(executor, tool, early_stopping_config))
+    }
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::TokenCollection;
+
+    /// Uses one token per character.
+    struct CharTokenizer;
+
+    impl Tokenizer for CharTokenizer {
+        fn tokenize_str(&self, doc: &str) -> Result<TokenCollection, TokenizerError> {
+            Ok(doc.chars().map(|c| c as usize).collect::<Vec<_>>().into())
+        }
+
+        fn to_string(&self, tokens: TokenCollection) -> Result<String, TokenizerError> {
+            tokens
+                .as_usize()?
+                .into_iter()
+                .map(|t| char::from_u32(t as u32).ok_or(TokenizerError::ToStringError))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_loads_and_splits_documents() {
+        let dir = std::env::temp_dir().join(format!("llm-chain-rag-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("a.txt"), "abcdef").unwrap();
+        std::fs::write(dir.join("nested/b.txt"), "xy").unwrap();
+        std::fs::write(dir.join("c.bin"), [0xff, 0xfe]).unwrap();
+
+        let builder = RagPipelineBuilder::<EmptyMetadata>::new()
+            .load_dir(&dir)
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let chunks: Vec<String> = builder
+            .split(CharTokenizer, 4, 2)
+            .into_chunks()
+            .unwrap()
+            .into_iter()
+            .map(|chunk| chunk.page_content)
+            .collect();
+        assert_eq!(chunks, vec!["abcd", "cdef", "ef", "xy"]);
+    }
+}
@@ -0,0 +1,97 @@
+//! [`Reranker`] implementations.
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::schema::Document;
+use crate::traits::{Reranker, RerankerError};
+
+/// Reranks documents with a cross-encoder served over HTTP.
+///
+/// The server must implement the `/rerank` endpoint of Hugging Face's
+/// [text-embeddings-inference](https://github.com/huggingface/text-embeddings-inference), which can
+/// serve cross-encoders such as `BAAI/bge-reranker-base` locally.
+pub struct CrossEncoderReranker {
+    base_url: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+}
+
+impl CrossEncoderReranker {
+    /// Creates a reranker for the server at `base_url`, e.g. `http://localhost:8080`.
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Sends `api_key` as a bearer token, for servers that require one.
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+}
+
+#[derive(Serialize)]
+struct RerankRequest<'a> {
+    query: &'a str,
+    texts: Vec<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct RerankScore {
+    index: usize,
+    score: f32,
+}
+
+#[derive(Debug, Error)]
+pub enum CrossEncoderRerankerError {
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error("The reranker returned an unknown or repeated document index: {0}")]
+    InvalidIndex(usize),
+}
+
+impl RerankerError for CrossEncoderRerankerError {}
+
+#[async_trait]
+impl Reranker for CrossEncoderReranker {
+    type Error = CrossEncoderRerankerError;
+
+    async fn rerank<M: Serialize + DeserializeOwned + Send + 'static>(
+        &self,
+        query: &str,
+        documents: Vec<Document<M>>,
+    ) -> Result<Vec<(Document<M>, f32)>, Self::Error> {
+        if documents.is_empty() {
+            return Ok(vec![]);
+        }
+        let body = RerankRequest {
+            query,
+            texts: documents.iter().map(|d| d.page_content.as_str()).collect(),
+        };
+        let mut request = self
+            .client
+            .post(format!("{}/rerank", self.base_url))
+            .json(&body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let scores: Vec<RerankScore> = request.send().await?.error_for_status()?.json().await?;
+
+        let mut documents: Vec<Option<Document<M>>> = documents.into_iter().map(Some).collect();
+        let mut reranked = Vec::with_capacity(scores.len());
+        for RerankScore { index, score } in scores {
+            let document = documents
+                .get_mut(index)
+                .and_then(Option::take)
+                .ok_or(CrossEncoderRerankerError::InvalidIndex(index))?;
+            reranked.push((document, score));
+        }
+        reranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(reranked)
+    }
+}
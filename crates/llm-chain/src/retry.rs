@@ -0,0 +1,75 @@
+//! Limits on how often failed calls are retried.
+
+use std::future::Future;
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+/// A number of retries shared by every executor and tool call in a run.
+///
+/// Each retry takes one from the budget. Once it is used up, failures are returned immediately.
+/// Clones share the same budget, so a single budget can cap the total number of retries across
+/// everything a run does.
+#[derive(Clone, Debug)]
+pub struct RetryBudget {
+    remaining: Arc<AtomicU32>,
+}
+
+impl RetryBudget {
+    /// Creates a budget that allows `max_retries` retries in total.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            remaining: Arc::new(AtomicU32::new(max_retries)),
+        }
+    }
+
+    /// The number of retries left.
+    pub fn remaining(&self) -> u32 {
+        self.remaining.load(Ordering::SeqCst)
+    }
+
+    /// Takes one retry from the budget, returning false if it is used up.
+    pub fn try_acquire(&self) -> bool {
+        self.remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    /// Calls `f` until it succeeds or the budget is used up, returning the last error in that case.
+    pub async fn retry<F, Fut, T, E>(&self, mut f: F) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        loop {
+            match f().await {
+                Ok(output) => return Ok(output),
+                Err(e) if !self.try_acquire() => return Err(e),
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_budget_is_shared_between_clones() {
+        let budget = RetryBudget::new(3);
+        let clone = budget.clone();
+        let mut calls = 0;
+        let res: Result<(), ()> = budget
+            .retry(|| {
+                calls += 1;
+                async { Err(()) }
+            })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(calls, 4);
+        assert_eq!(clone.remaining(), 0);
+        assert!(!clone.try_acquire());
+    }
+}
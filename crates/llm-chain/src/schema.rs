@@ -2,13 +2,28 @@
 //!
 //! This schema is used to store documents in vector stores. It is used to store the document's content and metadata.
 
+use std::{path::PathBuf, sync::Arc};
+
+use chrono::{DateTime, Utc};
+
+/// Returns the current time, used by document stores to decide which documents have expired.
+pub type ExpiryClock = Arc<dyn Fn() -> DateTime<Utc> + Send + Sync>;
+
+/// A document to store and search, built with [`Document::new`] or [`Document::for_image`].
+///
+/// Fields may be added in later versions, so documents can't be built with a struct literal.
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct Document<M = EmptyMetadata>
 where
     M: serde::Serialize + serde::de::DeserializeOwned,
 {
     pub page_content: String,
     pub metadata: Option<M>,
+    /// The raw bytes of a non-text document, such as an image. `page_content` then holds its caption, if any.
+    pub binary_content: Option<Vec<u8>>,
+    /// When the document expires. Searches leave out expired documents, and `purge_expired` deletes them.
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 impl<M> Document<M>
@@ -19,11 +34,57 @@ where
         Document {
             page_content,
             metadata: None,
+            binary_content: None,
+            expires_at: None,
         }
     }
+
+    /// Creates a document for an image, with an optional caption as its text content.
+    pub fn for_image(bytes: Vec<u8>, caption: String) -> Self {
+        Document {
+            page_content: caption,
+            metadata: None,
+            binary_content: Some(bytes),
+            expires_at: None,
+        }
+    }
+
+    /// Attaches `metadata` to the document.
+    pub fn with_metadata(mut self, metadata: M) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Makes the document expire at `expires_at`, e.g. for news or cached content.
+    pub fn with_expires_at(mut self, expires_at: DateTime<Utc>) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Whether the document has expired at `now`.
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
 }
 
-#[derive(Debug)]
+/// An image to embed, either stored in a file or given as raw bytes.
+#[derive(Debug, Clone)]
+pub enum Image {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+impl Image {
+    /// Returns the raw bytes of the image, reading it from disk if needed.
+    pub async fn read(&self) -> Result<Vec<u8>, std::io::Error> {
+        match self {
+            Image::Path(path) => tokio::fs::read(path).await,
+            Image::Bytes(bytes) => Ok(bytes.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct EmptyMetadata;
 
 impl From<()> for EmptyMetadata {
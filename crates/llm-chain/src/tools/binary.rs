@@ -0,0 +1,106 @@
+use std::fmt;
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use super::description::FormatPart;
+
+/// The largest payload [`Base64`] decodes by default, in bytes.
+pub const DEFAULT_MAX_DECODED_BYTES: usize = 10 * 1024 * 1024;
+
+/// Binary data passed to or from a tool as a base64-encoded string.
+///
+/// Tools exchange YAML with the model, so bytes such as images or files are encoded as base64.
+/// Use `Base64` as a field in a tool's input or output to decode and encode it automatically.
+///
+/// # Example
+///
+/// ```
+/// use llm_chain::tools::Base64;
+///
+/// let data: Base64 = "aGVsbG8=".parse().unwrap();
+/// assert_eq!(data.0, b"hello");
+/// assert_eq!(data.to_string(), "aGVsbG8=");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Base64(pub Vec<u8>);
+
+#[derive(Error, Debug)]
+pub enum Base64Error {
+    #[error("Base64 payload of {size} bytes exceeds the limit of {limit} bytes")]
+    TooLarge { size: usize, limit: usize },
+    #[error("Invalid base64: {0}")]
+    Decode(#[from] base64::DecodeError),
+}
+
+impl Base64 {
+    /// Decodes a base64 string, refusing payloads larger than [`DEFAULT_MAX_DECODED_BYTES`].
+    pub fn decode(encoded: &str) -> Result<Self, Base64Error> {
+        Self::decode_with_limit(encoded, DEFAULT_MAX_DECODED_BYTES)
+    }
+
+    /// Decodes a base64 string, refusing payloads that decode to more than `limit` bytes.
+    ///
+    /// The size is checked before decoding, so oversized payloads are rejected without allocating
+    /// memory for them.
+    pub fn decode_with_limit(encoded: &str, limit: usize) -> Result<Self, Base64Error> {
+        let encoded = encoded.trim();
+        let size = encoded.trim_end_matches('=').len() * 3 / 4;
+        if size > limit {
+            return Err(Base64Error::TooLarge { size, limit });
+        }
+        Ok(Self(STANDARD.decode(encoded)?))
+    }
+
+    /// Encodes the bytes as a base64 string.
+    pub fn encode(&self) -> String {
+        STANDARD.encode(&self.0)
+    }
+
+    /// Creates a `FormatPart` that tells the model the field holds base64-encoded binary data.
+    pub fn format_part(key: &str, purpose: &str) -> FormatPart {
+        FormatPart::new(key, &format!("{} (base64-encoded binary data)", purpose))
+    }
+}
+
+impl From<Vec<u8>> for Base64 {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl fmt::Display for Base64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.encode())
+    }
+}
+
+impl FromStr for Base64 {
+    type Err = Base64Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::decode(s)
+    }
+}
+
+impl Serialize for Base64 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.encode())
+    }
+}
+
+impl<'de> Deserialize<'de> for Base64 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let encoded = String::deserialize(deserializer)?;
+        Self::decode(&encoded).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,325 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use super::description::ToolDescription;
+use super::tool::{Tool, ToolError};
+
+/// What [`Bounded`] does with output that exceeds its limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowStrategy {
+    /// Cut the output down to the limit and note that it was truncated.
+    #[default]
+    Truncate,
+    /// Fail with [`BoundedError::OutputTooLarge`].
+    Error,
+}
+
+/// Sizes of the inputs and outputs a [`Bounded`] tool has seen, in bytes of YAML.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToolSizeMetrics {
+    pub calls: u64,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    pub largest_output_bytes: u64,
+    pub truncated_outputs: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    calls: AtomicU64,
+    input_bytes: AtomicU64,
+    output_bytes: AtomicU64,
+    largest_output_bytes: AtomicU64,
+    truncated_outputs: AtomicU64,
+}
+
+/// Wraps a tool, limiting how much output it can return and recording the size of its inputs and
+/// outputs.
+///
+/// The limit is enforced at the tool boundary, so a runaway tool such as a `BashTool` printing a huge
+/// file can't flood whatever invoked it. Sizes are measured on the YAML passed through
+/// [`Tool::invoke`]. When a tool is invoked through [`Tool::invoke_typed`] its output can't be
+/// truncated, so exceeding the limit is always an error there, and its already parsed input isn't
+/// counted in [`ToolSizeMetrics::input_bytes`].
+///
+/// # Example
+///
+/// ```
+/// use llm_chain::tools::{tools::BashTool, Bounded, OverflowStrategy};
+///
+/// let tool = Bounded::new(BashTool::new())
+///     .with_max_output_bytes(64 * 1024)
+///     .with_overflow_strategy(OverflowStrategy::Truncate);
+/// ```
+pub struct Bounded<T> {
+    tool: T,
+    max_output_bytes: Option<usize>,
+    overflow_strategy: OverflowStrategy,
+    counters: Counters,
+}
+
+#[derive(Debug, Error)]
+pub enum BoundedError<E>
+where
+    E: std::fmt::Debug + std::error::Error + ToolError,
+{
+    #[error(transparent)]
+    ToolError(E),
+    #[error("Tool output of {size} bytes exceeds the limit of {limit} bytes")]
+    OutputTooLarge { size: usize, limit: usize },
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+}
+
+impl<E> ToolError for BoundedError<E>
+where
+    E: std::fmt::Debug + std::error::Error + ToolError,
+{
+    fn need_more_info(&self) -> Option<&str> {
+        match self {
+            BoundedError::ToolError(e) => e.need_more_info(),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Bounded<T>
+where
+    T: Tool,
+{
+    pub fn new(tool: T) -> Self {
+        Self {
+            tool,
+            max_output_bytes: None,
+            overflow_strategy: OverflowStrategy::default(),
+            counters: Counters::default(),
+        }
+    }
+
+    /// Sets the largest output, in bytes, the tool may return.
+    pub fn with_max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = Some(max_output_bytes);
+        self
+    }
+
+    /// Sets what happens to output that exceeds the limit.
+    pub fn with_overflow_strategy(mut self, overflow_strategy: OverflowStrategy) -> Self {
+        self.overflow_strategy = overflow_strategy;
+        self
+    }
+
+    /// Returns the sizes recorded so far.
+    pub fn metrics(&self) -> ToolSizeMetrics {
+        ToolSizeMetrics {
+            calls: self.counters.calls.load(Ordering::Relaxed),
+            input_bytes: self.counters.input_bytes.load(Ordering::Relaxed),
+            output_bytes: self.counters.output_bytes.load(Ordering::Relaxed),
+            largest_output_bytes: self.counters.largest_output_bytes.load(Ordering::Relaxed),
+            truncated_outputs: self.counters.truncated_outputs.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the wrapped tool.
+    pub fn into_inner(self) -> T {
+        self.tool
+    }
+
+    fn record(&self, input_bytes: usize, output_bytes: usize) {
+        self.counters.calls.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .input_bytes
+            .fetch_add(input_bytes as u64, Ordering::Relaxed);
+        self.counters
+            .output_bytes
+            .fetch_add(output_bytes as u64, Ordering::Relaxed);
+        self.counters
+            .largest_output_bytes
+            .fetch_max(output_bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Shortens `text` to at most `limit` bytes without splitting a character.
+fn truncate(text: &str, limit: usize) -> &str {
+    let mut end = limit.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[async_trait]
+impl<T> Tool for Bounded<T>
+where
+    T: Tool + Send + Sync,
+    T::Output: Send,
+{
+    type Input = T::Input;
+    type Output = T::Output;
+    type Error = BoundedError<T::Error>;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let output = self
+            .tool
+            .invoke_typed(input)
+            .await
+            .map_err(BoundedError::ToolError)?;
+        let size = serde_yaml::to_string(&output)?.len();
+        self.record(0, size);
+        match self.max_output_bytes {
+            Some(limit) if size > limit => Err(BoundedError::OutputTooLarge { size, limit }),
+            _ => Ok(output),
+        }
+    }
+
+    fn description(&self) -> ToolDescription {
+        self.tool.description()
+    }
+
+    async fn invoke(&self, input: serde_yaml::Value) -> Result<serde_yaml::Value, Self::Error> {
+        let input_bytes = serde_yaml::to_string(&input)?.len();
+        let output = self
+            .tool
+            .invoke(input)
+            .await
+            .map_err(BoundedError::ToolError)?;
+        let rendered = serde_yaml::to_string(&output)?;
+        let size = rendered.len();
+        self.record(input_bytes, size);
+
+        let limit = match self.max_output_bytes {
+            Some(limit) if size > limit => limit,
+            _ => return Ok(output),
+        };
+        match self.overflow_strategy {
+            OverflowStrategy::Error => Err(BoundedError::OutputTooLarge { size, limit }),
+            OverflowStrategy::Truncate => {
+                self.counters
+                    .truncated_outputs
+                    .fetch_add(1, Ordering::Relaxed);
+                let text = match &output {
+                    serde_yaml::Value::String(text) => text.as_str(),
+                    _ => rendered.as_str(),
+                };
+                Ok(format!(
+                    "{}\n[output truncated to {} of {} bytes]",
+                    truncate(text, limit),
+                    limit,
+                    size
+                )
+                .into())
+            }
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.tool.matches(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use thiserror::Error;
+
+    use super::{Bounded, BoundedError, OverflowStrategy, ToolSizeMetrics};
+    use crate::tools::{Format, Tool, ToolDescription, ToolError};
+
+    #[derive(Debug, Error)]
+    #[error("Mocked tool error")]
+    struct MockError;
+
+    impl ToolError for MockError {
+        fn need_more_info(&self) -> Option<&str> {
+            Some("Which file?")
+        }
+    }
+
+    impl From<serde_yaml::Error> for MockError {
+        fn from(_: serde_yaml::Error) -> Self {
+            Self
+        }
+    }
+
+    /// Repeats its input ten times, or fails if the input is empty.
+    struct RepeatTool;
+
+    #[async_trait]
+    impl Tool for RepeatTool {
+        type Input = String;
+        type Output = String;
+        type Error = MockError;
+
+        async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+            if input.is_empty() {
+                return Err(MockError);
+            }
+            Ok(input.repeat(10))
+        }
+
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "repeat",
+                "Repeats the input",
+                "Use this to repeat",
+                Format::new(vec![]),
+                Format::new(vec![]),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn test_truncates_on_char_boundary() {
+        let tool = Bounded::new(RepeatTool).with_max_output_bytes(4);
+        let output = tool.invoke("é".into()).await.unwrap();
+        // The YAML rendering of "é" repeated ten times is 20 bytes plus a newline.
+        assert_eq!(
+            output.as_str().unwrap(),
+            "éé\n[output truncated to 4 of 21 bytes]"
+        );
+
+        let tool = Bounded::new(RepeatTool).with_max_output_bytes(5);
+        let output = tool.invoke("é".into()).await.unwrap();
+        assert!(output.as_str().unwrap().starts_with("éé\n"));
+    }
+
+    #[tokio::test]
+    async fn test_errors_on_overflow() {
+        let tool = Bounded::new(RepeatTool)
+            .with_max_output_bytes(4)
+            .with_overflow_strategy(OverflowStrategy::Error);
+        let err = tool.invoke("ab".into()).await.unwrap_err();
+        assert!(matches!(
+            err,
+            BoundedError::OutputTooLarge { size: 21, limit: 4 }
+        ));
+        let err = tool.invoke_typed(&"ab".to_string()).await.unwrap_err();
+        assert!(matches!(err, BoundedError::OutputTooLarge { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_records_sizes() {
+        let tool = Bounded::new(RepeatTool).with_max_output_bytes(30);
+        tool.invoke("ab".into()).await.unwrap();
+        tool.invoke("abcd".into()).await.unwrap();
+        tool.invoke_typed(&"a".to_string()).await.unwrap();
+        assert_eq!(
+            tool.metrics(),
+            ToolSizeMetrics {
+                calls: 3,
+                input_bytes: 3 + 5,
+                output_bytes: 21 + 41 + 11,
+                largest_output_bytes: 41,
+                truncated_outputs: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forwards_need_more_info() {
+        let tool = Bounded::new(RepeatTool);
+        let err = tool.invoke_typed(&String::new()).await.unwrap_err();
+        assert_eq!(err.need_more_info(), Some("Which file?"));
+    }
+}
@@ -0,0 +1,81 @@
+//! Command-line interfaces for tools, generated from their descriptions.
+//!
+//! This is handy for trying out a tool by hand, without a model in the loop.
+
+use clap::{builder::PossibleValuesParser, Arg, ArgMatches, Command};
+use thiserror::Error;
+
+use super::description::{Format, ToolDescription};
+use super::tool::{Tool, ToolError};
+
+#[derive(Debug, Error)]
+pub enum ToolCliError<E: ToolError> {
+    #[error(transparent)]
+    InvalidArguments(#[from] clap::Error),
+    #[error(transparent)]
+    ToolError(E),
+}
+
+impl ToolDescription {
+    /// Builds a command with one required `--<key>` option per input parameter.
+    pub fn to_cli_command(&self) -> Command {
+        let mut command = Command::new(self.name.clone()).about(self.description.clone());
+        for part in &self.input_format.parts {
+            let mut arg = Arg::new(part.key.clone())
+                .long(part.key.clone())
+                .help(part.purpose.clone())
+                .required(true);
+            if let Some(values) = &part.allowed_values {
+                arg = arg.value_parser(PossibleValuesParser::new(values.clone()));
+            }
+            command = command.arg(arg);
+        }
+        command
+    }
+}
+
+impl Format {
+    /// Assembles the YAML input for a tool from arguments parsed by `ToolDescription::to_cli_command`.
+    ///
+    /// Each value is read as YAML, so `--id 23` becomes a number and `--name Abc` a string.
+    pub fn input_from_arg_matches(&self, matches: &ArgMatches) -> serde_yaml::Value {
+        let mut input = serde_yaml::Mapping::new();
+        for part in &self.parts {
+            if let Some(raw) = matches.get_one::<String>(&part.key) {
+                let value = serde_yaml::from_str(raw)
+                    .unwrap_or_else(|_| serde_yaml::Value::String(raw.clone()));
+                input.insert(part.key.clone().into(), value);
+            }
+        }
+        serde_yaml::Value::Mapping(input)
+    }
+}
+
+/// Parses `args` into the tool's input and invokes the tool.
+///
+/// The first argument is the program name, as with `std::env::args`.
+///
+/// # Example
+///
+/// ```no_run
+/// use llm_chain::tools::{cli::run_tool_cli, tools::BashTool};
+/// # async fn run() {
+/// let output = run_tool_cli(&BashTool::new(), std::env::args()).await.unwrap();
+/// println!("{}", serde_yaml::to_string(&output).unwrap());
+/// # }
+/// ```
+pub async fn run_tool_cli<T, I, S>(
+    tool: &T,
+    args: I,
+) -> Result<serde_yaml::Value, ToolCliError<T::Error>>
+where
+    T: Tool + Sync,
+    I: IntoIterator<Item = S>,
+    S: Into<String> + Clone,
+{
+    let description = tool.description();
+    let args: Vec<String> = args.into_iter().map(Into::into).collect();
+    let matches = description.to_cli_command().try_get_matches_from(args)?;
+    let input = description.input_format.input_from_arg_matches(&matches);
+    tool.invoke(input).await.map_err(ToolCliError::ToolError)
+}
@@ -1,14 +1,40 @@
+use super::description::{
+    describe_to_repair_prompt, DisallowedValue, DuplicateKey, ToolDescription,
+};
 use super::tool::{Tool, ToolError};
-use crate::parsing::{find_yaml, ExtractionError};
-use crate::prompt::StringTemplate;
+use crate::options::Options;
+use crate::parsing::{find_json, find_yaml, ExtractionError};
+use crate::prompt::{Prompt, StringTemplate};
+use crate::traits::{Executor, ExecutorError};
+use futures::FutureExt;
 use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use thiserror::Error;
 
+/// A collection of tools the model can invoke.
+///
+/// Tools are reference counted, so collections derived with [`ToolCollection::subset`] share the
+/// same tool instances as the collection they came from instead of copying them.
 #[derive(Default)]
 pub struct ToolCollection<T> {
-    tools: Vec<T>,
+    tools: Vec<Arc<T>>,
+    default_timeout: Option<Duration>,
+    timeouts: HashMap<String, Duration>,
+    catch_panics: bool,
+    call_limits: HashMap<String, u32>,
+    call_counts: Mutex<HashMap<String, u32>>,
+    lenient_json: bool,
+    input_repair_retries: u32,
 }
 
+/// The output returned instead of calling a tool that has reached its call limit.
+const CALL_LIMIT_REACHED: &str =
+    "The {name} tool has reached its limit of {limit} calls for this task. Continue without it.";
+
 #[derive(Error, Debug)]
 pub enum ToolUseError<E: ToolError> {
     #[error("Model is not trying to invoke tools")]
@@ -21,8 +47,18 @@ pub enum ToolUseError<E: ToolError> {
     InvalidYaml(#[from] ExtractionError),
     #[error("Invalid format: {0}")]
     InvalidFormat(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    DisallowedValue(#[from] DisallowedValue),
+    #[error("Invalid description of the {tool} tool: {source}")]
+    DuplicateKey { tool: String, source: DuplicateKey },
     #[error("Tool invocation failed: {0}")]
     ToolInvocationFailed(String),
+    #[error("Tool did not respond within {0:?}, try again with a simpler input")]
+    Timeout(Duration),
+    #[error("Tool crashed: {0}")]
+    ToolPanicked(String),
+    #[error("Could not ask the model to correct the tool input: {0}")]
+    InputRepairFailed(ExecutorError),
     #[error(transparent)]
     ToolError(#[from] E),
 }
@@ -32,13 +68,141 @@ where
     T: Tool + Send + Sync,
 {
     pub fn new() -> Self {
-        Self { tools: vec![] }
+        Self {
+            tools: vec![],
+            default_timeout: None,
+            timeouts: HashMap::new(),
+            catch_panics: false,
+            call_limits: HashMap::new(),
+            call_counts: Default::default(),
+            lenient_json: false,
+            input_repair_retries: 0,
+        }
+    }
+
+    /// Sets how long any tool may run before its invocation fails with [`ToolUseError::Timeout`].
+    pub fn with_default_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long the tool called `name` may run, overriding the default timeout.
+    pub fn with_tool_timeout(mut self, name: &str, timeout: Duration) -> Self {
+        self.timeouts.insert(name.to_string(), timeout);
+        self
+    }
+
+    /// Turns panics in tools into [`ToolUseError::ToolPanicked`] errors instead of letting them
+    /// unwind through the caller, so that a buggy tool can't take down an agent.
+    ///
+    /// Only panics raised while the tool's future is polled are caught. Panics in tasks the tool
+    /// spawns itself are not, and nothing is caught when the binary is built with `panic = "abort"`.
+    /// The panic hook still runs, so the panic is printed to stderr as usual.
+    ///
+    /// Tools are not required to be `UnwindSafe`, so a tool that panics halfway through updating
+    /// its own state may leave that state inconsistent, and mutexes it held are poisoned. Prefer
+    /// this for stateless tools, or tools whose state can't be broken by a panic.
+    pub fn with_catch_panics(mut self, catch_panics: bool) -> Self {
+        self.catch_panics = catch_panics;
+        self
+    }
+
+    /// Repairs single quotes and trailing commas in JSON tool invocations, see
+    /// [`crate::parsing::find_json`].
+    ///
+    /// Invocations are parsed as YAML first. If that fails, the first JSON object in the model's
+    /// output is parsed instead, which handles JSON surrounded by prose. The repairs only apply to
+    /// that fallback.
+    pub fn with_lenient_json(mut self, lenient_json: bool) -> Self {
+        self.lenient_json = lenient_json;
+        self
+    }
+
+    /// Sets how often [`ToolCollection::invoke_with_input_repair`] asks the model to correct an
+    /// input the tool can't deserialize. Defaults to 0, which fails on the first invalid input.
+    pub fn with_input_repair_retries(mut self, retries: u32) -> Self {
+        self.input_repair_retries = retries;
+        self
+    }
+
+    /// Limits how often the tool called `name` may be invoked, e.g. to cap the cost of a paid API.
+    ///
+    /// Once the limit is reached, invoking the tool returns a message saying so instead of calling
+    /// it, so the model can carry on without the tool rather than the run failing. Calls are counted
+    /// per collection, so take a fresh one for each run with [`ToolCollection::new_run`].
+    pub fn with_tool_call_limit(mut self, name: &str, max_calls: u32) -> Self {
+        self.call_limits.insert(name.to_string(), max_calls);
+        self
+    }
+
+    /// Returns how often each tool in this collection was called.
+    ///
+    /// Invocations refused by a call limit are not counted.
+    pub fn call_counts(&self) -> HashMap<String, u32> {
+        self.call_counts.lock().unwrap().clone()
+    }
+
+    /// Returns a collection for a new run, with the same tools and settings but no calls counted.
+    ///
+    /// Call limits apply to each collection on its own, so runs that start from the same collection,
+    /// one after the other or concurrently, don't use up each other's calls.
+    pub fn new_run(&self) -> Self {
+        self.with_tools(self.tools.clone())
+    }
+
+    /// Counts a call to the tool called `name`, returning false if it has reached its limit.
+    fn try_count_call(&self, name: &str) -> bool {
+        let mut counts = self.call_counts.lock().unwrap();
+        let count = counts.entry(name.to_string()).or_default();
+        if self
+            .call_limits
+            .get(name)
+            .is_some_and(|&limit| *count >= limit)
+        {
+            return false;
+        }
+        *count += 1;
+        true
     }
 
     pub fn add_tool(&mut self, tool: T) {
+        self.tools.push(Arc::new(tool));
+    }
+
+    /// Adds a tool that is shared with other collections.
+    pub fn add_shared_tool(&mut self, tool: Arc<T>) {
         self.tools.push(tool);
     }
 
+    /// Returns a new collection containing only the tools named in `allowed`.
+    ///
+    /// The tools are shared with this collection rather than cloned, so any state they hold (such as
+    /// connection pools or counters) is shared too. Calls are counted from zero, as with
+    /// [`ToolCollection::new_run`]. Names that don't match a tool are ignored.
+    pub fn subset(&self, allowed: &[&str]) -> Self {
+        let tools = self
+            .tools
+            .iter()
+            .filter(|t| allowed.iter().any(|name| t.matches(name)))
+            .cloned()
+            .collect();
+        self.with_tools(tools)
+    }
+
+    /// Returns a collection of `tools` with the settings of this one and no calls counted.
+    fn with_tools(&self, tools: Vec<Arc<T>>) -> Self {
+        Self {
+            tools,
+            default_timeout: self.default_timeout,
+            timeouts: self.timeouts.clone(),
+            catch_panics: self.catch_panics,
+            call_limits: self.call_limits.clone(),
+            call_counts: Default::default(),
+            lenient_json: self.lenient_json,
+            input_repair_retries: self.input_repair_retries,
+        }
+    }
+
     pub async fn invoke(
         &self,
         name: &str,
@@ -49,14 +213,105 @@ where
             .iter()
             .find(|t| t.matches(name))
             .ok_or(ToolUseError::ToolNotFound)?;
-        tool.invoke(input.clone()).await.map_err(|e| e.into())
+        let description = tool.description();
+        description.input_format.check_allowed_values(input)?;
+        if !self.try_count_call(&description.name) {
+            let limit = self.call_limits[&description.name];
+            return Ok(CALL_LIMIT_REACHED
+                .replace("{name}", &description.name)
+                .replace("{limit}", &limit.to_string())
+                .into());
+        }
+        let timeout = self
+            .timeouts
+            .get(&description.name)
+            .or(self.default_timeout.as_ref());
+        let call = self.call_tool(tool, input.clone());
+        match timeout {
+            Some(&timeout) => tokio::time::timeout(timeout, call)
+                .await
+                .map_err(|_| ToolUseError::Timeout(timeout))?,
+            None => call.await,
+        }
+    }
+
+    /// Like [`ToolCollection::invoke`], but asks the model to correct an input the tool can't
+    /// deserialize instead of failing.
+    ///
+    /// The input is checked with [`Tool::check_input`]. The model is only shown the tool's input
+    /// format and the invalid input, so this is much cheaper than planning the whole step again. The corrected input is checked again, up to
+    /// the number of times set with [`ToolCollection::with_input_repair_retries`], after which the
+    /// last error is returned as [`ToolUseError::InvalidFormat`].
+    pub async fn invoke_with_input_repair<X: Executor>(
+        &self,
+        executor: &X,
+        name: &str,
+        input: &serde_yaml::Value,
+    ) -> Result<serde_yaml::Value, ToolUseError<<T as Tool>::Error>> {
+        let tool = self
+            .tools
+            .iter()
+            .find(|t| t.matches(name))
+            .ok_or(ToolUseError::ToolNotFound)?;
+        let mut input = input.clone();
+        let mut repairs = 0;
+        while let Err(error) = tool.check_input(&input) {
+            if repairs >= self.input_repair_retries {
+                return Err(ToolUseError::InvalidFormat(error));
+            }
+            repairs += 1;
+            let prompt = Prompt::text(describe_to_repair_prompt(
+                &tool.description().input_format,
+                &serde_yaml::to_string(&input)?,
+            ));
+            let response = executor
+                .execute(Options::empty(), &prompt)
+                .await
+                .map_err(ToolUseError::InputRepairFailed)?
+                .to_immediate()
+                .await
+                .map_err(ToolUseError::InputRepairFailed)?
+                .as_content()
+                .extract_last_body()
+                .cloned()
+                .unwrap_or_default();
+            // An unparseable response is tried as is, so it counts as a failed repair.
+            input = repaired_input(&response).unwrap_or(serde_yaml::Value::String(response));
+        }
+        self.invoke(name, &input).await
+    }
+
+    /// Invokes `tool`, catching its panics if `with_catch_panics` is enabled.
+    async fn call_tool(
+        &self,
+        tool: &T,
+        input: serde_yaml::Value,
+    ) -> Result<serde_yaml::Value, ToolUseError<<T as Tool>::Error>> {
+        if !self.catch_panics {
+            return tool.invoke(input).await.map_err(|e| e.into());
+        }
+        AssertUnwindSafe(tool.invoke(input))
+            .catch_unwind()
+            .await
+            .map_err(|payload| ToolUseError::ToolPanicked(panic_message(&*payload)))?
+            .map_err(|e| e.into())
+    }
+
+    /// Finds the tool invocations in `data`, falling back to the first JSON object if it contains
+    /// no YAML invocations.
+    fn find_invocations(&self, data: &str) -> Result<Vec<ToolInvocationInput>, ExtractionError> {
+        find_yaml::<ToolInvocationInput>(data).or_else(|yaml_error| {
+            find_json::<ToolInvocationInput>(data, self.lenient_json)
+                .map(|invocation| vec![invocation])
+                .map_err(|_| yaml_error)
+        })
     }
 
     pub fn get_tool_invocation(
         &self,
         data: &str,
     ) -> Result<ToolInvocationInput, ToolUseError<<T as Tool>::Error>> {
-        let tool_invocations: Vec<ToolInvocationInput> = find_yaml::<ToolInvocationInput>(data)?;
+        let tool_invocations = self.find_invocations(data)?;
         if tool_invocations.len() > 1 {
             return Err(ToolUseError::MultipleInvocations);
         }
@@ -66,6 +321,80 @@ where
             .ok_or(ToolUseError::NoToolInvocation)
     }
 
+    /// Returns every tool invocation in `data`, with exact duplicates removed.
+    ///
+    /// Invocations are returned in the order they first appear.
+    pub fn get_tool_invocations(
+        &self,
+        data: &str,
+    ) -> Result<Vec<ToolInvocationInput>, ToolUseError<<T as Tool>::Error>> {
+        let tool_invocations = self.find_invocations(data)?;
+        if tool_invocations.is_empty() {
+            return Err(ToolUseError::NoToolInvocation);
+        }
+        Ok(dedup_invocations(tool_invocations))
+    }
+
+    /// Process chat input that may contain several tool invocations and execute them in order.
+    ///
+    /// Identical invocations are only executed once, and every copy gets the same output.
+    /// Returns one output per invocation found in `data`, including duplicates.
+    pub async fn process_chat_inputs(
+        &self,
+        data: &str,
+    ) -> Result<Vec<String>, ToolUseError<<T as Tool>::Error>> {
+        let tool_invocations = self.find_invocations(data)?;
+        if tool_invocations.is_empty() {
+            return Err(ToolUseError::NoToolInvocation);
+        }
+        Ok(self
+            .invoke_all(tool_invocations)
+            .await?
+            .into_iter()
+            .map(|result| result.output)
+            .collect())
+    }
+
+    /// Like `process_chat_inputs`, but pairs each output with the id of the call it answers.
+    ///
+    /// Invocations keep the `call_id` the model gave them, and get a new unique id otherwise. Put
+    /// the results in the follow up prompt so the model can tell which output belongs to which call.
+    pub async fn process_chat_inputs_with_ids(
+        &self,
+        data: &str,
+    ) -> Result<Vec<ToolCallResult>, ToolUseError<<T as Tool>::Error>> {
+        let tool_invocations = self.find_invocations(data)?;
+        if tool_invocations.is_empty() {
+            return Err(ToolUseError::NoToolInvocation);
+        }
+        self.invoke_all(tool_invocations).await
+    }
+
+    async fn invoke_all(
+        &self,
+        tool_invocations: Vec<ToolInvocationInput>,
+    ) -> Result<Vec<ToolCallResult>, ToolUseError<<T as Tool>::Error>> {
+        let unique = dedup_invocations(tool_invocations.clone());
+        let mut outputs = Vec::with_capacity(unique.len());
+        for invocation in &unique {
+            let output = self.invoke(&invocation.command, &invocation.input).await?;
+            outputs.push(serde_yaml::to_string(&output)?);
+        }
+        Ok(tool_invocations
+            .into_iter()
+            .map(|invocation| {
+                let idx = unique
+                    .iter()
+                    .position(|u| u.same_call(&invocation))
+                    .unwrap();
+                ToolCallResult {
+                    call_id: invocation.call_id.unwrap_or_else(new_call_id),
+                    output: outputs[idx].clone(),
+                }
+            })
+            .collect())
+    }
+
     /// Process chat input and execute the appropriate tool.
     ///
     /// The input string should contain a YAML block describing the tool invocation.
@@ -86,24 +415,708 @@ where
         serde_yaml::to_string(&output).map_err(|e| e.into())
     }
 
+    /// Returns the descriptions of the tools, in the order they were added.
+    pub fn descriptions(&self) -> Vec<ToolDescription> {
+        self.tools.iter().map(|t| t.description()).collect()
+    }
+
     /// Generate a YAML-formatted string describing the available tools.
+    ///
+    /// Fails with [`ToolUseError::DuplicateKey`] if a tool's input or output format repeats a key.
     pub fn describe(&self) -> Result<String, ToolUseError<<T as Tool>::Error>> {
-        let des: Vec<_> = self.tools.iter().map(|t| t.description()).collect();
-        serde_yaml::to_string(&des).map_err(|e| e.into())
+        let descriptions = self.descriptions();
+        descriptions.iter().try_for_each(check_unique_keys)?;
+        serde_yaml::to_string(&descriptions).map_err(|e| e.into())
     }
 
     /// Generate a prompt template for the tool collection. Combine it with a normal prompt template to perform your task.
     pub fn to_prompt_template(&self) -> Result<StringTemplate, ToolUseError<<T as Tool>::Error>> {
+        self.to_prompt_template_with(&PromptRenderOptions::default())
+    }
+
+    /// Generate a prompt template for the tool collection using a custom layout.
+    pub fn to_prompt_template_with(
+        &self,
+        options: &PromptRenderOptions,
+    ) -> Result<StringTemplate, ToolUseError<<T as Tool>::Error>> {
+        let tools = if options.separator.is_empty() && !options.numbered {
+            self.describe()?
+        } else {
+            self.describe_with(options)?
+        };
         Ok(StringTemplate::combine(vec![
-            StringTemplate::static_string(include_str!("./tool_prompt_prefix.txt").to_string()),
-            StringTemplate::static_string(self.describe()?),
+            StringTemplate::static_string(options.header.clone()),
+            StringTemplate::static_string(tools),
             StringTemplate::static_string("\n\n"),
         ]))
     }
+
+    /// Renders each tool description as a list entry, as laid out by `options`.
+    fn describe_with(
+        &self,
+        options: &PromptRenderOptions,
+    ) -> Result<String, ToolUseError<<T as Tool>::Error>> {
+        let mut entries = Vec::with_capacity(self.tools.len());
+        for (idx, tool) in self.tools.iter().enumerate() {
+            let marker = if options.numbered {
+                format!("{}. ", idx + 1)
+            } else {
+                "- ".to_string()
+            };
+            let indent = " ".repeat(marker.len());
+            let description = tool.description();
+            check_unique_keys(&description)?;
+            let yaml = serde_yaml::to_string(&description)?;
+            let mut entry = String::new();
+            for (line_idx, line) in yaml.lines().enumerate() {
+                entry.push_str(if line_idx == 0 { &marker } else { &indent });
+                entry.push_str(line);
+                entry.push('\n');
+            }
+            entries.push(entry);
+        }
+        Ok(entries.join(&options.separator))
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Controls how [`ToolCollection::to_prompt_template_with`] lays out the tool descriptions.
+///
+/// The default reproduces the output of [`ToolCollection::to_prompt_template`].
+#[derive(Debug, Clone)]
+pub struct PromptRenderOptions {
+    /// The text placed before the tool descriptions.
+    pub header: String,
+    /// The text placed between tool descriptions.
+    pub separator: String,
+    /// Whether to number the tools instead of listing them with dashes.
+    pub numbered: bool,
+}
+
+impl Default for PromptRenderOptions {
+    fn default() -> Self {
+        Self {
+            header: include_str!("./tool_prompt_prefix.txt").to_string(),
+            separator: String::new(),
+            numbered: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ToolInvocationInput {
     pub command: String,
     pub input: serde_yaml::Value,
+    /// Identifies the call, so its result can be matched to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_id: Option<String>,
+}
+
+impl ToolInvocationInput {
+    /// Whether both invocations run the same command with the same input, whatever their ids.
+    fn same_call(&self, other: &ToolInvocationInput) -> bool {
+        self.command == other.command && self.input == other.input
+    }
+}
+
+/// The output of a tool call, with the id of the call it answers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ToolCallResult {
+    pub call_id: String,
+    pub output: String,
+}
+
+/// Checks that the formats of a tool don't repeat a key, naming the tool if they do.
+fn check_unique_keys<E: ToolError>(description: &ToolDescription) -> Result<(), ToolUseError<E>> {
+    description
+        .check_unique_keys()
+        .map_err(|source| ToolUseError::DuplicateKey {
+            tool: description.name.clone(),
+            source,
+        })
+}
+
+/// Returns the message a panic was raised with.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "<non-string panic payload>".to_string())
+}
+
+/// Finds the corrected input in the model's response to a [`describe_to_repair_prompt`].
+fn repaired_input(response: &str) -> Option<serde_yaml::Value> {
+    find_yaml::<serde_yaml::Value>(response.trim())
+        .ok()
+        .and_then(|inputs| inputs.into_iter().next())
+}
+
+/// Generates an id for a tool call the model didn't give one.
+pub(crate) fn new_call_id() -> String {
+    format!("call_{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Removes repeated invocations, keeping the first occurrence of each.
+fn dedup_invocations(invocations: Vec<ToolInvocationInput>) -> Vec<ToolInvocationInput> {
+    let mut unique: Vec<ToolInvocationInput> = Vec::with_capacity(invocations.len());
+    for invocation in invocations {
+        if !unique.iter().any(|u| u.same_call(&invocation)) {
+            unique.push(invocation);
+        }
+    }
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+    use thiserror::Error;
+
+    use super::{repaired_input, PromptRenderOptions, ToolCollection, ToolUseError};
+    use crate::options::Options;
+    use crate::output::Output;
+    use crate::prompt::Prompt;
+    use crate::tokens::{
+        PromptTokensError, TokenCollection, TokenCount, Tokenizer, TokenizerError,
+    };
+    use crate::tools::{Format, Tool, ToolDescription, ToolError};
+    use crate::traits::{Executor, ExecutorCreationError, ExecutorError};
+    use crate::Parameters;
+
+    #[derive(Debug, Error)]
+    #[error("Mocked tool error")]
+    struct MockError;
+
+    impl ToolError for MockError {}
+
+    impl From<serde_yaml::Error> for MockError {
+        fn from(_: serde_yaml::Error) -> Self {
+            Self
+        }
+    }
+
+    #[derive(Default)]
+    struct EchoTool {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Tool for EchoTool {
+        type Input = String;
+        type Output = String;
+        type Error = MockError;
+
+        async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if input == "panic" {
+                panic!("echo refuses to panic");
+            }
+            if input == "sleep" {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(format!("echo {}", input))
+        }
+
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "echo",
+                "Echoes the input",
+                "Use this to echo",
+                Format::new(vec![]),
+                Format::new(vec![]),
+            )
+        }
+    }
+
+    /// A tool that only differs from others by its name.
+    struct NamedTool(&'static str);
+
+    #[async_trait]
+    impl Tool for NamedTool {
+        type Input = String;
+        type Output = String;
+        type Error = MockError;
+
+        async fn invoke_typed(&self, _: &Self::Input) -> Result<Self::Output, Self::Error> {
+            Ok(self.0.to_string())
+        }
+
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                self.0,
+                "Returns its name",
+                "Use this to test",
+                Format::new(vec![]),
+                Format::new(vec![]),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_chat_inputs_dedups_identical_calls() {
+        let mut tc = ToolCollection::new();
+        tc.add_tool(EchoTool::default());
+        let response = "```yaml
+command: echo
+input: b
+```
+```yaml
+command: echo
+input: a
+```
+```yaml
+command: echo
+input: b
+```";
+        let invocations = tc.get_tool_invocations(response).unwrap();
+        let inputs: Vec<_> = invocations
+            .iter()
+            .map(|i| i.input.as_str().unwrap())
+            .collect();
+        assert_eq!(inputs, vec!["b", "a"]);
+
+        let outputs = tc.process_chat_inputs(response).await.unwrap();
+        assert_eq!(outputs, vec!["echo b\n", "echo a\n", "echo b\n"]);
+        assert_eq!(tc.tools[0].calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_process_chat_inputs_with_ids_pairs_results_with_calls() {
+        let mut tc = ToolCollection::new();
+        tc.add_tool(EchoTool::default());
+        let response = "```yaml
+command: echo
+input: a
+call_id: first
+```
+```yaml
+command: echo
+input: a
+```";
+        let results = tc.process_chat_inputs_with_ids(response).await.unwrap();
+        assert_eq!(results[0].call_id, "first");
+        assert!(results[1].call_id.starts_with("call_"));
+        assert!(results.iter().all(|r| r.output == "echo a\n"));
+        assert_eq!(tc.tools[0].calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_finds_json_invocations_in_prose() {
+        let tc = ToolCollection::<EchoTool>::new();
+        let response = "I'll echo it. {'command': 'echo', 'input': \"it's\",} Done.";
+        assert!(tc.get_tool_invocation(response).is_err());
+
+        let tc = tc.with_lenient_json(true);
+        let invocation = tc.get_tool_invocation(response).unwrap();
+        assert_eq!(invocation.command, "echo");
+        assert_eq!(invocation.input, "it's");
+    }
+
+    #[test]
+    fn test_finds_repaired_input() {
+        let response = "```yaml\ncmd: ls\n```";
+        assert_eq!(repaired_input(response).unwrap()["cmd"], "ls");
+        assert_eq!(repaired_input(" hello\n").unwrap(), "hello");
+    }
+
+    struct MockTokenizer;
+
+    impl Tokenizer for MockTokenizer {
+        fn tokenize_str(&self, _: &str) -> Result<TokenCollection, TokenizerError> {
+            todo!()
+        }
+
+        fn to_string(&self, _: TokenCollection) -> Result<String, TokenizerError> {
+            todo!()
+        }
+    }
+
+    /// An executor that always responds with the same corrected input.
+    struct RepairExecutor {
+        response: &'static str,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Executor for RepairExecutor {
+        type StepTokenizer<'a> = MockTokenizer;
+
+        fn new_with_options(_: Options) -> Result<Self, ExecutorCreationError> {
+            todo!()
+        }
+
+        async fn execute(&self, _: &Options, _: &Prompt) -> Result<Output, ExecutorError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Output::new_immediate(Prompt::text(
+                self.response.to_string(),
+            )))
+        }
+
+        fn tokens_used(&self, _: &Options, _: &Prompt) -> Result<TokenCount, PromptTokensError> {
+            todo!()
+        }
+
+        fn max_tokens_allowed(&self, _: &Options) -> i32 {
+            todo!()
+        }
+
+        fn answer_prefix(&self, _: &Prompt) -> Option<String> {
+            None
+        }
+
+        fn get_tokenizer(&self, _: &Options) -> Result<MockTokenizer, TokenizerError> {
+            todo!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repairs_invalid_input() {
+        let mut tc = ToolCollection::new().with_input_repair_retries(2);
+        tc.add_tool(EchoTool::default());
+        let invalid = serde_yaml::Value::Sequence(vec!["hi".into()]);
+
+        let executor = RepairExecutor {
+            response: "```yaml\nhi\n```",
+            calls: AtomicUsize::new(0),
+        };
+        let output = tc
+            .invoke_with_input_repair(&executor, "echo", &invalid)
+            .await
+            .unwrap();
+        assert_eq!(output, "echo hi");
+        assert_eq!(executor.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(tc.tools[0].calls.load(Ordering::SeqCst), 1);
+
+        let executor = RepairExecutor {
+            response: "```yaml\n- still a list\n```",
+            calls: AtomicUsize::new(0),
+        };
+        let err = tc
+            .invoke_with_input_repair(&executor, "echo", &invalid)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ToolUseError::InvalidFormat(_)));
+        assert_eq!(executor.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(tc.tools[0].calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct AddInput {
+        a: i64,
+        b: i64,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct AddOutput {
+        sum: i64,
+    }
+
+    #[derive(Debug, Error)]
+    #[error("Mocked add error: {0}")]
+    struct AddError(#[from] serde_yaml::Error);
+
+    impl ToolError for AddError {}
+
+    struct AddTool;
+
+    #[async_trait]
+    impl Tool for AddTool {
+        type Input = AddInput;
+        type Output = AddOutput;
+        type Error = AddError;
+
+        async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+            Ok(AddOutput {
+                sum: input.a + input.b,
+            })
+        }
+
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "add",
+                "Adds two numbers",
+                "Use this to add",
+                vec![
+                    ("a", "The first number").into(),
+                    ("b", "The second number").into(),
+                ]
+                .into(),
+                vec![("sum", "The sum").into()].into(),
+            )
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct NegateInput {
+        n: i64,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct NegateOutput {
+        negated: i64,
+    }
+
+    #[derive(Debug, Error)]
+    #[error("Mocked negate error: {0}")]
+    struct NegateError(#[from] serde_yaml::Error);
+
+    impl ToolError for NegateError {}
+
+    struct NegateTool;
+
+    #[async_trait]
+    impl Tool for NegateTool {
+        type Input = NegateInput;
+        type Output = NegateOutput;
+        type Error = NegateError;
+
+        async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+            Ok(NegateOutput { negated: -input.n })
+        }
+
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "negate",
+                "Negates a number",
+                "Use this to negate",
+                vec![("n", "The number").into()].into(),
+                vec![("negated", "The negated number").into()].into(),
+            )
+        }
+    }
+
+    crate::multitool!(
+        Calculator,
+        CalculatorInput,
+        CalculatorOutput,
+        CalculatorError,
+        AddTool,
+        AddInput,
+        AddOutput,
+        AddError,
+        NegateTool,
+        NegateInput,
+        NegateOutput,
+        NegateError
+    );
+
+    #[tokio::test]
+    async fn test_repairs_multitool_input() {
+        let mut tc = ToolCollection::<Calculator>::new().with_input_repair_retries(1);
+        tc.add_tool(AddTool.into());
+        tc.add_tool(NegateTool.into());
+        let executor = RepairExecutor {
+            response: "```yaml\nn: 3\n```",
+            calls: AtomicUsize::new(0),
+        };
+
+        let valid = serde_yaml::from_str("a: 1\nb: 2").unwrap();
+        let output = tc
+            .invoke_with_input_repair(&executor, "add", &valid)
+            .await
+            .unwrap();
+        assert_eq!(output["sum"], 3);
+        assert_eq!(executor.calls.load(Ordering::SeqCst), 0);
+
+        let invalid = serde_yaml::from_str("n: three").unwrap();
+        let output = tc
+            .invoke_with_input_repair(&executor, "negate", &invalid)
+            .await
+            .unwrap();
+        assert_eq!(output["negated"], -3);
+        assert_eq!(executor.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_refuses_to_describe_duplicate_keys() {
+        struct CopyTool;
+
+        #[async_trait]
+        impl Tool for CopyTool {
+            type Input = String;
+            type Output = String;
+            type Error = MockError;
+
+            async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+                Ok(input.clone())
+            }
+
+            fn description(&self) -> ToolDescription {
+                let input = vec![("path", "The source").into(), ("path", "The target").into()];
+                ToolDescription::new(
+                    "copy",
+                    "Copies a file",
+                    "",
+                    input.into(),
+                    Format::new(vec![]),
+                )
+            }
+        }
+
+        let mut tc = ToolCollection::new();
+        tc.add_tool(CopyTool);
+        let error = tc.describe().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid description of the copy tool: The key \"path\" is described more than once"
+        );
+        assert!(tc.to_prompt_template().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_catches_tool_panics() {
+        let mut tc = ToolCollection::new().with_catch_panics(true);
+        tc.add_tool(EchoTool::default());
+        let err = tc.invoke("echo", &"panic".into()).await.unwrap_err();
+        assert!(
+            matches!(err, ToolUseError::ToolPanicked(message) if message == "echo refuses to panic")
+        );
+        assert_eq!(
+            tc.invoke("echo", &"still here".into()).await.unwrap(),
+            "echo still here"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_times_out_slow_tools() {
+        let mut tc = ToolCollection::new()
+            .with_default_timeout(Duration::from_secs(5))
+            .with_tool_timeout("echo", Duration::from_millis(10));
+        tc.add_tool(EchoTool::default());
+        let err = tc.invoke("echo", &"sleep".into()).await.unwrap_err();
+        assert!(
+            matches!(err, ToolUseError::Timeout(timeout) if timeout == Duration::from_millis(10))
+        );
+        assert_eq!(
+            tc.invoke("echo", &"fast".into()).await.unwrap(),
+            "echo fast"
+        );
+    }
+
+    #[test]
+    fn test_subset_shares_named_tools() {
+        let search = Arc::new(NamedTool("search"));
+        let mut tc = ToolCollection::new();
+        tc.add_shared_tool(search.clone());
+        tc.add_tool(NamedTool("math"));
+        tc.add_tool(NamedTool("shell"));
+
+        let subset = tc.subset(&["shell", "search", "missing"]);
+        let names: Vec<_> = subset.descriptions().into_iter().map(|d| d.name).collect();
+        assert_eq!(names, ["search", "shell"]);
+        assert!(Arc::ptr_eq(&subset.tools[0], &search));
+        assert!(Arc::ptr_eq(&subset.tools[1], &tc.tools[2]));
+    }
+
+    fn render(tc: &ToolCollection<NamedTool>, options: &PromptRenderOptions) -> String {
+        tc.to_prompt_template_with(options)
+            .unwrap()
+            .format(&Parameters::new())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_renders_prompt_with_options() {
+        let mut tc = ToolCollection::new();
+        tc.add_tool(NamedTool("search"));
+        tc.add_tool(NamedTool("math"));
+        let default = PromptRenderOptions::default();
+        assert_eq!(
+            render(&tc, &default),
+            format!(
+                "{}{}\n\n",
+                include_str!("./tool_prompt_prefix.txt"),
+                tc.describe().unwrap()
+            )
+        );
+
+        let header = PromptRenderOptions {
+            header: "Tools:\n".to_string(),
+            ..default.clone()
+        };
+        assert_eq!(
+            render(&tc, &header),
+            "Tools:
+- name: search
+  description: Returns its name
+  description_context: Use this to test
+  input_format: {}
+  output_format: {}
+- name: math
+  description: Returns its name
+  description_context: Use this to test
+  input_format: {}
+  output_format: {}
+
+
+"
+        );
+
+        let numbered = PromptRenderOptions {
+            header: String::new(),
+            numbered: true,
+            ..default.clone()
+        };
+        assert_eq!(
+            render(&tc, &numbered),
+            "1. name: search
+   description: Returns its name
+   description_context: Use this to test
+   input_format: {}
+   output_format: {}
+2. name: math
+   description: Returns its name
+   description_context: Use this to test
+   input_format: {}
+   output_format: {}
+
+
+"
+        );
+
+        let separated = PromptRenderOptions {
+            header: String::new(),
+            separator: "\n".to_string(),
+            ..default
+        };
+        assert_eq!(
+            render(&tc, &separated),
+            "- name: search
+  description: Returns its name
+  description_context: Use this to test
+  input_format: {}
+  output_format: {}
+
+- name: math
+  description: Returns its name
+  description_context: Use this to test
+  input_format: {}
+  output_format: {}
+
+
+"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_limits_tool_calls() {
+        let mut tc = ToolCollection::new().with_tool_call_limit("echo", 1);
+        tc.add_tool(EchoTool::default());
+        assert_eq!(tc.invoke("echo", &"a".into()).await.unwrap(), "echo a");
+        let refused = tc.invoke("echo", &"b".into()).await.unwrap();
+        assert!(refused.as_str().unwrap().contains("limit of 1 calls"));
+        assert_eq!(tc.call_counts()["echo"], 1);
+
+        let run = tc.new_run();
+        assert!(run.call_counts().is_empty());
+        assert_eq!(run.invoke("echo", &"c".into()).await.unwrap(), "echo c");
+        assert_eq!(tc.call_counts()["echo"], 1);
+        let refused = tc.invoke("echo", &"d".into()).await.unwrap();
+        assert!(refused.as_str().unwrap().contains("limit of 1 calls"));
+    }
 }
@@ -1,10 +1,15 @@
-use serde::{ser::SerializeMap, Serialize, Serializer};
+use serde::{ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+use std::{borrow::Cow, collections::HashMap};
+use thiserror::Error;
 
 /// Represents a single parameter for a tool.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FormatPart {
     pub key: String,
     pub purpose: String,
+    /// The only values the parameter accepts, if it is restricted to a fixed set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_values: Option<Vec<String>>,
 }
 
 impl FormatPart {
@@ -13,8 +18,70 @@ impl FormatPart {
         FormatPart {
             key: key.to_string(),
             purpose: purpose.to_string(),
+            allowed_values: None,
         }
     }
+
+    /// Creates a `FormatPart` that only accepts one of `allowed_values`.
+    ///
+    /// The allowed values are listed in the prompt, and `ToolCollection` rejects invocations that
+    /// use any other value.
+    pub fn one_of(key: &str, purpose: &str, allowed_values: &[&str]) -> Self {
+        FormatPart {
+            allowed_values: Some(allowed_values.iter().map(|v| v.to_string()).collect()),
+            ..FormatPart::new(key, purpose)
+        }
+    }
+
+    /// Creates a `FormatPart` for a map whose keys aren't known in advance, such as HTTP headers
+    /// or template variables.
+    ///
+    /// The purpose explains that any string key is accepted, and lists the fields of the values if
+    /// `value_format` has any.
+    pub fn map_of(key: &str, purpose: &str, value_format: &Format) -> Self {
+        let purpose = format!(
+            "{}. A map from any string key to a value{}",
+            purpose,
+            describe_fields(value_format)
+        );
+        FormatPart::new(key, &purpose)
+    }
+
+    /// Returns the purpose as shown to the model, including the allowed values.
+    pub fn describe_purpose(&self) -> Cow<'_, str> {
+        match &self.allowed_values {
+            Some(values) => format!("{} (one of: {})", self.purpose, values.join(", ")).into(),
+            None => Cow::Borrowed(&self.purpose),
+        }
+    }
+
+    /// Creates a `FormatPart` from a purpose written by `describe_purpose`, reading the allowed
+    /// values back out of it.
+    fn from_described_purpose(key: &str, described_purpose: &str) -> Self {
+        let allowed = described_purpose
+            .strip_suffix(')')
+            .and_then(|rest| rest.rsplit_once(" (one of: "));
+        match allowed {
+            Some((purpose, values)) => {
+                FormatPart::one_of(key, purpose, &values.split(", ").collect::<Vec<_>>())
+            }
+            None => FormatPart::new(key, described_purpose),
+        }
+    }
+}
+
+/// Lists the fields of `format` as " with the fields: ...", or returns an empty string if it has
+/// none.
+fn describe_fields(format: &Format) -> String {
+    if format.parts.is_empty() {
+        return String::new();
+    }
+    let fields: Vec<String> = format
+        .parts
+        .iter()
+        .map(|part| format!("{} ({})", part.key, part.describe_purpose()))
+        .collect();
+    format!(" with the fields: {}", fields.join(", "))
 }
 
 impl<K: Into<String>, P: Into<String>> From<(K, P)> for FormatPart {
@@ -34,8 +101,84 @@ impl Format {
     pub fn new(parts: Vec<FormatPart>) -> Self {
         Format { parts }
     }
+
+    /// Returns a JSON schema for an object with one required property per part.
+    ///
+    /// Parts don't carry a type, so each property is only described by its purpose.
+    pub fn json_schema(&self) -> serde_json::Value {
+        let properties: serde_json::Map<String, serde_json::Value> = self
+            .parts
+            .iter()
+            .map(|part| {
+                let mut property = serde_json::json!({ "description": part.purpose });
+                if let Some(values) = &part.allowed_values {
+                    property["enum"] = values.clone().into();
+                }
+                (part.key.clone(), property)
+            })
+            .collect();
+        let required: Vec<&str> = self.parts.iter().map(|part| part.key.as_str()).collect();
+        serde_json::json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+
+    /// Checks that no two parts share a key.
+    ///
+    /// Parts are serialized as a map, so a repeated key would collapse into one entry and show the
+    /// model a different schema than the tool parses.
+    pub fn check_unique_keys(&self) -> Result<(), DuplicateKey> {
+        for (idx, part) in self.parts.iter().enumerate() {
+            if self.parts[..idx].iter().any(|other| other.key == part.key) {
+                return Err(DuplicateKey(part.key.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every restricted parameter in `input` uses one of its allowed values.
+    ///
+    /// Parameters that are missing from `input` are not checked.
+    pub fn check_allowed_values(&self, input: &serde_yaml::Value) -> Result<(), DisallowedValue> {
+        for part in &self.parts {
+            let (Some(allowed), Some(value)) = (&part.allowed_values, input.get(&part.key)) else {
+                continue;
+            };
+            let value = match value {
+                serde_yaml::Value::String(value) => value.clone(),
+                other => serde_yaml::to_string(other)
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+            };
+            if !allowed.contains(&value) {
+                return Err(DisallowedValue {
+                    key: part.key.clone(),
+                    value,
+                    allowed: allowed.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
 }
 
+/// A tool was invoked with a value outside of a parameter's allowed values.
+#[derive(Debug, Error)]
+#[error("Invalid value \"{value}\" for \"{key}\", expected one of: {}", .allowed.join(", "))]
+pub struct DisallowedValue {
+    pub key: String,
+    pub value: String,
+    pub allowed: Vec<String>,
+}
+
+/// A format has more than one part with the same key.
+#[derive(Debug, Error)]
+#[error("The key \"{0}\" is described more than once")]
+pub struct DuplicateKey(pub String);
+
 impl<T: AsRef<[FormatPart]>> From<T> for Format {
     fn from(parts: T) -> Self {
         Format::new(parts.as_ref().to_vec())
@@ -50,15 +193,118 @@ impl Serialize for Format {
         let n = self.parts.len();
         let mut map = serializer.serialize_map(Some(n))?;
         for part in &self.parts {
-            map.serialize_entry(&part.key, &part.purpose)?;
+            map.serialize_entry(&part.key, &part.describe_purpose())?;
         }
         map.end()
     }
 }
 
+/// Reads a format from a map of keys to purposes, the inverse of its `Serialize` implementation.
+///
+/// Allowed values listed at the end of a purpose are read back into `allowed_values`, so the format
+/// round-trips as long as no allowed value contains ", ".
+impl<'de> Deserialize<'de> for Format {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct FormatVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for FormatVisitor {
+            type Value = Format;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a map of parameter names to their purpose")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Format, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let mut parts = vec![];
+                while let Some((key, purpose)) = map.next_entry::<String, String>()? {
+                    parts.push(FormatPart::from_described_purpose(&key, &purpose));
+                }
+                Ok(Format::new(parts))
+            }
+        }
+
+        deserializer.deserialize_map(FormatVisitor)
+    }
+}
+
+impl Format {
+    /// Compares this format with a newer version of it, reporting the keys that were added,
+    /// removed or had their purpose changed.
+    pub fn diff(&self, newer: &Format) -> FormatDiff {
+        let find = |format: &'_ Format, key: &str| -> Option<FormatPart> {
+            format.parts.iter().find(|p| p.key == key).cloned()
+        };
+        let mut diff = FormatDiff::default();
+        for part in &self.parts {
+            match find(newer, &part.key) {
+                None => diff.removed.push(part.key.clone()),
+                Some(newer_part)
+                    if newer_part.purpose != part.purpose
+                        || newer_part.allowed_values != part.allowed_values =>
+                {
+                    diff.changed.push(part.key.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        for part in &newer.parts {
+            if find(self, &part.key).is_none() {
+                diff.added.push(part.key.clone());
+            }
+        }
+        diff
+    }
+}
+
+/// The differences between two versions of a `Format`, as returned by `Format::diff`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FormatDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl FormatDiff {
+    /// Returns true if the two formats are identical.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
 /// A trait to provide a description format for a tool.
 pub trait Describe {
     fn describe() -> Format;
+
+    /// Returns a JSON schema for the described type, see [`Format::json_schema`].
+    fn json_schema() -> serde_json::Value {
+        Self::describe().json_schema()
+    }
+}
+
+/// A plain string has no fields.
+impl Describe for String {
+    fn describe() -> Format {
+        Format::new(vec![])
+    }
+}
+
+/// Describes an open map as a single part with the key `*`, standing for every entry of the map.
+///
+/// To describe a map field of a struct, use [`FormatPart::map_of`] with the field's name instead.
+impl<V: Describe> Describe for HashMap<String, V> {
+    fn describe() -> Format {
+        let purpose = format!(
+            "Any number of entries, each mapping a string key to a value{}",
+            describe_fields(&V::describe())
+        );
+        Format::new(vec![FormatPart::new("*", &purpose)])
+    }
 }
 
 /// Represents the description of a tool, including its name, usage, and input/output formats.
@@ -91,4 +337,302 @@ impl ToolDescription {
             output_format,
         }
     }
+
+    /// Returns a stable fingerprint of the tool's name and input/output formats.
+    ///
+    /// The fingerprint only changes when the model-facing schema changes, so it can be asserted in
+    /// tests to catch accidental changes to a tool's contract.
+    pub fn fingerprint(&self) -> String {
+        // FNV-1a is used because, unlike the std hashers, its output is guaranteed to be stable.
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut write = |s: &str| {
+            for byte in s.bytes().chain(std::iter::once(0)) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+        write(&self.name);
+        for format in [&self.input_format, &self.output_format] {
+            write(&format.parts.len().to_string());
+            for part in &format.parts {
+                write(&part.key);
+                write(&part.purpose);
+                if let Some(values) = &part.allowed_values {
+                    write("one_of");
+                    values.iter().for_each(|value| write(value));
+                }
+            }
+        }
+        format!("{:016x}", hash)
+    }
+
+    /// Checks that neither the input nor the output format repeats a key, see
+    /// [`Format::check_unique_keys`].
+    pub fn check_unique_keys(&self) -> Result<(), DuplicateKey> {
+        self.input_format.check_unique_keys()?;
+        self.output_format.check_unique_keys()
+    }
+
+    /// Compares this description with a newer version of the same tool.
+    pub fn diff(&self, newer: &ToolDescription) -> ToolDescriptionDiff {
+        ToolDescriptionDiff {
+            input: self.input_format.diff(&newer.input_format),
+            output: self.output_format.diff(&newer.output_format),
+        }
+    }
+}
+
+/// The version of the manifest format written by `ToolDescription::to_manifest`.
+///
+/// New fields are only ever added, so manifests with an older version can always be read.
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    name: String,
+    description: String,
+    description_context: String,
+    input_format: Vec<FormatPart>,
+    output_format: Vec<FormatPart>,
+}
+
+#[derive(Debug, Error)]
+pub enum ManifestError {
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error("Manifest version {0} is newer than the supported version {MANIFEST_VERSION}")]
+    UnsupportedVersion(u32),
+}
+
+impl ToolDescription {
+    /// Serializes the description to a JSON manifest that other services can load with
+    /// `ToolDescription::from_manifest`.
+    pub fn to_manifest(&self) -> Result<String, ManifestError> {
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            name: self.name.clone(),
+            description: self.description.clone(),
+            description_context: self.description_context.clone(),
+            input_format: self.input_format.parts.clone(),
+            output_format: self.output_format.parts.clone(),
+        };
+        Ok(serde_json::to_string_pretty(&manifest)?)
+    }
+
+    /// Loads a description from a JSON manifest written by `ToolDescription::to_manifest`.
+    ///
+    /// Unknown fields are ignored, but manifests written by a newer, unsupported version are rejected.
+    pub fn from_manifest(manifest: &str) -> Result<Self, ManifestError> {
+        let manifest: Manifest = serde_json::from_str(manifest)?;
+        if manifest.version > MANIFEST_VERSION {
+            return Err(ManifestError::UnsupportedVersion(manifest.version));
+        }
+        Ok(ToolDescription::new(
+            &manifest.name,
+            &manifest.description,
+            &manifest.description_context,
+            Format::new(manifest.input_format),
+            Format::new(manifest.output_format),
+        ))
+    }
+}
+
+/// The differences between two versions of a `ToolDescription`, as returned by `ToolDescription::diff`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ToolDescriptionDiff {
+    pub input: FormatDiff,
+    pub output: FormatDiff,
+}
+
+impl ToolDescriptionDiff {
+    /// Returns true if neither the input nor the output format changed.
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty() && self.output.is_empty()
+    }
+}
+
+/// Builds an instruction asking the model to correct `bad_input` so that it matches `format`.
+///
+/// The instruction lists the required fields, the fields missing from `bad_input` if it is a YAML
+/// map, and the offending input itself.
+pub fn describe_to_repair_prompt(format: &Format, bad_input: &str) -> String {
+    let fields = serde_yaml::to_string(format).unwrap_or_default();
+    let mut prompt = format!(
+        "Your output did not match the required format. It must be YAML with these fields:\n{}",
+        fields
+    );
+    if let Ok(serde_yaml::Value::Mapping(map)) = serde_yaml::from_str(bad_input) {
+        let missing: Vec<&str> = format
+            .parts
+            .iter()
+            .filter(|part| !map.contains_key(part.key.as_str()))
+            .map(|part| part.key.as_str())
+            .collect();
+        if !missing.is_empty() {
+            prompt += &format!("\nThese fields are missing: {}\n", missing.join(", "));
+        }
+    }
+    prompt += &format!(
+        "\nYour output was:\n```\n{}\n```\n\nRespond again with only the corrected YAML.",
+        bad_input.trim()
+    );
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repair_prompt_lists_missing_fields() {
+        let format: Format = vec![
+            ("query", "The query").into(),
+            ("limit", "How many results to return").into(),
+        ]
+        .into();
+        let prompt = describe_to_repair_prompt(&format, "query: rust");
+        assert!(prompt.contains("limit: How many results to return"));
+        assert!(prompt.contains("These fields are missing: limit\n"));
+        assert!(prompt.contains("```\nquery: rust\n```"));
+
+        let prompt = describe_to_repair_prompt(&format, "not: [valid");
+        assert!(!prompt.contains("missing"));
+    }
+
+    fn description(input: Vec<FormatPart>) -> ToolDescription {
+        ToolDescription::new("Tool", "A tool", "Use it", input.into(), vec![].into())
+    }
+
+    #[test]
+    fn test_fingerprint_is_stable() {
+        let a = description(vec![("query", "The query").into()]);
+        let b = description(vec![("query", "The query").into()]);
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert_eq!(a.fingerprint(), "41a321fc9b24469d");
+    }
+
+    #[test]
+    fn test_fingerprint_changes_with_format() {
+        let a = description(vec![("query", "The query").into()]);
+        let b = description(vec![("query", "The search query").into()]);
+        assert_ne!(a.fingerprint(), b.fingerprint());
+    }
+
+    #[test]
+    fn test_diff_reports_changes() {
+        let old = description(vec![
+            ("query", "The query").into(),
+            ("limit", "Max results").into(),
+        ]);
+        let new = description(vec![
+            ("query", "The search query").into(),
+            ("offset", "Results to skip").into(),
+        ]);
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.input,
+            FormatDiff {
+                added: vec!["offset".into()],
+                removed: vec!["limit".into()],
+                changed: vec!["query".into()],
+            }
+        );
+        assert!(diff.output.is_empty());
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        let original = description(vec![
+            ("query", "The query (in English)").into(),
+            FormatPart::one_of("mode", "How to search", &["fast", "thorough"]),
+        ]);
+        let yaml = serde_yaml::to_string(&original.input_format).unwrap();
+        assert_eq!(
+            yaml,
+            "query: The query (in English)\nmode: 'How to search (one of: fast, thorough)'\n"
+        );
+        let loaded = description(serde_yaml::from_str::<Format>(&yaml).unwrap().parts);
+        assert_eq!(loaded.input_format.parts[1].purpose, "How to search");
+        assert_eq!(loaded.fingerprint(), original.fingerprint());
+        assert_eq!(serde_yaml::to_string(&loaded.input_format).unwrap(), yaml);
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let original = description(vec![
+            ("query", "The query").into(),
+            FormatPart::one_of("mode", "How to search", &["fast", "thorough"]),
+        ]);
+        let loaded = ToolDescription::from_manifest(&original.to_manifest().unwrap()).unwrap();
+        assert_eq!(loaded.name, original.name);
+        assert_eq!(loaded.fingerprint(), original.fingerprint());
+
+        let newer = original.to_manifest().unwrap().replace(
+            &format!("\"version\": {}", MANIFEST_VERSION),
+            "\"version\": 999",
+        );
+        assert!(matches!(
+            ToolDescription::from_manifest(&newer),
+            Err(ManifestError::UnsupportedVersion(999))
+        ));
+    }
+
+    #[test]
+    fn test_detects_duplicate_keys() {
+        let format: Format = vec![
+            ("path", "The file to read").into(),
+            ("limit", "The number of lines").into(),
+            ("path", "The file to write").into(),
+        ]
+        .into();
+        let error = format.check_unique_keys().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The key \"path\" is described more than once"
+        );
+
+        let description = ToolDescription::new("copy", "", "", Format::new(vec![]), format);
+        assert!(description.check_unique_keys().is_err());
+        let unique: Format = vec![("path", "The file to read").into()].into();
+        assert!(unique.check_unique_keys().is_ok());
+    }
+
+    #[test]
+    fn test_describes_open_maps() {
+        let format = HashMap::<String, String>::describe();
+        assert_eq!(format.parts[0].key, "*");
+        assert_eq!(
+            format.parts[0].purpose,
+            "Any number of entries, each mapping a string key to a value"
+        );
+
+        let value_format: Format = vec![("value", "The header value").into()].into();
+        let part = FormatPart::map_of("headers", "The HTTP headers", &value_format);
+        assert_eq!(
+            part.purpose,
+            "The HTTP headers. A map from any string key to a value with the fields: value (The header value)"
+        );
+    }
+
+    #[test]
+    fn test_one_of_renders_and_validates() {
+        let format = Format::new(vec![FormatPart::one_of(
+            "operation",
+            "The operation to run",
+            &["create", "list"],
+        )]);
+        assert_eq!(
+            serde_yaml::to_string(&format).unwrap(),
+            "operation: 'The operation to run (one of: create, list)'\n"
+        );
+        let input = |op: &str| serde_yaml::from_str(&format!("operation: {}", op)).unwrap();
+        assert!(format.check_allowed_values(&input("list")).is_ok());
+        let err = format.check_allowed_values(&input("delete")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid value \"delete\" for \"operation\", expected one of: create, list"
+        );
+    }
 }
@@ -0,0 +1,199 @@
+use std::fmt::Display;
+use std::future::Future;
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use super::description::{Describe, Format, FormatPart, ToolDescription};
+use super::tool::{Tool, ToolError};
+
+type BoxedFn = Box<
+    dyn Fn(serde_yaml::Value) -> BoxFuture<'static, Result<serde_yaml::Value, FnToolError>>
+        + Send
+        + Sync,
+>;
+
+#[derive(Debug, Error)]
+pub enum FnToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl ToolError for FnToolError {}
+
+/// A tool that calls an async function, for tools too simple to implement [`Tool`] for.
+///
+/// The function's input and output types are erased, so tools made from different functions have
+/// the same type and can be added to one [`super::ToolCollection`]. The input is deserialized into
+/// the function's input type when the tool is invoked, and errors are reported with their `Display`
+/// message.
+///
+/// # Example
+///
+/// ```
+/// use llm_chain::tools::{Describe, FnTool, Format, ToolCollection};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Deserialize)]
+/// struct AddInput {
+///     a: i64,
+///     b: i64,
+/// }
+///
+/// impl Describe for AddInput {
+///     fn describe() -> Format {
+///         vec![("a", "The first number").into(), ("b", "The second number").into()].into()
+///     }
+/// }
+///
+/// #[derive(Serialize)]
+/// struct AddOutput {
+///     sum: i64,
+/// }
+///
+/// impl Describe for AddOutput {
+///     fn describe() -> Format {
+///         vec![("sum", "The sum of the numbers").into()].into()
+///     }
+/// }
+///
+/// let mut tools = ToolCollection::new();
+/// tools.add_tool(FnTool::new("add", "Adds two numbers.", |input: AddInput| async move {
+///     Ok::<_, std::convert::Infallible>(AddOutput { sum: input.a + input.b })
+/// }));
+/// ```
+pub struct FnTool {
+    name: String,
+    description: String,
+    description_context: String,
+    input_format: Vec<FormatPart>,
+    output_format: Vec<FormatPart>,
+    func: BoxedFn,
+}
+
+impl FnTool {
+    /// Creates a tool called `name` that calls `func`.
+    ///
+    /// The input and output formats are taken from the `Describe` implementations of the function's
+    /// input and output types.
+    pub fn new<I, O, E, F, Fut>(name: &str, description: &str, func: F) -> Self
+    where
+        I: Describe + DeserializeOwned + Send + 'static,
+        O: Describe + Serialize + Send + 'static,
+        E: Display + Send + 'static,
+        F: Fn(I) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<O, E>> + Send + 'static,
+    {
+        let func: BoxedFn = Box::new(move |input| match serde_yaml::from_value::<I>(input) {
+            Err(e) => futures::future::ready(Err(e.into())).boxed(),
+            Ok(input) => {
+                let output = func(input);
+                async move {
+                    let output = output
+                        .await
+                        .map_err(|e| FnToolError::Failed(e.to_string()))?;
+                    Ok(serde_yaml::to_value(output)?)
+                }
+                .boxed()
+            }
+        });
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            description_context: String::new(),
+            input_format: I::describe().parts,
+            output_format: O::describe().parts,
+            func,
+        }
+    }
+
+    /// Sets the context telling the model when to use the tool.
+    pub fn with_context(mut self, description_context: &str) -> Self {
+        self.description_context = description_context.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl Tool for FnTool {
+    type Input = serde_yaml::Value;
+
+    type Output = serde_yaml::Value;
+
+    type Error = FnToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        (self.func)(input.clone()).await
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            &self.name,
+            &self.description,
+            &self.description_context,
+            Format::new(self.input_format.clone()),
+            Format::new(self.output_format.clone()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::tools::ToolCollection;
+
+    #[derive(Deserialize)]
+    struct DivideInput {
+        a: f64,
+        b: f64,
+    }
+
+    impl Describe for DivideInput {
+        fn describe() -> Format {
+            vec![("a", "The dividend").into(), ("b", "The divisor").into()].into()
+        }
+    }
+
+    #[derive(Serialize)]
+    struct DivideOutput {
+        quotient: f64,
+    }
+
+    impl Describe for DivideOutput {
+        fn describe() -> Format {
+            vec![("quotient", "The quotient").into()].into()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_calls_function_and_reports_errors() {
+        let mut tools = ToolCollection::new();
+        tools.add_tool(FnTool::new(
+            "divide",
+            "Divides two numbers.",
+            |input: DivideInput| async move {
+                if input.b == 0.0 {
+                    return Err("Cannot divide by zero");
+                }
+                Ok(DivideOutput {
+                    quotient: input.a / input.b,
+                })
+            },
+        ));
+
+        let input = serde_yaml::from_str("a: 6\nb: 4").unwrap();
+        let output = tools.invoke("divide", &input).await.unwrap();
+        assert_eq!(output["quotient"], 1.5);
+
+        let input = serde_yaml::from_str("a: 6\nb: 0").unwrap();
+        let err = tools.invoke("divide", &input).await.unwrap_err();
+        assert_eq!(err.to_string(), "Cannot divide by zero");
+    }
+}
@@ -0,0 +1,302 @@
+//! Serves tools to [Model Context Protocol](https://modelcontextprotocol.io) clients.
+//!
+//! MCP clients, such as desktop assistants and IDE integrations, talk JSON-RPC to a server that
+//! lists and calls tools. [`McpServer`] answers them from a [`ToolCollection`], so any tool can be
+//! exposed to those clients without changes.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use super::collection::{ToolCollection, ToolUseError};
+use super::description::ToolDescription;
+use super::tool::Tool;
+
+/// The version of the protocol the server implements.
+pub const MCP_PROTOCOL_VERSION: &str = "2024-11-05";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+impl ToolDescription {
+    /// Returns the MCP definition of the tool, as listed by `tools/list`.
+    ///
+    /// The input schema is built from the input format, see [`super::Format::json_schema`].
+    pub fn to_mcp_tool(&self) -> Value {
+        json!({
+            "name": self.name,
+            "description": format!("{} {}", self.description, self.description_context).trim(),
+            "inputSchema": self.input_format.json_schema(),
+        })
+    }
+}
+
+/// A JSON-RPC request or notification. Notifications have no id and get no response.
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct CallToolParams {
+    name: String,
+    #[serde(default)]
+    arguments: Option<Value>,
+}
+
+/// An MCP server exposing the tools of a [`ToolCollection`].
+///
+/// Tool failures are reported to the client as results with `isError` set, so the model calling
+/// the tool can see what went wrong.
+///
+/// # Example
+///
+/// ```no_run
+/// use llm_chain::tools::{mcp::McpServer, tools::BashTool, ToolCollection};
+/// # async fn run() {
+/// let mut tools = ToolCollection::new();
+/// tools.add_tool(BashTool::new());
+/// McpServer::new("my-tools", "0.1.0", tools)
+///     .serve_stdio()
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub struct McpServer<T> {
+    name: String,
+    version: String,
+    tools: ToolCollection<T>,
+}
+
+impl<T> McpServer<T>
+where
+    T: Tool + Send + Sync,
+{
+    /// Creates a server that introduces itself to clients as `name` at `version`.
+    pub fn new(name: &str, version: &str, tools: ToolCollection<T>) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            tools,
+        }
+    }
+
+    /// Serves clients over standard input and output, until standard input is closed.
+    pub async fn serve_stdio(&self) -> std::io::Result<()> {
+        self.serve(BufReader::new(tokio::io::stdin()), tokio::io::stdout())
+            .await
+    }
+
+    /// Reads newline-delimited JSON-RPC messages from `reader` and writes the responses to
+    /// `writer`, until `reader` is exhausted.
+    pub async fn serve<R, W>(&self, reader: R, mut writer: W) -> std::io::Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut lines = reader.lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_message(&line).await {
+                writer.write_all(response.as_bytes()).await?;
+                writer.write_all(b"\n").await?;
+                writer.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a single JSON-RPC message, returning the response if it needs one.
+    ///
+    /// Use this to serve clients over a transport other than stdio.
+    pub async fn handle_message(&self, message: &str) -> Option<String> {
+        let response = match serde_json::from_str::<Value>(message) {
+            Err(e) => Some(error_response(Value::Null, PARSE_ERROR, &e.to_string())),
+            Ok(value) => match serde_json::from_value::<Request>(value) {
+                Err(e) => Some(error_response(Value::Null, INVALID_REQUEST, &e.to_string())),
+                Ok(request) => self.handle_request(request).await,
+            },
+        };
+        response.map(|response| response.to_string())
+    }
+
+    async fn handle_request(&self, request: Request) -> Option<Value> {
+        // Notifications such as `notifications/initialized` need no response.
+        let id = request.id?;
+        let result = match request.method.as_str() {
+            "initialize" => Ok(json!({
+                "protocolVersion": MCP_PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": self.name, "version": self.version },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => {
+                let tools: Vec<Value> = self
+                    .tools
+                    .descriptions()
+                    .iter()
+                    .map(ToolDescription::to_mcp_tool)
+                    .collect();
+                Ok(json!({ "tools": tools }))
+            }
+            "tools/call" => self.call_tool(request.params).await,
+            method => Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+        };
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, &message),
+        })
+    }
+
+    async fn call_tool(&self, params: Value) -> Result<Value, (i64, String)> {
+        let params: CallToolParams =
+            serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+        let input = serde_yaml::to_value(params.arguments.unwrap_or_else(|| json!({})))
+            .map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+        let (text, is_error) = match self.tools.invoke(&params.name, &input).await {
+            Err(ToolUseError::ToolNotFound) => {
+                return Err((INVALID_PARAMS, format!("Unknown tool: {}", params.name)))
+            }
+            Err(e) => (e.to_string(), true),
+            Ok(output) => match serde_json::to_string(&output) {
+                Ok(text) => (text, false),
+                Err(e) => (e.to_string(), true),
+            },
+        };
+        Ok(json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": is_error,
+        }))
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+    use thiserror::Error;
+
+    use super::*;
+    use crate::tools::{Describe, Format, ToolError};
+
+    #[derive(Debug, Error)]
+    #[error("Mocked tool error")]
+    struct MockError;
+
+    impl ToolError for MockError {}
+
+    impl From<serde_yaml::Error> for MockError {
+        fn from(_: serde_yaml::Error) -> Self {
+            Self
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct ShoutInput {
+        text: String,
+    }
+
+    impl Describe for ShoutInput {
+        fn describe() -> Format {
+            vec![("text", "The text to shout").into()].into()
+        }
+    }
+
+    struct ShoutTool;
+
+    #[async_trait]
+    impl Tool for ShoutTool {
+        type Input = ShoutInput;
+        type Output = String;
+        type Error = MockError;
+
+        async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+            Ok(input.text.to_uppercase())
+        }
+
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "shout",
+                "Uppercases text.",
+                "Use this to shout.",
+                ShoutInput::describe(),
+                Format::new(vec![]),
+            )
+        }
+    }
+
+    async fn request(server: &McpServer<ShoutTool>, message: Value) -> Value {
+        let response = server.handle_message(&message.to_string()).await.unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_lists_and_calls_tools() {
+        let mut tools = ToolCollection::new();
+        tools.add_tool(ShoutTool);
+        let server = McpServer::new("test", "1.0.0", tools);
+
+        let notification = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        assert_eq!(server.handle_message(&notification.to_string()).await, None);
+
+        let list = request(
+            &server,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }),
+        )
+        .await;
+        assert_eq!(
+            list["result"]["tools"][0],
+            json!({
+                "name": "shout",
+                "description": "Uppercases text. Use this to shout.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": { "text": { "description": "The text to shout" } },
+                    "required": ["text"],
+                },
+            })
+        );
+
+        let call = request(
+            &server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "tools/call",
+                "params": { "name": "shout", "arguments": { "text": "hi" } },
+            }),
+        )
+        .await;
+        assert_eq!(call["id"], 2);
+        assert_eq!(call["result"]["content"][0]["text"], "\"HI\"");
+        assert_eq!(call["result"]["isError"], false);
+
+        let unknown = request(
+            &server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": "tools/call",
+                "params": { "name": "whisper", "arguments": {} },
+            }),
+        )
+        .await;
+        assert_eq!(unknown["error"]["code"], INVALID_PARAMS);
+    }
+}
@@ -28,15 +28,38 @@
 //!
 //! - `tools`: A submodule that provides a variety of pre-defined tools.
 
+mod binary;
+mod bounded;
+#[cfg(feature = "cli")]
+pub mod cli;
 mod collection;
 mod description;
+mod fn_tool;
+#[cfg(feature = "mcp")]
+pub mod mcp;
 #[cfg(feature = "multitool_default")]
 pub mod multitool_default;
-pub use description::{Describe, Format, FormatPart, ToolDescription};
+pub use description::{
+    describe_to_repair_prompt, Describe, DisallowedValue, DuplicateKey, Format, FormatDiff,
+    FormatPart, ManifestError, ToolDescription, ToolDescriptionDiff, MANIFEST_VERSION,
+};
 pub mod multitool;
+pub mod openapi;
+mod streaming;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod tool;
 #[allow(clippy::module_inception)]
 pub mod tools;
+mod transform;
 
-pub use collection::{ToolCollection, ToolInvocationInput, ToolUseError};
+pub use binary::{Base64, Base64Error, DEFAULT_MAX_DECODED_BYTES};
+pub use bounded::{Bounded, BoundedError, OverflowStrategy, ToolSizeMetrics};
+pub(crate) use collection::new_call_id;
+pub use collection::{
+    PromptRenderOptions, ToolCallResult, ToolCollection, ToolInvocationInput, ToolUseError,
+};
+pub use fn_tool::{FnTool, FnToolError};
+pub use streaming::ToolInvocationDetector;
 pub use tool::{Tool, ToolError};
+pub use transform::{Lowercase, NonEmpty, Transform, Transformed, Trim};
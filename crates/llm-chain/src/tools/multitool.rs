@@ -55,15 +55,22 @@ macro_rules! multitool {
 
         #[derive(Debug, Error)]
         enum $error {
-            #[error("Could not convert")]
+            #[error("The input or output belongs to a different tool")]
             BadVariant,
-            #[error(transparent)]
+            #[error("Invalid YAML: {0}")]
             YamlError(#[from] serde_yaml::Error),
             $(#[error(transparent)]
             $tool_error(#[from] $tool_error)),+
         }
 
-        impl ToolError for $error {}
+        impl ToolError for $error {
+            fn need_more_info(&self) -> Option<&str> {
+                match self {
+                    $($error::$tool_error(e) => e.need_more_info(),)+
+                    _ => None,
+                }
+            }
+        }
 
         enum $multitool {
             $($tool($tool)),+
@@ -113,6 +120,13 @@ macro_rules! multitool {
                 }
             }
 
+            /// Checks `input` against the input of the wrapped tool, which is what `invoke` receives.
+            fn check_input(&self, input: &serde_yaml::Value) -> Result<(), serde_yaml::Error> {
+                match self {
+                    $($multitool::$tool(t) => t.check_input(input)),+
+                }
+            }
+
             /// Checks whether the tool matches the given name.
             ///
             /// This function is used to find the appropriate tool in a `ToolCollection` based on its name.
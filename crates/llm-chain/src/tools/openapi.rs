@@ -0,0 +1,113 @@
+//! Describes tools as an [OpenAPI 3](https://spec.openapis.org/oas/v3.0.3) document.
+//!
+//! Each tool becomes a `POST /tools/{name}` operation taking its input as a JSON body and returning
+//! its output as JSON, with schemas built from the tool's formats. Serve the operations with any
+//! HTTP server to expose a [`ToolCollection`] as a documented REST API.
+
+use serde_json::{json, Map, Value};
+
+use super::collection::ToolCollection;
+use super::description::ToolDescription;
+use super::tool::Tool;
+
+/// The OpenAPI version of the generated documents.
+pub const OPENAPI_VERSION: &str = "3.0.3";
+
+impl ToolDescription {
+    /// Returns the path the tool is served at, `/tools/` followed by its name.
+    ///
+    /// Characters other than ASCII letters, digits, `-`, `_` and `.` are replaced with `_`, so the
+    /// name doesn't need to be escaped.
+    pub fn openapi_path(&self) -> String {
+        format!("/tools/{}", path_segment(&self.name))
+    }
+
+    /// Returns the OpenAPI operation calling the tool.
+    ///
+    /// The schemas are built from the input and output formats, see [`super::Format::json_schema`].
+    pub fn to_openapi_operation(&self) -> Value {
+        json!({
+            "operationId": path_segment(&self.name),
+            "summary": self.description,
+            "description": self.description_context,
+            "requestBody": {
+                "required": true,
+                "content": { "application/json": { "schema": self.input_format.json_schema() } },
+            },
+            "responses": {
+                "200": {
+                    "description": "The output of the tool",
+                    "content": { "application/json": { "schema": self.output_format.json_schema() } },
+                },
+                "default": {
+                    "description": "The tool failed",
+                    "content": { "application/json": { "schema": {
+                        "type": "object",
+                        "properties": { "error": { "type": "string" } },
+                        "required": ["error"],
+                    } } },
+                },
+            },
+        })
+    }
+}
+
+impl<T> ToolCollection<T>
+where
+    T: Tool + Send + Sync,
+{
+    /// Returns an OpenAPI document with an operation for each tool, see
+    /// [`ToolDescription::to_openapi_operation`].
+    pub fn to_openapi(&self, title: &str, version: &str) -> Value {
+        let paths: Map<String, Value> = self
+            .descriptions()
+            .iter()
+            .map(|description| {
+                (
+                    description.openapi_path(),
+                    json!({ "post": description.to_openapi_operation() }),
+                )
+            })
+            .collect();
+        json!({
+            "openapi": OPENAPI_VERSION,
+            "info": { "title": title, "version": version },
+            "paths": paths,
+        })
+    }
+}
+
+fn path_segment(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tools::{BashTool, CodecTool};
+
+    #[test]
+    fn test_describes_each_tool_as_an_operation() {
+        let mut tools = ToolCollection::new();
+        tools.add_tool(BashTool::new());
+        let spec = tools.to_openapi("Shell", "1.0.0");
+        assert_eq!(spec["openapi"], OPENAPI_VERSION);
+        assert_eq!(spec["info"]["title"], "Shell");
+
+        let operation = &spec["paths"]["/tools/BashTool"]["post"];
+        assert_eq!(operation["operationId"], "BashTool");
+        let input = &operation["requestBody"]["content"]["application/json"]["schema"];
+        assert_eq!(input["required"], json!(["cmd"]));
+        let output = &operation["responses"]["200"]["content"]["application/json"]["schema"];
+        assert_eq!(output["required"], json!(["result", "stderr", "stdout"]));
+
+        let mut description = CodecTool::new().description();
+        description.name = "Encode text".to_string();
+        assert_eq!(description.openapi_path(), "/tools/Encode_text");
+    }
+}
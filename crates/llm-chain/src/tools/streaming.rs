@@ -0,0 +1,85 @@
+use super::collection::ToolInvocationInput;
+
+/// Detects tool invocations in a model response while it is still being streamed.
+///
+/// Feed the detector each chunk of the response with [`ToolInvocationDetector::push`]. As soon as a
+/// fenced YAML or JSON block holding a tool invocation has been closed, it is returned, so the tool
+/// can be started before the rest of the response arrives. Partial lines are buffered until they
+/// are complete, and blocks that don't parse as an invocation are skipped.
+///
+/// # Example
+///
+/// ```
+/// use llm_chain::tools::ToolInvocationDetector;
+///
+/// let mut detector = ToolInvocationDetector::new();
+/// assert!(detector.push("Let me look that up.\n```ya").is_empty());
+/// assert!(detector.push("ml\ncommand: search\ninput: rust\n").is_empty());
+/// let invocations = detector.push("```\nand then...");
+/// assert_eq!(invocations[0].command, "search");
+/// ```
+#[derive(Default)]
+pub struct ToolInvocationDetector {
+    buffer: String,
+    line_start: usize,
+    block: Option<Block>,
+}
+
+enum Block {
+    /// A YAML or JSON block whose content starts at the given offset.
+    Candidate(usize),
+    /// A block in some other language, which is skipped.
+    Other,
+}
+
+impl ToolInvocationDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the next chunk of the response, returning the invocations completed by it.
+    pub fn push(&mut self, chunk: &str) -> Vec<ToolInvocationInput> {
+        self.buffer.push_str(chunk);
+        let mut found = vec![];
+        while let Some(offset) = self.buffer[self.line_start..].find('\n') {
+            let line_end = self.line_start + offset;
+            found.extend(self.process_line(line_end));
+            self.line_start = line_end + 1;
+        }
+        found
+    }
+
+    /// Processes the last line of the response, which may not end in a newline.
+    pub fn finish(&mut self) -> Vec<ToolInvocationInput> {
+        if self.line_start >= self.buffer.len() {
+            return vec![];
+        }
+        let found = self.process_line(self.buffer.len());
+        self.line_start = self.buffer.len();
+        found.into_iter().collect()
+    }
+
+    /// The response received so far.
+    pub fn text(&self) -> &str {
+        &self.buffer
+    }
+
+    fn process_line(&mut self, line_end: usize) -> Option<ToolInvocationInput> {
+        let line = self.buffer[self.line_start..line_end].trim();
+        let fence = line.strip_prefix("```")?;
+        match self.block.take() {
+            None => {
+                self.block = Some(match fence.trim() {
+                    "yaml" | "yml" | "json" | "" => Block::Candidate(line_end + 1),
+                    _ => Block::Other,
+                });
+                None
+            }
+            Some(Block::Candidate(content_start)) => {
+                let content = self.buffer.get(content_start..self.line_start)?;
+                serde_yaml::from_str(content).ok()
+            }
+            Some(Block::Other) => None,
+        }
+    }
+}
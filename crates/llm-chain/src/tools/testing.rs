@@ -0,0 +1,403 @@
+//! Helpers for testing that tools cope with whatever a model throws at them.
+//!
+//! Models regularly produce inputs with missing fields, wrong types or made up values. A tool that
+//! `unwrap`s on such input takes the whole agent down with it, so `fuzz_tool` feeds a tool many
+//! random inputs shaped after its input `Format`, plus some malformed ones, and reports any that
+//! made it panic.
+//!
+//! [`assert_tool_schema_stable!`](crate::assert_tool_schema_stable) compares a tool's input or
+//! output format with a snapshot, so changes to it don't go unnoticed.
+
+use futures::FutureExt;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde_yaml::{Mapping, Value};
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+
+use super::collection::panic_message;
+use super::description::{Format, FormatPart};
+use super::tool::Tool;
+
+/// An input that made a tool panic.
+#[derive(Debug, Clone)]
+pub struct FuzzPanic {
+    pub input: Value,
+    pub message: String,
+}
+
+/// The outcome of fuzzing a tool.
+#[derive(Debug, Clone, Default)]
+pub struct FuzzReport {
+    /// Invocations that returned `Ok`.
+    pub ok: usize,
+    /// Invocations that returned `Err`.
+    pub err: usize,
+    /// Invocations that panicked.
+    pub panics: Vec<FuzzPanic>,
+}
+
+impl FuzzReport {
+    pub fn runs(&self) -> usize {
+        self.ok + self.err + self.panics.len()
+    }
+}
+
+/// Generates random inputs for a tool from its input `Format`.
+///
+/// About half of the inputs have every field of the format with a plausible value. The others are
+/// malformed: fields are missing or of the wrong type, unknown fields are added, or the input is
+/// not a mapping at all.
+pub struct InputGenerator {
+    rng: StdRng,
+}
+
+impl InputGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Generates one input for `format`.
+    pub fn generate(&mut self, format: &Format) -> Value {
+        if self.rng.gen_bool(0.5) {
+            self.well_formed(format)
+        } else {
+            self.malformed(format)
+        }
+    }
+
+    /// Generates an input with every field of `format`.
+    pub fn well_formed(&mut self, format: &Format) -> Value {
+        let mut input = Mapping::new();
+        for part in &format.parts {
+            let value = self.value_for(part);
+            input.insert(part.key.clone().into(), value);
+        }
+        Value::Mapping(input)
+    }
+
+    /// Generates an input that does not match `format`.
+    pub fn malformed(&mut self, format: &Format) -> Value {
+        match self.rng.gen_range(0..4) {
+            0 => self.scalar(),
+            1 => Value::Sequence(vec![self.well_formed(format)]),
+            _ => {
+                let Value::Mapping(mut input) = self.well_formed(format) else {
+                    unreachable!("well_formed always returns a mapping")
+                };
+                for part in &format.parts {
+                    match self.rng.gen_range(0..3) {
+                        0 => {
+                            input.remove(part.key.as_str());
+                        }
+                        1 => {
+                            let value = self.any_value(2);
+                            input.insert(part.key.clone().into(), value);
+                        }
+                        _ => {}
+                    }
+                }
+                if self.rng.gen_bool(0.3) {
+                    let value = self.any_value(2);
+                    input.insert("unexpected_field".into(), value);
+                }
+                Value::Mapping(input)
+            }
+        }
+    }
+
+    fn value_for(&mut self, part: &FormatPart) -> Value {
+        match &part.allowed_values {
+            Some(values) if !values.is_empty() && self.rng.gen_bool(0.9) => {
+                values.choose(&mut self.rng).unwrap().clone().into()
+            }
+            _ => self.scalar(),
+        }
+    }
+
+    fn scalar(&mut self) -> Value {
+        match self.rng.gen_range(0..6) {
+            0 => Value::Null,
+            1 => self.rng.gen::<bool>().into(),
+            2 => self.rng.gen_range(-1_000_000i64..1_000_000).into(),
+            3 => (self.rng.gen::<f64>() * 1000.0).into(),
+            4 => String::new().into(),
+            _ => self.string().into(),
+        }
+    }
+
+    fn any_value(&mut self, depth: usize) -> Value {
+        if depth == 0 {
+            return self.scalar();
+        }
+        match self.rng.gen_range(0..4) {
+            0 => Value::Sequence(
+                (0..self.rng.gen_range(0..4))
+                    .map(|_| self.any_value(depth - 1))
+                    .collect(),
+            ),
+            1 => {
+                let mut mapping = Mapping::new();
+                for _ in 0..self.rng.gen_range(0..4) {
+                    let key = self.string();
+                    let value = self.any_value(depth - 1);
+                    mapping.insert(key.into(), value);
+                }
+                Value::Mapping(mapping)
+            }
+            _ => self.scalar(),
+        }
+    }
+
+    fn string(&mut self) -> String {
+        const SAMPLES: &[&str] = &[
+            "hello",
+            "Hello, World!",
+            "   ",
+            "\n",
+            "-1",
+            "0",
+            "NaN",
+            "null",
+            "~",
+            "../../etc/passwd",
+            "ü 日本語 🙂",
+            "{{ placeholder }}",
+            "'quoted' \"text\"",
+        ];
+        if self.rng.gen_bool(0.7) {
+            SAMPLES.choose(&mut self.rng).unwrap().to_string()
+        } else {
+            let len = self.rng.gen_range(0..64);
+            (0..len).map(|_| self.rng.gen::<char>()).collect()
+        }
+    }
+}
+
+/// Invokes `tool` with `runs` random inputs generated from its input format, and reports how it
+/// handled them.
+///
+/// The same `seed` always produces the same inputs, so failures can be reproduced.
+pub async fn fuzz_tool<T: Tool + Sync>(tool: &T, runs: usize, seed: u64) -> FuzzReport {
+    let format = tool.description().input_format;
+    let mut generator = InputGenerator::new(seed);
+    let mut report = FuzzReport::default();
+    for _ in 0..runs {
+        let input = generator.generate(&format);
+        match AssertUnwindSafe(tool.invoke(input.clone()))
+            .catch_unwind()
+            .await
+        {
+            Ok(Ok(_)) => report.ok += 1,
+            Ok(Err(_)) => report.err += 1,
+            Err(panic) => report.panics.push(FuzzPanic {
+                input,
+                message: panic_message(&*panic),
+            }),
+        }
+    }
+    report
+}
+
+/// Fuzzes `tool` like `fuzz_tool` and panics, listing the offending inputs, if it ever panicked.
+///
+/// # Example
+///
+/// ```no_run
+/// use llm_chain::tools::{testing::assert_tool_never_panics, tools::BashTool};
+/// # async fn run() {
+/// assert_tool_never_panics(&BashTool::new(), 100).await;
+/// # }
+/// ```
+pub async fn assert_tool_never_panics<T: Tool + Sync>(tool: &T, runs: usize) {
+    let report = fuzz_tool(tool, runs, 0).await;
+    if !report.panics.is_empty() {
+        let details = report
+            .panics
+            .iter()
+            .map(|p| {
+                format!(
+                    "{}\n  with input: {}",
+                    p.message,
+                    serde_yaml::to_string(&p.input).unwrap_or_default().trim()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        panic!(
+            "{} panicked on {} of {} inputs:\n{}",
+            tool.description().name,
+            report.panics.len(),
+            report.runs(),
+            details
+        );
+    }
+}
+
+/// Set this environment variable to write the current formats to their snapshot files instead of
+/// comparing them.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "LLM_CHAIN_UPDATE_SNAPSHOTS";
+
+/// Compares `format` with the snapshot at `path`, panicking with the differences if they don't
+/// match.
+///
+/// The snapshot is written instead if the `LLM_CHAIN_UPDATE_SNAPSHOTS` environment variable is set.
+/// Use [`assert_tool_schema_stable!`](crate::assert_tool_schema_stable) rather than calling this
+/// directly.
+pub fn assert_format_snapshot(format: &Format, path: &Path) {
+    let current = serde_json::to_string_pretty(&format.parts).expect("formats serialize to JSON");
+    if std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_some() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).expect("can create the snapshot directory");
+        }
+        std::fs::write(path, current + "\n").expect("can write the snapshot");
+        return;
+    }
+    let snapshot = std::fs::read_to_string(path).unwrap_or_else(|_| {
+        panic!(
+            "No schema snapshot at {}, run the test with {}=1 to create it",
+            path.display(),
+            UPDATE_SNAPSHOTS_ENV
+        )
+    });
+    let parts: Vec<FormatPart> = serde_json::from_str(&snapshot)
+        .unwrap_or_else(|e| panic!("Invalid schema snapshot at {}: {}", path.display(), e));
+    let snapshot = Format::new(parts);
+    let diff = snapshot.diff(format);
+    if diff.is_empty() {
+        return;
+    }
+
+    let mut report = vec![];
+    for key in &diff.added {
+        report.push(format!("+ {}", key));
+    }
+    for key in &diff.removed {
+        report.push(format!("- {}", key));
+    }
+    for key in &diff.changed {
+        let describe = |format: &Format| {
+            format
+                .parts
+                .iter()
+                .find(|p| &p.key == key)
+                .map(|p| p.describe_purpose().into_owned())
+                .unwrap_or_default()
+        };
+        report.push(format!(
+            "~ {}\n    was: {}\n    now: {}",
+            key,
+            describe(&snapshot),
+            describe(format)
+        ));
+    }
+    panic!(
+        "The schema no longer matches the snapshot at {}:\n{}\nIf the change is intended, run the test with {}=1 to update the snapshot.",
+        path.display(),
+        report.join("\n"),
+        UPDATE_SNAPSHOTS_ENV
+    );
+}
+
+/// Asserts that the `Format` of a type implementing `Describe` matches a snapshot committed with
+/// the tests, to catch accidental changes to what the model sees.
+///
+/// The snapshot path is relative to the crate's manifest directory. Set the
+/// `LLM_CHAIN_UPDATE_SNAPSHOTS` environment variable to create or update the snapshot.
+///
+/// # Example
+///
+/// ```no_run
+/// use llm_chain::assert_tool_schema_stable;
+/// use llm_chain::tools::tools::BashToolInput;
+///
+/// assert_tool_schema_stable!(BashToolInput, "tests/snapshots/bash_input.json");
+/// ```
+#[macro_export]
+macro_rules! assert_tool_schema_stable {
+    ($ty:ty, $path:expr) => {
+        $crate::tools::testing::assert_format_snapshot(
+            &<$ty as $crate::tools::Describe>::describe(),
+            &::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join($path),
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{Describe, ToolDescription, ToolError};
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+    use thiserror::Error;
+
+    #[derive(Serialize, Deserialize)]
+    struct CountInput {
+        count: String,
+    }
+
+    impl Describe for CountInput {
+        fn describe() -> Format {
+            vec![("count", "How many").into()].into()
+        }
+    }
+
+    #[derive(Debug, Error)]
+    #[error(transparent)]
+    struct CountError(#[from] serde_yaml::Error);
+
+    impl ToolError for CountError {}
+
+    struct UnwrappingTool;
+
+    #[async_trait]
+    impl Tool for UnwrappingTool {
+        type Input = CountInput;
+        type Output = usize;
+        type Error = CountError;
+
+        async fn invoke_typed(&self, input: &CountInput) -> Result<usize, CountError> {
+            Ok(input.count.parse().unwrap())
+        }
+
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "Count",
+                "Counts",
+                "Count",
+                CountInput::describe(),
+                vec![].into(),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fuzz_tool_finds_panics() {
+        let report = fuzz_tool(&UnwrappingTool, 50, 7).await;
+        assert_eq!(report.runs(), 50);
+        assert!(report.err > 0);
+        assert!(!report.panics.is_empty());
+    }
+
+    #[test]
+    fn test_format_snapshot_reports_changes() {
+        let path =
+            std::env::temp_dir().join(format!("llm-chain-snapshot-{}.json", std::process::id()));
+        let snapshot: Format = vec![("count", "How many").into()].into();
+        std::fs::write(&path, serde_json::to_string(&snapshot.parts).unwrap()).unwrap();
+        assert_format_snapshot(&snapshot, &path);
+
+        let changed: Format = vec![
+            ("count", "How many items").into(),
+            ("unit", "The unit").into(),
+        ]
+        .into();
+        let panic =
+            std::panic::catch_unwind(|| assert_format_snapshot(&changed, &path)).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.contains("+ unit"));
+        assert!(message.contains("~ count\n    was: How many\n    now: How many items"));
+    }
+}
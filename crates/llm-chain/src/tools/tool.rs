@@ -4,7 +4,15 @@ use async_trait::async_trait;
 use serde::{de::DeserializeOwned, Serialize};
 
 /// Marker trait for Tool errors. It is needed so the concrete Errors can have a derived `From<ToolError>`
-pub trait ToolError {}
+pub trait ToolError {
+    /// Returns a question to ask if the tool failed because its input was missing information.
+    ///
+    /// Agents that support it ask the question and invoke the tool again with the answer added to
+    /// the input, instead of failing. See the self ask with search agent's `resume`.
+    fn need_more_info(&self) -> Option<&str> {
+        None
+    }
+}
 
 /// The `Tool` trait defines an interface for tools that can be added to a `ToolCollection`.
 ///
@@ -34,6 +42,11 @@ pub trait Tool {
         Ok(serde_yaml::to_value(output)?)
     }
 
+    /// Checks that `input` deserializes into the tool's input, without invoking the tool.
+    fn check_input(&self, input: &serde_yaml::Value) -> Result<(), serde_yaml::Error> {
+        serde_yaml::from_value::<Self::Input>(input.clone()).map(|_| ())
+    }
+
     /// Checks whether the tool matches the given name.
     ///
     /// This function is used to find the appropriate tool in a `ToolCollection` based on its name.
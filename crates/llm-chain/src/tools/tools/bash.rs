@@ -1,3 +1,4 @@
+use super::process;
 use crate::tools::description::{Describe, Format, ToolDescription};
 use crate::tools::tool::{Tool, ToolError};
 use async_trait::async_trait;
@@ -53,15 +54,15 @@ impl Describe for BashToolOutput {
 
 #[derive(Debug, Error)]
 pub enum BashToolError {
-    #[error(transparent)]
+    #[error("Invalid YAML: {0}")]
     YamlError(#[from] serde_yaml::Error),
-    #[error(transparent)]
+    #[error("Failed to run the command: {0}")]
     IOError(#[from] std::io::Error),
     #[error("Type `isize` overflowed when reading output status code {0}")]
     OutputStatusCodeOverflow(#[from] TryFromIntError),
     #[error("Received a None status code, which means the program was exited by signal")]
     ProcessTerminatedBySignal,
-    #[error(transparent)]
+    #[error("The command output is not valid UTF-8: {0}")]
     FromUtf8Error(#[from] FromUtf8Error),
 }
 
@@ -73,7 +74,9 @@ impl Tool for BashTool {
     type Output = BashToolOutput;
     type Error = BashToolError;
     async fn invoke_typed(&self, input: &BashToolInput) -> Result<BashToolOutput, BashToolError> {
-        let output = Command::new("bash").arg("-c").arg(&input.cmd).output()?;
+        let mut command = Command::new("bash");
+        command.arg("-c").arg(&input.cmd);
+        let output = process::output(command).await?;
 
         Ok(BashToolOutput {
             status: output
@@ -96,3 +99,49 @@ impl Tool for BashTool {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Returns true if the process exists and hasn't exited.
+    #[cfg(target_os = "linux")]
+    fn is_running(pid: &str) -> bool {
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            Ok(stat) => !stat.contains(") Z "),
+            Err(_) => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_explains_invalid_input() {
+        let input = serde_yaml::from_str("command: ls").unwrap();
+        let error = BashTool::new().invoke(input).await.unwrap_err();
+        assert_eq!(error.to_string(), "Invalid YAML: missing field `cmd`");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_kills_command_when_cancelled() {
+        let pid_file =
+            std::env::temp_dir().join(format!("llm-chain-bash-{}", uuid::Uuid::new_v4()));
+        let input = BashToolInput {
+            cmd: format!("sleep 30 & echo $! > {}; wait", pid_file.display()),
+        };
+        let tool = BashTool::new();
+        let call = tokio::time::timeout(Duration::from_millis(500), tool.invoke_typed(&input));
+        assert!(call.await.is_err());
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        std::fs::remove_file(&pid_file).unwrap();
+        let pid = pid.trim();
+        for _ in 0..20 {
+            if !is_running(pid) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("The command was still running after the call was cancelled");
+    }
+}
@@ -88,9 +88,9 @@ struct BingSearchResult {
 pub enum BingSearchError {
     #[error("No search results were returned")]
     NoResults,
-    #[error(transparent)]
+    #[error("Invalid YAML: {0}")]
     Yaml(#[from] serde_yaml::Error),
-    #[error(transparent)]
+    #[error("The Bing search request failed: {0}")]
     Request(#[from] reqwest::Error),
 }
 
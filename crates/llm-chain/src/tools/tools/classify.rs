@@ -0,0 +1,196 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    options::Options,
+    parsing::find_yaml,
+    prompt::Prompt,
+    tools::{
+        describe_to_repair_prompt, Describe, Format, FormatPart, Tool, ToolDescription, ToolError,
+    },
+    traits::{Executor, ExecutorError},
+};
+
+struct Label {
+    name: String,
+    description: Option<String>,
+}
+
+/// A tool that asks the model to classify text into one of a fixed set of labels.
+///
+/// The model's answer is checked against the labels. If it is malformed or uses an unknown label,
+/// the model is asked once more before the tool fails.
+pub struct ClassifyTool<E: Executor> {
+    executor: E,
+    labels: Vec<Label>,
+}
+
+impl<E: Executor> ClassifyTool<E> {
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            labels: vec![],
+        }
+    }
+
+    /// Adds a label the text can be classified as.
+    pub fn with_label(mut self, name: &str) -> Self {
+        self.labels.push(Label {
+            name: name.to_string(),
+            description: None,
+        });
+        self
+    }
+
+    /// Adds a label with a description of the texts it applies to.
+    pub fn with_described_label(mut self, name: &str, description: &str) -> Self {
+        self.labels.push(Label {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+        });
+        self
+    }
+
+    fn output_format(&self) -> Format {
+        let names: Vec<&str> = self.labels.iter().map(|l| l.name.as_str()).collect();
+        let mut format = ClassifyToolOutput::describe();
+        format.parts[0] = FormatPart::one_of("label", "The label that fits the text best", &names);
+        format
+    }
+
+    fn classification_prompt(&self, text: &str) -> String {
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| match &label.description {
+                Some(description) => format!("- {}: {}", label.name, description),
+                None => format!("- {}", label.name),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let fields = serde_yaml::to_string(&self.output_format()).unwrap_or_default();
+        format!(
+            "Classify the text below with one of these labels:
+{}
+
+Text:
+{}
+
+Respond with YAML containing these fields:
+{}",
+            labels, text, fields
+        )
+    }
+
+    /// Parses the model's answer, returning the canonical spelling of the label.
+    fn parse_response(&self, response: &str) -> Option<ClassifyToolOutput> {
+        let output = find_yaml::<ClassifyToolOutput>(response)
+            .ok()
+            .and_then(|outputs| outputs.into_iter().next())
+            .or_else(|| serde_yaml::from_str(response).ok())?;
+        let label = self
+            .labels
+            .iter()
+            .find(|l| l.name.eq_ignore_ascii_case(output.label.trim()))?;
+        Some(ClassifyToolOutput {
+            label: label.name.clone(),
+            ..output
+        })
+    }
+
+    async fn complete(&self, prompt: String) -> Result<String, ClassifyToolError> {
+        self.executor
+            .execute(Options::empty(), &Prompt::text(prompt))
+            .await?
+            .to_immediate()
+            .await?
+            .as_content()
+            .extract_last_body()
+            .cloned()
+            .ok_or(ClassifyToolError::NoChoicesReturned)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ClassifyToolInput {
+    pub text: String,
+}
+
+impl Describe for ClassifyToolInput {
+    fn describe() -> Format {
+        vec![("text", "The text to classify").into()].into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClassifyToolOutput {
+    pub label: String,
+    pub confidence_rationale: String,
+}
+
+impl Describe for ClassifyToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("label", "The label that fits the text best").into(),
+            (
+                "confidence_rationale",
+                "Why the label fits, and how confident you are",
+            )
+                .into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ClassifyToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("The model call failed: {0}")]
+    ExecutorError(#[from] ExecutorError),
+    #[error("Model response was empty or contained no choices")]
+    NoChoicesReturned,
+    #[error("The model did not answer with a known label: {0}")]
+    InvalidResponse(String),
+}
+
+impl ToolError for ClassifyToolError {}
+
+#[async_trait]
+impl<E> Tool for ClassifyTool<E>
+where
+    E: Executor + Send + Sync,
+{
+    type Input = ClassifyToolInput;
+
+    type Output = ClassifyToolOutput;
+
+    type Error = ClassifyToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let prompt = self.classification_prompt(&input.text);
+        let response = self.complete(prompt.clone()).await?;
+        if let Some(output) = self.parse_response(&response) {
+            return Ok(output);
+        }
+        let retry_prompt = format!(
+            "{}\n\n{}",
+            prompt,
+            describe_to_repair_prompt(&self.output_format(), &response)
+        );
+        let response = self.complete(retry_prompt).await?;
+        self.parse_response(&response)
+            .ok_or(ClassifyToolError::InvalidResponse(response))
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "ClassifyTool",
+            "A tool that classifies text into one of a fixed set of labels.",
+            "Use this to decide which category a piece of text belongs to.",
+            ClassifyToolInput::describe(),
+            self.output_format(),
+        )
+    }
+}
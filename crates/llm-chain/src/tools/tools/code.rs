@@ -0,0 +1,197 @@
+use crate::tools::description::{Describe, Format, FormatPart, ToolDescription};
+use crate::tools::tool::{Tool, ToolError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::num::TryFromIntError;
+use std::string::FromUtf8Error;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::process::Command;
+
+/// The interpreter used to run code in one language.
+#[derive(Clone, Debug)]
+struct Interpreter {
+    path: String,
+    eval_flag: String,
+}
+
+/// A tool that executes code in one of several languages.
+///
+/// Python, Node, Ruby and Bash are supported out of the box. Each language runs with the interpreter
+/// found on the `PATH`, which can be changed with `with_interpreter_path`.
+pub struct CodeTool {
+    interpreters: BTreeMap<String, Interpreter>,
+    timeout: Option<Duration>,
+    dry_run: bool,
+}
+
+impl CodeTool {
+    pub fn new() -> Self {
+        CodeTool {
+            interpreters: BTreeMap::new(),
+            timeout: None,
+            dry_run: false,
+        }
+        .with_language("python", "python3", "-c")
+        .with_language("node", "node", "-e")
+        .with_language("ruby", "ruby", "-e")
+        .with_language("bash", "bash", "-c")
+    }
+
+    /// Adds a language, run by passing the code to `path` after `eval_flag`.
+    pub fn with_language(mut self, language: &str, path: &str, eval_flag: &str) -> Self {
+        self.interpreters.insert(
+            language.to_string(),
+            Interpreter {
+                path: path.to_string(),
+                eval_flag: eval_flag.to_string(),
+            },
+        );
+        self
+    }
+
+    /// Changes the interpreter used for an already supported language.
+    pub fn with_interpreter_path(mut self, language: &str, path: &str) -> Self {
+        if let Some(interpreter) = self.interpreters.get_mut(language) {
+            interpreter.path = path.to_string();
+        }
+        self
+    }
+
+    /// Kills the interpreter if the code runs for longer than `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the command that would be run instead of running it.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+impl Default for CodeTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CodeToolInput {
+    language: String,
+    code: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CodeToolOutput {
+    stderr: String,
+    stdout: String,
+    status: isize,
+}
+
+impl Describe for CodeToolInput {
+    fn describe() -> Format {
+        vec![
+            ("language", "The language the code is written in.").into(),
+            ("code", "The code to execute.").into(),
+        ]
+        .into()
+    }
+}
+
+impl Describe for CodeToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("status", "Exit code 0 == success").into(),
+            ("stderr", "The stderr output of the code").into(),
+            ("stdout", "The stdout output of the code").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CodeToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("Failed to run the code: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("Unsupported language \"{0}\"")]
+    UnsupportedLanguage(String),
+    #[error("The code did not finish within {0:?}")]
+    Timeout(Duration),
+    #[error("Type `isize` overflowed when reading output status code {0}")]
+    OutputStatusCodeOverflow(#[from] TryFromIntError),
+    #[error("Received a None status code, which means the program was exited by signal")]
+    ProcessTerminatedBySignal,
+    #[error("The program output is not valid UTF-8: {0}")]
+    FromUtf8Error(#[from] FromUtf8Error),
+}
+
+impl ToolError for CodeToolError {}
+
+#[async_trait]
+impl Tool for CodeTool {
+    type Input = CodeToolInput;
+    type Output = CodeToolOutput;
+    type Error = CodeToolError;
+
+    async fn invoke_typed(&self, input: &CodeToolInput) -> Result<CodeToolOutput, CodeToolError> {
+        let interpreter = self
+            .interpreters
+            .get(&input.language.to_lowercase())
+            .ok_or_else(|| CodeToolError::UnsupportedLanguage(input.language.clone()))?;
+
+        if self.dry_run {
+            return Ok(CodeToolOutput {
+                stderr: String::new(),
+                stdout: format!(
+                    "{} {} {:?}",
+                    interpreter.path, interpreter.eval_flag, input.code
+                ),
+                status: 0,
+            });
+        }
+
+        let mut command = Command::new(&interpreter.path);
+        command
+            .arg(&interpreter.eval_flag)
+            .arg(&input.code)
+            .kill_on_drop(true);
+        let output = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, command.output())
+                .await
+                .map_err(|_| CodeToolError::Timeout(timeout))??,
+            None => command.output().await?,
+        };
+
+        Ok(CodeToolOutput {
+            status: output
+                .status
+                .code()
+                .ok_or(CodeToolError::ProcessTerminatedBySignal)?
+                .try_into()?,
+            stderr: String::from_utf8(output.stderr)?,
+            stdout: String::from_utf8(output.stdout)?,
+        })
+    }
+
+    fn description(&self) -> ToolDescription {
+        let languages: Vec<&str> = self.interpreters.keys().map(|l| l.as_str()).collect();
+        let mut input_format = CodeToolInput::describe();
+        input_format.parts[0] = FormatPart::one_of(
+            "language",
+            "The language the code is written in.",
+            &languages,
+        );
+        ToolDescription::new(
+            "CodeTool",
+            "A tool that executes code in one of several languages.",
+            "Use this to run code to solve your goals",
+            input_format,
+            CodeToolOutput::describe(),
+        )
+    }
+}
@@ -0,0 +1,216 @@
+use async_trait::async_trait;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::string::FromUtf8Error;
+use thiserror::Error;
+
+use crate::tools::{Describe, Format, FormatPart, Tool, ToolDescription, ToolError};
+
+/// A tool that encodes and decodes base64, hex, URL encoding and JWTs.
+///
+/// JWTs are only decoded, their signature is not verified.
+pub struct CodecTool {}
+
+impl CodecTool {
+    pub fn new() -> Self {
+        CodecTool {}
+    }
+}
+
+impl Default for CodecTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CodecOperation {
+    Base64Encode,
+    Base64Decode,
+    HexEncode,
+    HexDecode,
+    UrlEncode,
+    UrlDecode,
+    JwtDecode,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CodecToolInput {
+    pub operation: CodecOperation,
+    pub input: String,
+}
+
+impl Describe for CodecToolInput {
+    fn describe() -> Format {
+        vec![
+            FormatPart::one_of(
+                "operation",
+                "The transformation to apply to the input",
+                &[
+                    "base64_encode",
+                    "base64_decode",
+                    "hex_encode",
+                    "hex_decode",
+                    "url_encode",
+                    "url_decode",
+                    "jwt_decode",
+                ],
+            ),
+            ("input", "The string to transform").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CodecToolOutput {
+    pub output: String,
+}
+
+impl Describe for CodecToolOutput {
+    fn describe() -> Format {
+        vec![(
+            "output",
+            "The transformed string. Decoded JWTs are JSON with a header and a payload",
+        )
+            .into()]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CodecToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("Invalid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("Invalid hex: {0}")]
+    Hex(String),
+    #[error("Invalid URL encoding: {0}")]
+    Url(String),
+    #[error("Invalid JWT: {0}")]
+    Jwt(String),
+    #[error("The decoded bytes are not valid UTF-8: {0}")]
+    Utf8(#[from] FromUtf8Error),
+}
+
+impl ToolError for CodecToolError {}
+
+fn hex_encode(input: &str) -> String {
+    input.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_decode(input: &str) -> Result<String, CodecToolError> {
+    let digits: Vec<u8> = input
+        .trim()
+        .trim_start_matches("0x")
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    let pairs = digits.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(CodecToolError::Hex("odd number of digits".to_string()));
+    }
+    let bytes = pairs
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| {
+                    CodecToolError::Hex(format!("\"{}\"", String::from_utf8_lossy(pair)))
+                })
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986.
+fn url_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn url_decode(input: &str) -> Result<String, CodecToolError> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut iter = input.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'%' => {
+                let pair = [iter.next(), iter.next()];
+                let byte = match pair {
+                    [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                        .ok()
+                        .and_then(|pair| u8::from_str_radix(pair, 16).ok()),
+                    _ => None,
+                };
+                bytes.push(byte.ok_or_else(|| {
+                    CodecToolError::Url("'%' must be followed by two hex digits".to_string())
+                })?);
+            }
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(b),
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn jwt_decode(input: &str) -> Result<String, CodecToolError> {
+    let parts: Vec<&str> = input.trim().split('.').collect();
+    if parts.len() != 3 {
+        return Err(CodecToolError::Jwt(format!(
+            "expected 3 dot-separated parts, found {}",
+            parts.len()
+        )));
+    }
+    let decode_part = |part: &str| -> Result<serde_json::Value, CodecToolError> {
+        let bytes = URL_SAFE_NO_PAD.decode(part.trim_end_matches('='))?;
+        serde_json::from_slice(&bytes).map_err(|e| CodecToolError::Jwt(e.to_string()))
+    };
+    let decoded = serde_json::json!({
+        "header": decode_part(parts[0])?,
+        "payload": decode_part(parts[1])?,
+    });
+    serde_json::to_string_pretty(&decoded).map_err(|e| CodecToolError::Jwt(e.to_string()))
+}
+
+#[async_trait]
+impl Tool for CodecTool {
+    type Input = CodecToolInput;
+
+    type Output = CodecToolOutput;
+
+    type Error = CodecToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let text = &input.input;
+        let output = match input.operation {
+            CodecOperation::Base64Encode => STANDARD.encode(text),
+            CodecOperation::Base64Decode => String::from_utf8(STANDARD.decode(text.trim())?)?,
+            CodecOperation::HexEncode => hex_encode(text),
+            CodecOperation::HexDecode => hex_decode(text)?,
+            CodecOperation::UrlEncode => url_encode(text),
+            CodecOperation::UrlDecode => url_decode(text)?,
+            CodecOperation::JwtDecode => jwt_decode(text)?,
+        };
+        Ok(CodecToolOutput { output })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "CodecTool",
+            "A tool that encodes and decodes base64, hex and URL encoding, and decodes JWTs without verifying them.",
+            "Use this to decode tokens and encoded values, or to encode values.",
+            CodecToolInput::describe(),
+            CodecToolOutput::describe(),
+        )
+    }
+}
@@ -0,0 +1,181 @@
+use crate::tools::description::{Describe, Format, ToolDescription};
+use crate::tools::tool::{Tool, ToolError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+
+/// The default number of rows returned by the `CsvTool`.
+const DEFAULT_MAX_ROWS: usize = 1000;
+
+/// A tool that parses CSV data, either from a file or given inline.
+pub struct CsvTool {
+    max_rows: usize,
+}
+
+impl CsvTool {
+    pub fn new() -> Self {
+        CsvTool {
+            max_rows: DEFAULT_MAX_ROWS,
+        }
+    }
+
+    /// Sets the maximum number of rows returned. Any further rows are dropped and the output is
+    /// marked as truncated.
+    pub fn with_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+}
+
+impl Default for CsvTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CsvToolInput {
+    path_or_content: String,
+    #[serde(default = "default_has_header")]
+    has_header: bool,
+    #[serde(default)]
+    delimiter: Option<String>,
+}
+
+fn default_has_header() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CsvToolOutput {
+    columns: Vec<String>,
+    rows: Vec<serde_yaml::Value>,
+    truncated: bool,
+}
+
+impl Describe for CsvToolInput {
+    fn describe() -> Format {
+        vec![
+            (
+                "path_or_content",
+                "The path to a CSV file, or the CSV data itself",
+            )
+                .into(),
+            (
+                "has_header",
+                "Whether the first row holds the column names, defaults to true",
+            )
+                .into(),
+            (
+                "delimiter",
+                "The character separating fields, defaults to ','",
+            )
+                .into(),
+        ]
+        .into()
+    }
+}
+
+impl Describe for CsvToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("columns", "The column names, empty if there is no header").into(),
+            ("rows", "The rows, as maps from column name to value if there is a header, otherwise as lists of values").into(),
+            ("truncated", "Whether rows were left out because there were too many").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CsvToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("Failed to read the CSV file: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("Invalid CSV: {0}")]
+    CsvError(#[from] ::csv::Error),
+    #[error("The delimiter must be a single ASCII character, got \"{0}\"")]
+    InvalidDelimiter(String),
+}
+
+impl ToolError for CsvToolError {}
+
+impl CsvTool {
+    fn parse(&self, data: &str, input: &CsvToolInput) -> Result<CsvToolOutput, CsvToolError> {
+        let delimiter = match input.delimiter.as_deref() {
+            None => b',',
+            Some("\\t") => b'\t',
+            Some(d) if d.len() == 1 && d.is_ascii() => d.as_bytes()[0],
+            Some(d) => return Err(CsvToolError::InvalidDelimiter(d.to_string())),
+        };
+        let mut reader = ::csv::ReaderBuilder::new()
+            .has_headers(input.has_header)
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_reader(data.as_bytes());
+
+        let columns: Vec<String> = if input.has_header {
+            reader.headers()?.iter().map(|h| h.to_string()).collect()
+        } else {
+            vec![]
+        };
+        let mut rows = vec![];
+        let mut truncated = false;
+        for record in reader.records() {
+            if rows.len() == self.max_rows {
+                truncated = true;
+                break;
+            }
+            let record = record?;
+            let row = if input.has_header {
+                let mut map = serde_yaml::Mapping::new();
+                for (idx, value) in record.iter().enumerate() {
+                    let key = columns
+                        .get(idx)
+                        .cloned()
+                        .unwrap_or_else(|| format!("column_{}", idx + 1));
+                    map.insert(key.into(), value.into());
+                }
+                serde_yaml::Value::Mapping(map)
+            } else {
+                record.iter().map(serde_yaml::Value::from).collect()
+            };
+            rows.push(row);
+        }
+        Ok(CsvToolOutput {
+            columns,
+            rows,
+            truncated,
+        })
+    }
+}
+
+#[async_trait]
+impl Tool for CsvTool {
+    type Input = CsvToolInput;
+    type Output = CsvToolOutput;
+    type Error = CsvToolError;
+
+    async fn invoke_typed(&self, input: &CsvToolInput) -> Result<CsvToolOutput, CsvToolError> {
+        let source = input.path_or_content.trim();
+        let is_path = !source.contains('\n') && Path::new(source).is_file();
+        let data = if is_path {
+            tokio::fs::read_to_string(source).await?
+        } else {
+            input.path_or_content.clone()
+        };
+        self.parse(&data, input)
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "CsvTool",
+            "A tool that parses CSV data into rows.",
+            "Use this to read CSV files or CSV data",
+            CsvToolInput::describe(),
+            CsvToolOutput::describe(),
+        )
+    }
+}
@@ -0,0 +1,242 @@
+use async_trait::async_trait;
+use difflib::sequencematcher::SequenceMatcher;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::tools::{Describe, Format, FormatPart, Tool, ToolDescription, ToolError};
+
+/// The number of unchanged lines shown around each change in a unified diff.
+const CONTEXT_LINES: usize = 3;
+
+/// A tool that compares two versions of a text.
+///
+/// The diff is returned either as a unified diff, like `diff -u`, or as lists of added, removed
+/// and changed lines or words.
+pub struct DiffTool {}
+
+impl DiffTool {
+    pub fn new() -> Self {
+        DiffTool {}
+    }
+}
+
+impl Default for DiffTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffFormat {
+    #[default]
+    Unified,
+    Structured,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffGranularity {
+    #[default]
+    Line,
+    Word,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DiffToolInput {
+    pub before: String,
+    pub after: String,
+    #[serde(default)]
+    pub format: DiffFormat,
+    #[serde(default)]
+    pub granularity: DiffGranularity,
+}
+
+impl Describe for DiffToolInput {
+    fn describe() -> Format {
+        vec![
+            ("before", "The original text").into(),
+            ("after", "The changed text").into(),
+            FormatPart::one_of(
+                "format",
+                "How to return the diff: a unified diff, or lists of added, removed and changed parts. Defaults to unified",
+                &["unified", "structured"],
+            ),
+            FormatPart::one_of(
+                "granularity",
+                "Whether to compare lines or words. Defaults to line",
+                &["line", "word"],
+            ),
+        ]
+        .into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiffChange {
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct DiffToolOutput {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub removed: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed: Option<Vec<DiffChange>>,
+}
+
+impl Describe for DiffToolOutput {
+    fn describe() -> Format {
+        vec![
+            (
+                "diff",
+                "The unified diff, for the unified format. Empty if the texts are the same",
+            )
+                .into(),
+            ("added", "The parts only in the changed text, for the structured format").into(),
+            (
+                "removed",
+                "The parts only in the original text, for the structured format",
+            )
+                .into(),
+            (
+                "changed",
+                "The parts that were replaced, each with its `before` and `after`, for the structured format",
+            )
+                .into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DiffToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+}
+
+impl ToolError for DiffToolError {}
+
+fn split(text: &str, granularity: DiffGranularity) -> Vec<&str> {
+    match granularity {
+        DiffGranularity::Line => text.lines().collect(),
+        DiffGranularity::Word => text.split_whitespace().collect(),
+    }
+}
+
+fn unified_diff(before: &[&str], after: &[&str]) -> String {
+    difflib::unified_diff(before, after, "before", "after", "", "", CONTEXT_LINES)
+        .iter()
+        // Header lines end in a newline, and the file headers in a tab before the empty date.
+        .map(|line| match line.strip_suffix('\n') {
+            Some(header) => header.trim_end_matches('\t'),
+            None => line.as_str(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn structured_diff(before: &[&str], after: &[&str], separator: &str) -> DiffToolOutput {
+    let (mut added, mut removed, mut changed) = (vec![], vec![], vec![]);
+    let mut matcher = SequenceMatcher::new(before, after);
+    for opcode in matcher.get_opcodes() {
+        let old = before[opcode.first_start..opcode.first_end].join(separator);
+        let new = after[opcode.second_start..opcode.second_end].join(separator);
+        match opcode.tag.as_str() {
+            "insert" => added.push(new),
+            "delete" => removed.push(old),
+            "replace" => changed.push(DiffChange {
+                before: old,
+                after: new,
+            }),
+            _ => {}
+        }
+    }
+    DiffToolOutput {
+        added: Some(added),
+        removed: Some(removed),
+        changed: Some(changed),
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl Tool for DiffTool {
+    type Input = DiffToolInput;
+
+    type Output = DiffToolOutput;
+
+    type Error = DiffToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let before = split(&input.before, input.granularity);
+        let after = split(&input.after, input.granularity);
+        Ok(match input.format {
+            DiffFormat::Unified => DiffToolOutput {
+                diff: Some(unified_diff(&before, &after)),
+                ..Default::default()
+            },
+            DiffFormat::Structured => {
+                let separator = match input.granularity {
+                    DiffGranularity::Line => "\n",
+                    DiffGranularity::Word => " ",
+                };
+                structured_diff(&before, &after, separator)
+            }
+        })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "DiffTool",
+            "A tool that compares two versions of a text and reports what changed.",
+            "Use this to review edits to code or documents.",
+            DiffToolInput::describe(),
+            DiffToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_diffs_lines_and_words() {
+        let input = |format, granularity| DiffToolInput {
+            before: "one\ntwo\nthree\nfour".to_string(),
+            after: "one\n2\nthree\nfour\nfive".to_string(),
+            format,
+            granularity,
+        };
+        let tool = DiffTool::new();
+
+        let unified = tool
+            .invoke_typed(&input(DiffFormat::Unified, DiffGranularity::Line))
+            .await
+            .unwrap();
+        assert_eq!(
+            unified.diff.unwrap(),
+            "--- before\n+++ after\n@@ -1,4 +1,5 @@\n one\n-two\n+2\n three\n four\n+five"
+        );
+
+        let structured = tool
+            .invoke_typed(&input(DiffFormat::Structured, DiffGranularity::Word))
+            .await
+            .unwrap();
+        assert_eq!(structured.added.unwrap(), vec!["five"]);
+        assert_eq!(structured.removed.unwrap(), Vec::<String>::new());
+        assert_eq!(
+            structured.changed.unwrap(),
+            vec![DiffChange {
+                before: "two".to_string(),
+                after: "2".to_string(),
+            }]
+        );
+    }
+}
@@ -44,9 +44,9 @@ impl Describe for ExitToolOutput {
 
 #[derive(Debug, Error)]
 pub enum ExitToolError {
-    #[error(transparent)]
+    #[error("Invalid YAML: {0}")]
     YamlError(#[from] serde_yaml::Error),
-    #[error(transparent)]
+    #[error("I/O error: {0}")]
     IOError(#[from] std::io::Error),
 }
 
@@ -0,0 +1,276 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::tools::{Describe, Format, FormatPart, Tool, ToolDescription, ToolError};
+
+/// The public Nominatim instance, which allows at most one request per second.
+const NOMINATIM_URL: &str = "https://nominatim.openstreetmap.org";
+
+/// A place and its coordinates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeocodeResult {
+    pub lat: f64,
+    pub lon: f64,
+    pub display_name: String,
+}
+
+/// A geocoding service used by the `GeocodeTool`.
+///
+/// Implement this to use a commercial geocoder instead of Nominatim.
+#[async_trait]
+pub trait GeocodingProvider: Send + Sync {
+    /// Finds the coordinates of the place best matching `query`.
+    async fn forward(&self, query: &str) -> Result<GeocodeResult, GeocodeToolError>;
+
+    /// Finds the place at the given coordinates.
+    async fn reverse(&self, lat: f64, lon: f64) -> Result<GeocodeResult, GeocodeToolError>;
+}
+
+/// Geocodes with an OpenStreetMap Nominatim server, the public one by default.
+///
+/// Requests are spaced out by at least `min_interval` (one second by default) to follow the
+/// public instance's usage policy, which also asks for an identifying user agent.
+pub struct NominatimProvider {
+    base_url: String,
+    user_agent: String,
+    min_interval: Duration,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl NominatimProvider {
+    pub fn new() -> Self {
+        Self {
+            base_url: NOMINATIM_URL.to_string(),
+            user_agent: concat!("llm-chain/", env!("CARGO_PKG_VERSION")).to_string(),
+            min_interval: Duration::from_secs(1),
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// Uses a self-hosted Nominatim server.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Identifies your application to the server, as the public instance asks.
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Sets the minimum time between two requests.
+    pub fn with_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    async fn get(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<serde_json::Value, GeocodeToolError> {
+        let mut last_request = self.last_request.lock().await;
+        if let Some(last) = *last_request {
+            tokio::time::sleep_until(last + self.min_interval).await;
+        }
+        *last_request = Some(Instant::now());
+        let response = reqwest::Client::new()
+            .get(format!("{}/{}", self.base_url, path))
+            .query(&[("format", "jsonv2")])
+            .query(query)
+            .header(reqwest::header::USER_AGENT, &self.user_agent)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response)
+    }
+}
+
+impl Default for NominatimProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Deserialize)]
+struct NominatimPlace {
+    lat: String,
+    lon: String,
+    display_name: String,
+}
+
+impl TryFrom<NominatimPlace> for GeocodeResult {
+    type Error = GeocodeToolError;
+
+    fn try_from(place: NominatimPlace) -> Result<Self, Self::Error> {
+        let parse = |value: &str| {
+            value
+                .parse()
+                .map_err(|_| GeocodeToolError::InvalidCoordinate(value.to_string()))
+        };
+        Ok(GeocodeResult {
+            lat: parse(&place.lat)?,
+            lon: parse(&place.lon)?,
+            display_name: place.display_name,
+        })
+    }
+}
+
+#[async_trait]
+impl GeocodingProvider for NominatimProvider {
+    async fn forward(&self, query: &str) -> Result<GeocodeResult, GeocodeToolError> {
+        let response = self
+            .get("search", &[("q", query.to_string()), ("limit", "1".into())])
+            .await?;
+        let places: Vec<NominatimPlace> = serde_json::from_value(response)?;
+        places
+            .into_iter()
+            .next()
+            .ok_or(GeocodeToolError::NoResults)?
+            .try_into()
+    }
+
+    async fn reverse(&self, lat: f64, lon: f64) -> Result<GeocodeResult, GeocodeToolError> {
+        let response = self
+            .get(
+                "reverse",
+                &[("lat", lat.to_string()), ("lon", lon.to_string())],
+            )
+            .await?;
+        if response.get("error").is_some() {
+            return Err(GeocodeToolError::NoResults);
+        }
+        serde_json::from_value::<NominatimPlace>(response)?.try_into()
+    }
+}
+
+/// A tool that turns place names into coordinates and coordinates into place names.
+pub struct GeocodeTool<P = NominatimProvider> {
+    provider: P,
+}
+
+impl GeocodeTool<NominatimProvider> {
+    pub fn new() -> Self {
+        Self::for_provider(NominatimProvider::new())
+    }
+}
+
+impl Default for GeocodeTool<NominatimProvider> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: GeocodingProvider> GeocodeTool<P> {
+    pub fn for_provider(provider: P) -> Self {
+        Self { provider }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GeocodeOperation {
+    Forward,
+    Reverse,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GeocodeToolInput {
+    pub operation: GeocodeOperation,
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(default)]
+    pub lat: Option<f64>,
+    #[serde(default)]
+    pub lon: Option<f64>,
+}
+
+impl Describe for GeocodeToolInput {
+    fn describe() -> Format {
+        vec![
+            FormatPart::one_of(
+                "operation",
+                "Whether to find the coordinates of a place or the place at some coordinates",
+                &["forward", "reverse"],
+            ),
+            ("query", "The place to find, for forward geocoding").into(),
+            ("lat", "The latitude, for reverse geocoding").into(),
+            ("lon", "The longitude, for reverse geocoding").into(),
+        ]
+        .into()
+    }
+}
+
+pub type GeocodeToolOutput = GeocodeResult;
+
+impl Describe for GeocodeToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("lat", "The latitude of the place").into(),
+            ("lon", "The longitude of the place").into(),
+            ("display_name", "The full name of the place").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum GeocodeToolError {
+    #[error("No place was found")]
+    NoResults,
+    #[error("The `{0}` field is required for this operation")]
+    MissingField(&'static str),
+    #[error("Invalid coordinate returned by the geocoder: {0}")]
+    InvalidCoordinate(String),
+    #[error("Invalid YAML: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("Invalid response from the geocoder: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("The geocoding request failed: {0}")]
+    Request(#[from] reqwest::Error),
+}
+
+impl ToolError for GeocodeToolError {}
+
+#[async_trait]
+impl<P: GeocodingProvider> Tool for GeocodeTool<P> {
+    type Input = GeocodeToolInput;
+
+    type Output = GeocodeToolOutput;
+
+    type Error = GeocodeToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        match input.operation {
+            GeocodeOperation::Forward => {
+                let query = input
+                    .query
+                    .as_deref()
+                    .ok_or(GeocodeToolError::MissingField("query"))?;
+                self.provider.forward(query).await
+            }
+            GeocodeOperation::Reverse => {
+                let lat = input.lat.ok_or(GeocodeToolError::MissingField("lat"))?;
+                let lon = input.lon.ok_or(GeocodeToolError::MissingField("lon"))?;
+                self.provider.reverse(lat, lon).await
+            }
+        }
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "GeocodeTool",
+            "A tool that finds the coordinates of a place, or the place at some coordinates.",
+            "Use this when you need to know where a place is or what is at a location.",
+            GeocodeToolInput::describe(),
+            GeocodeToolOutput::describe(),
+        )
+    }
+}
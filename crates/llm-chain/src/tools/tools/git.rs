@@ -0,0 +1,206 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::Command;
+use std::string::FromUtf8Error;
+use thiserror::Error;
+
+use super::process;
+use crate::tools::{Describe, Format, FormatPart, Tool, ToolDescription, ToolError};
+
+/// How many commits the `log` operation returns.
+const LOG_COUNT: usize = 20;
+
+/// A tool that inspects a git repository.
+///
+/// Only the `status`, `diff`, `log` and `branch` operations are allowed by default, so the agent can
+/// read the state of the repository without changing it. Committing has to be enabled with
+/// [`GitTool::with_commit_allowed`].
+pub struct GitTool {
+    working_dir: PathBuf,
+    commit_allowed: bool,
+}
+
+impl GitTool {
+    /// Creates a tool for the repository containing `working_dir`.
+    pub fn new(working_dir: impl Into<PathBuf>) -> Self {
+        GitTool {
+            working_dir: working_dir.into(),
+            commit_allowed: false,
+        }
+    }
+
+    /// Allows the `commit` operation, which commits all changes to tracked files.
+    pub fn with_commit_allowed(mut self) -> Self {
+        self.commit_allowed = true;
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GitOperation {
+    Status,
+    Diff,
+    Log,
+    Branch,
+    Commit,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GitToolInput {
+    pub operation: GitOperation,
+    /// For `diff`, whether to show the staged changes instead of the unstaged ones.
+    #[serde(default)]
+    pub staged: bool,
+    /// The commit message, for `commit`.
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl Describe for GitToolInput {
+    fn describe() -> Format {
+        vec![
+            FormatPart::one_of(
+                "operation",
+                "The git operation to run",
+                &["status", "diff", "log", "branch", "commit"],
+            ),
+            (
+                "staged",
+                "For diff, true to show the staged changes instead of the unstaged ones. Defaults to false",
+            )
+                .into(),
+            ("message", "The commit message, required for commit").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GitToolOutput {
+    pub output: String,
+}
+
+impl Describe for GitToolOutput {
+    fn describe() -> Format {
+        vec![("output", "The output of the git command").into()].into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum GitToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("Failed to run git: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("The git output is not valid UTF-8: {0}")]
+    FromUtf8Error(#[from] FromUtf8Error),
+    #[error("git failed: {0}")]
+    GitFailed(String),
+    #[error("Committing is not allowed")]
+    CommitNotAllowed,
+    #[error("A message is required to commit")]
+    MissingCommitMessage,
+}
+
+impl ToolError for GitToolError {}
+
+impl GitTool {
+    fn args(&self, input: &GitToolInput) -> Result<Vec<String>, GitToolError> {
+        let args = match input.operation {
+            GitOperation::Status => vec!["status", "--short", "--branch"],
+            GitOperation::Diff if input.staged => vec!["diff", "--staged"],
+            GitOperation::Diff => vec!["diff"],
+            GitOperation::Log => {
+                return Ok(vec![
+                    "log".to_string(),
+                    format!("--max-count={}", LOG_COUNT),
+                    "--date=short".to_string(),
+                    "--format=%h %ad %an: %s".to_string(),
+                ])
+            }
+            GitOperation::Branch => vec!["branch", "--all"],
+            GitOperation::Commit => {
+                if !self.commit_allowed {
+                    return Err(GitToolError::CommitNotAllowed);
+                }
+                let message = input
+                    .message
+                    .as_deref()
+                    .filter(|message| !message.trim().is_empty())
+                    .ok_or(GitToolError::MissingCommitMessage)?;
+                vec!["commit", "--all", "--message", message]
+            }
+        };
+        Ok(args.into_iter().map(str::to_string).collect())
+    }
+}
+
+#[async_trait]
+impl Tool for GitTool {
+    type Input = GitToolInput;
+
+    type Output = GitToolOutput;
+
+    type Error = GitToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let mut command = Command::new("git");
+        command
+            .current_dir(&self.working_dir)
+            .args(self.args(input)?)
+            .env("GIT_TERMINAL_PROMPT", "0");
+        let output = process::output(command).await?;
+        if !output.status.success() {
+            return Err(GitToolError::GitFailed(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        Ok(GitToolOutput {
+            output: String::from_utf8(output.stdout)?,
+        })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "GitTool",
+            "A tool that shows the status, diff, log and branches of the git repository you are working in.",
+            "Use this to see what has changed in the repository before and after editing it.",
+            GitToolInput::describe(),
+            GitToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_runs_read_operations_and_gates_commit() {
+        let dir = std::env::temp_dir().join(format!("llm-chain-git-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let status = Command::new("git")
+            .arg("init")
+            .current_dir(&dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success());
+        std::fs::write(dir.join("notes.txt"), "hello").unwrap();
+
+        let input = |operation| GitToolInput {
+            operation,
+            staged: false,
+            message: Some("Add notes".to_string()),
+        };
+        let tool = GitTool::new(&dir);
+        let status = tool.invoke_typed(&input(GitOperation::Status)).await;
+        let commit = tool.invoke_typed(&input(GitOperation::Commit)).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(status.unwrap().output.contains("?? notes.txt"));
+        assert!(matches!(commit, Err(GitToolError::CommitNotAllowed)));
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+use crate::document_stores::in_memory_document_store::InMemoryDocumentStore;
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+
+/// The number of texts returned when the tool is given a plain query, e.g. by an agent.
+const DEFAULT_LIMIT: u32 = 4;
+
+/// A tool that searches the documents of an [`InMemoryDocumentStore`] for keywords.
+///
+/// Unlike the vector store tool, it finds documents containing the exact words of the query, which
+/// suits names, codes and other terms that semantic search matches poorly. Documents are ranked by
+/// their BM25 score, see [`InMemoryDocumentStore::keyword_search`].
+///
+/// The store is shared, so documents added to it after the tool is created are searched too.
+pub struct KeywordSearchTool<M>
+where
+    M: Serialize + DeserializeOwned + Send + Sync,
+{
+    store: Arc<Mutex<InMemoryDocumentStore<M>>>,
+    topic: String,
+}
+
+impl<M> KeywordSearchTool<M>
+where
+    M: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Creates a tool searching `store`, which holds documents about `topic`.
+    pub fn new(store: Arc<Mutex<InMemoryDocumentStore<M>>>, topic: &str) -> Self {
+        Self {
+            store,
+            topic: topic.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct KeywordSearchToolInput {
+    pub query: String,
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+fn default_limit() -> u32 {
+    DEFAULT_LIMIT
+}
+
+impl From<String> for KeywordSearchToolInput {
+    fn from(query: String) -> Self {
+        Self {
+            query,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+impl Describe for KeywordSearchToolInput {
+    fn describe() -> Format {
+        vec![
+            (
+                "query",
+                "The keywords to search for. Texts containing them exactly are returned",
+            )
+                .into(),
+            ("limit", "The maximum number of texts to return").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct KeywordSearchToolOutput {
+    pub texts: Vec<String>,
+}
+
+/// Joins the texts, separated by blank lines.
+impl From<KeywordSearchToolOutput> for String {
+    fn from(output: KeywordSearchToolOutput) -> Self {
+        output.texts.join("\n\n")
+    }
+}
+
+impl Describe for KeywordSearchToolOutput {
+    fn describe() -> Format {
+        vec![(
+            "texts",
+            "The texts containing the keywords, those containing them most often first",
+        )
+            .into()]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KeywordSearchToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+}
+
+impl ToolError for KeywordSearchToolError {}
+
+#[async_trait]
+impl<M> Tool for KeywordSearchTool<M>
+where
+    M: Serialize + DeserializeOwned + Send + Sync,
+{
+    type Input = KeywordSearchToolInput;
+
+    type Output = KeywordSearchToolOutput;
+
+    type Error = KeywordSearchToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let store = self.store.lock().await;
+        let texts = store
+            .keyword_search(&input.query, input.limit as usize)
+            .into_iter()
+            .map(|(_, document)| document.page_content)
+            .collect();
+        Ok(KeywordSearchToolOutput { texts })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "KeywordSearchTool",
+            "A tool that finds texts containing the given keywords.",
+            &format!(
+                "Use this to find texts about {} that mention exact words such as names, codes or rare terms. Prefer semantic search for questions phrased in your own words.",
+                self.topic
+            ),
+            KeywordSearchToolInput::describe(),
+            KeywordSearchToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::document_stores::document_store::DocumentStore;
+    use crate::schema::Document;
+
+    #[tokio::test]
+    async fn test_searches_shared_store() {
+        let store = Arc::new(Mutex::new(InMemoryDocumentStore::<()>::new()));
+        let tool = KeywordSearchTool::new(store.clone(), "error codes");
+        store
+            .lock()
+            .await
+            .insert(&HashMap::from([
+                (0, Document::new("E1234 means the disk is full".into())),
+                (1, Document::new("E5678 means the network is down".into())),
+            ]))
+            .await
+            .unwrap();
+
+        let output = tool.invoke_typed(&"e5678".to_string().into()).await;
+        assert_eq!(
+            output.unwrap().texts,
+            vec!["E5678 means the network is down".to_string()]
+        );
+    }
+}
@@ -3,13 +3,55 @@
 
 mod bash;
 mod bing_search;
+mod classify;
+mod code;
+mod codec;
+mod csv;
+mod diff;
 mod exit;
+mod geocode;
+mod git;
+mod keyword_search;
+mod process;
 mod python;
+#[cfg(feature = "redis")]
+mod redis;
+mod scheduler;
+mod similarity;
+mod translate;
 mod vectorstore;
+mod web_reader;
 pub use bash::{BashTool, BashToolError, BashToolInput, BashToolOutput};
 pub use bing_search::{BingSearch, BingSearchError, BingSearchInput, BingSearchOutput};
+pub use classify::{ClassifyTool, ClassifyToolError, ClassifyToolInput, ClassifyToolOutput};
+pub use code::{CodeTool, CodeToolError, CodeToolInput, CodeToolOutput};
+pub use codec::{CodecOperation, CodecTool, CodecToolError, CodecToolInput, CodecToolOutput};
+pub use csv::{CsvTool, CsvToolError, CsvToolInput, CsvToolOutput};
+pub use diff::{
+    DiffChange, DiffFormat, DiffGranularity, DiffTool, DiffToolError, DiffToolInput, DiffToolOutput,
+};
 pub use exit::{ExitTool, ExitToolError, ExitToolInput, ExitToolOutput};
+pub use geocode::{
+    GeocodeOperation, GeocodeResult, GeocodeTool, GeocodeToolError, GeocodeToolInput,
+    GeocodeToolOutput, GeocodingProvider, NominatimProvider,
+};
+pub use git::{GitOperation, GitTool, GitToolError, GitToolInput, GitToolOutput};
+pub use keyword_search::{
+    KeywordSearchTool, KeywordSearchToolError, KeywordSearchToolInput, KeywordSearchToolOutput,
+};
 pub use python::{PythonTool, PythonToolError, PythonToolInput, PythonToolOutput};
+#[cfg(feature = "redis")]
+pub use redis::{RedisOperation, RedisTool, RedisToolError, RedisToolInput, RedisToolOutput};
+pub use scheduler::{
+    CancelScheduledTool, CancelScheduledToolInput, CancelScheduledToolOutput, ScheduleHandler,
+    SchedulerTool, SchedulerToolError, SchedulerToolInput, SchedulerToolOutput,
+};
+pub use similarity::{
+    SemanticSimilarityTool, SemanticSimilarityToolError, SemanticSimilarityToolInput,
+    SemanticSimilarityToolOutput,
+};
+pub use translate::{TranslateTool, TranslateToolError, TranslateToolInput, TranslateToolOutput};
 pub use vectorstore::{
     VectorStoreTool, VectorStoreToolError, VectorStoreToolInput, VectorStoreToolOutput,
 };
+pub use web_reader::{WebReaderTool, WebReaderToolError, WebReaderToolInput, WebReaderToolOutput};
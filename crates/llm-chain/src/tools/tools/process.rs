@@ -0,0 +1,43 @@
+use std::process::{Command, Output, Stdio};
+
+/// Runs `command` to completion and collects its output, like `Command::output`, without blocking
+/// the executor.
+///
+/// If the returned future is dropped before the command finishes, e.g. because the tool call timed
+/// out or the agent was cancelled, the command is killed. On Unix the command runs in its own
+/// process group and the whole group is killed, so processes it started are not left running either.
+pub(crate) async fn output(mut command: Command) -> std::io::Result<Output> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let child = tokio::process::Command::from(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut guard = ProcessGroupGuard { pid: child.id() };
+    let output = child.wait_with_output().await;
+    guard.pid = None;
+    output
+}
+
+/// Kills the process group led by `pid` when dropped, unless `pid` was cleared.
+struct ProcessGroupGuard {
+    pid: Option<u32>,
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.pid {
+            // SAFETY: `kill` has no memory safety requirements. The group was created for the
+            // command and is only signalled while the command hasn't been waited for.
+            unsafe {
+                libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+            }
+        }
+    }
+}
@@ -1,8 +1,10 @@
+use super::process;
 use crate::tools::description::{Describe, Format, ToolDescription};
 use crate::tools::tool::{Tool, ToolError};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::process::Command;
+use std::string::FromUtf8Error;
 use thiserror::Error;
 
 pub struct PythonTool {}
@@ -48,10 +50,12 @@ impl Describe for PythonToolOutput {
 
 #[derive(Debug, Error)]
 pub enum PythonToolError {
-    #[error(transparent)]
+    #[error("Invalid YAML: {0}")]
     YamlError(#[from] serde_yaml::Error),
-    #[error(transparent)]
+    #[error("Failed to run Python: {0}")]
     IOError(#[from] std::io::Error),
+    #[error("The Python output is not valid UTF-8: {0}")]
+    FromUtf8Error(#[from] FromUtf8Error),
 }
 
 impl ToolError for PythonToolError {}
@@ -63,13 +67,12 @@ impl Tool for PythonTool {
     type Error = PythonToolError;
 
     async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
-        let output = Command::new("python3")
-            .arg("-c")
-            .arg(&input.code)
-            .output()?;
+        let mut command = Command::new("python3");
+        command.arg("-c").arg(&input.code);
+        let output = process::output(command).await?;
         Ok(PythonToolOutput {
-            result: String::from_utf8(output.stdout).unwrap(),
-            stderr: String::from_utf8(output.stderr).unwrap(),
+            result: String::from_utf8(output.stdout)?,
+            stderr: String::from_utf8(output.stderr)?,
         })
     }
 
@@ -83,3 +86,17 @@ impl Tool for PythonTool {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rejects_non_utf8_output() {
+        let input = PythonToolInput {
+            code: "import sys; sys.stdout.buffer.write(b'\\xff')".to_string(),
+        };
+        let result = PythonTool::new().invoke_typed(&input).await;
+        assert!(matches!(result, Err(PythonToolError::FromUtf8Error(_))));
+    }
+}
@@ -0,0 +1,190 @@
+use async_trait::async_trait;
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::tools::{Describe, Format, FormatPart, Tool, ToolDescription, ToolError};
+
+/// A tool that reads and writes keys and lists in Redis.
+///
+/// Every key is prefixed with the namespace given at construction, so that agents or sessions
+/// sharing a Redis server can't see or overwrite each other's keys.
+pub struct RedisTool {
+    connection: MultiplexedConnection,
+    namespace: String,
+}
+
+impl RedisTool {
+    /// Creates a tool that stores its keys under `namespace` using an open connection.
+    pub fn new(connection: MultiplexedConnection, namespace: &str) -> Self {
+        Self {
+            connection,
+            namespace: namespace.to_string(),
+        }
+    }
+
+    /// Connects to the Redis server at `url`, e.g. `redis://127.0.0.1/`.
+    pub async fn connect(url: &str, namespace: &str) -> Result<Self, RedisToolError> {
+        let connection = redis::Client::open(url)?
+            .get_multiplexed_tokio_connection()
+            .await?;
+        Ok(Self::new(connection, namespace))
+    }
+
+    fn namespaced(&self, key: &str) -> String {
+        format!("{}:{}", self.namespace, key)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RedisOperation {
+    Get,
+    Set,
+    Del,
+    Lpush,
+    Lrange,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RedisToolInput {
+    pub operation: RedisOperation,
+    pub key: String,
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub ttl_seconds: Option<usize>,
+    #[serde(default)]
+    pub start: Option<isize>,
+    #[serde(default)]
+    pub stop: Option<isize>,
+}
+
+impl Describe for RedisToolInput {
+    fn describe() -> Format {
+        vec![
+            FormatPart::one_of(
+                "operation",
+                "The command to run: get, set or del a string, push to the front of a list or read a range of a list",
+                &["get", "set", "del", "lpush", "lrange"],
+            ),
+            ("key", "The key to operate on").into(),
+            ("value", "The value to store, for set and lpush").into(),
+            (
+                "ttl_seconds",
+                "How long the key should live, for set. Optional, keys never expire by default",
+            )
+                .into(),
+            ("start", "The index of the first element, for lrange. Defaults to 0").into(),
+            (
+                "stop",
+                "The index of the last element, for lrange. Negative indices count from the end, defaults to -1",
+            )
+                .into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct RedisToolOutput {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<i64>,
+}
+
+impl Describe for RedisToolOutput {
+    fn describe() -> Format {
+        vec![
+            (
+                "value",
+                "The value of the key, for get. Absent if the key does not exist",
+            )
+                .into(),
+            ("values", "The elements of the list, for lrange").into(),
+            (
+                "count",
+                "The number of keys deleted for del, or the length of the list after lpush",
+            )
+                .into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RedisToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("The Redis command failed: {0}")]
+    Redis(#[from] redis::RedisError),
+    #[error("The `{0}` field is required for this operation")]
+    MissingField(&'static str),
+}
+
+impl ToolError for RedisToolError {}
+
+#[async_trait]
+impl Tool for RedisTool {
+    type Input = RedisToolInput;
+
+    type Output = RedisToolOutput;
+
+    type Error = RedisToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let key = self.namespaced(&input.key);
+        let value = || {
+            input
+                .value
+                .as_deref()
+                .ok_or(RedisToolError::MissingField("value"))
+        };
+        let mut connection = self.connection.clone();
+        let mut output = RedisToolOutput::default();
+        match input.operation {
+            RedisOperation::Get => output.value = connection.get(&key).await?,
+            RedisOperation::Set => match input.ttl_seconds {
+                Some(ttl) => connection.set_ex(&key, value()?, ttl).await?,
+                None => connection.set(&key, value()?).await?,
+            },
+            RedisOperation::Del => output.count = Some(connection.del(&key).await?),
+            RedisOperation::Lpush => output.count = Some(connection.lpush(&key, value()?).await?),
+            RedisOperation::Lrange => {
+                output.values = Some(
+                    connection
+                        .lrange(&key, input.start.unwrap_or(0), input.stop.unwrap_or(-1))
+                        .await?,
+                )
+            }
+        }
+        Ok(output)
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "RedisTool",
+            "A tool that stores and reads strings and lists in a Redis key-value store.",
+            "Use this to remember information between steps or sessions, and to recall it later.",
+            RedisToolInput::describe(),
+            RedisToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_defaults_optional_fields() {
+        let input: RedisToolInput = serde_yaml::from_str("operation: lrange\nkey: notes").unwrap();
+        assert_eq!(input.operation, RedisOperation::Lrange);
+        assert_eq!(input.value, None);
+        assert_eq!((input.start, input.stop), (None, None));
+    }
+}
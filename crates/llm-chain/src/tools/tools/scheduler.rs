@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+
+/// Called with the job id and payload each time a scheduled job fires.
+///
+/// The handler runs on the scheduler's task, so it should hand long running work off to a task of
+/// its own.
+pub type ScheduleHandler = Arc<dyn Fn(&str, &str) + Send + Sync>;
+
+type Jobs = Arc<Mutex<HashMap<String, JoinHandle<()>>>>;
+
+/// How many times the search for the next run of a cron schedule may step forward before giving
+/// up, which only happens for schedules that can never fire, like `0 0 31 2 *`.
+const MAX_CRON_STEPS: usize = 100_000;
+
+/// A tool that schedules a payload to be passed to a handler after a delay, at a given time or on a
+/// cron schedule.
+///
+/// Jobs run on tokio tasks until they finish or are cancelled with the tool returned by
+/// [`SchedulerTool::cancel_tool`]. They keep running when the tool is dropped.
+pub struct SchedulerTool {
+    handler: ScheduleHandler,
+    jobs: Jobs,
+}
+
+impl SchedulerTool {
+    /// Creates a tool that calls `handler` with the job id and payload when a job fires.
+    pub fn new<F>(handler: F) -> Self
+    where
+        F: Fn(&str, &str) + Send + Sync + 'static,
+    {
+        SchedulerTool {
+            handler: Arc::new(handler),
+            jobs: Default::default(),
+        }
+    }
+
+    /// Returns a tool that cancels the jobs scheduled by this one.
+    pub fn cancel_tool(&self) -> CancelScheduledTool {
+        CancelScheduledTool {
+            jobs: self.jobs.clone(),
+        }
+    }
+
+    /// Returns the ids of the jobs that are still scheduled.
+    pub fn job_ids(&self) -> Vec<String> {
+        self.jobs.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SchedulerToolInput {
+    /// A delay such as `90s`, `15m`, `2h` or `1d`, an RFC 3339 time, or a cron expression.
+    pub run_at_or_cron: String,
+    pub payload: String,
+}
+
+impl Describe for SchedulerToolInput {
+    fn describe() -> Format {
+        vec![
+            (
+                "run_at_or_cron",
+                "When to run: a delay such as 90s, 15m, 2h or 1d, a time such as 2023-06-01T09:30:00Z, or a cron expression with 5 fields (minute hour day month weekday) in UTC to run repeatedly",
+            )
+                .into(),
+            ("payload", "What to do when the job runs").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SchedulerToolOutput {
+    pub job_id: String,
+    /// When the job runs next, as an RFC 3339 time.
+    pub next_run: String,
+}
+
+impl Describe for SchedulerToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("job_id", "The id of the job, used to cancel it").into(),
+            ("next_run", "When the job runs next").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SchedulerToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("Invalid schedule \"{0}\", use a delay such as 15m, an RFC 3339 time or a cron expression with 5 fields")]
+    InvalidSchedule(String),
+    #[error("The time {0} is in the past")]
+    InPast(String),
+    #[error("The cron expression \"{0}\" never matches a time")]
+    NeverRuns(String),
+}
+
+impl ToolError for SchedulerToolError {}
+
+/// When a job runs.
+#[derive(Debug, Clone, PartialEq)]
+enum Schedule {
+    Once(DateTime<Utc>),
+    Cron(CronSchedule),
+}
+
+impl Schedule {
+    fn parse(schedule: &str, now: DateTime<Utc>) -> Result<Self, SchedulerToolError> {
+        let schedule = schedule.trim();
+        let invalid = || SchedulerToolError::InvalidSchedule(schedule.to_string());
+        if schedule.split_whitespace().count() == 5 {
+            return CronSchedule::parse(schedule)
+                .map(Schedule::Cron)
+                .ok_or_else(invalid);
+        }
+        if let Ok(time) = DateTime::parse_from_rfc3339(schedule) {
+            return Ok(Schedule::Once(time.with_timezone(&Utc)));
+        }
+        parse_delay(schedule)
+            .map(|delay| Schedule::Once(now + delay))
+            .ok_or_else(invalid)
+    }
+
+    /// Returns the first time after `now` the job should run, or the time of a one-off job.
+    fn next_run(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Once(time) => Some(*time),
+            Schedule::Cron(cron) => cron.next_after(now),
+        }
+    }
+}
+
+/// Parses a delay such as `90s`, `15m`, `2h` or `1d`.
+fn parse_delay(delay: &str) -> Option<Duration> {
+    let unit_start = delay.find(|c: char| !c.is_ascii_digit())?;
+    let amount: i64 = delay[..unit_start].parse().ok()?;
+    match delay[unit_start..].trim() {
+        "s" | "sec" | "secs" | "seconds" => Some(Duration::seconds(amount)),
+        "m" | "min" | "mins" | "minutes" => Some(Duration::minutes(amount)),
+        "h" | "hour" | "hours" => Some(Duration::hours(amount)),
+        "d" | "day" | "days" => Some(Duration::days(amount)),
+        _ => None,
+    }
+}
+
+/// A cron schedule in UTC, with the allowed values of each field as a bit set.
+#[derive(Debug, Clone, PartialEq)]
+struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of month or day of week is restricted. When both are, a day matching either
+    /// one matches, as in standard cron.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl CronSchedule {
+    fn parse(expression: &str) -> Option<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            return None;
+        };
+        let mut weekday_set = parse_cron_field(weekdays, 0, 7)?;
+        // Both 0 and 7 are Sunday.
+        if weekday_set & (1 << 7) != 0 {
+            weekday_set |= 1;
+        }
+        Some(CronSchedule {
+            minutes: parse_cron_field(minutes, 0, 59)?,
+            hours: parse_cron_field(hours, 0, 23)?,
+            days: parse_cron_field(days, 1, 31)?,
+            months: parse_cron_field(months, 1, 12)?,
+            weekdays: weekday_set,
+            days_restricted: *days != "*",
+            weekdays_restricted: *weekdays != "*",
+        })
+    }
+
+    fn matches_day(&self, time: DateTime<Utc>) -> bool {
+        let day = self.days & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().num_days_from_sunday()) != 0;
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// Returns the first matching minute after `after`.
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut time = after.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        for _ in 0..MAX_CRON_STEPS {
+            if self.months & (1 << time.month()) == 0 {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = time
+                    .with_day(1)?
+                    .with_month(month)?
+                    .with_year(year)?
+                    .with_hour(0)?
+                    .with_minute(0)?;
+            } else if !self.matches_day(time) {
+                time = (time + Duration::days(1)).with_hour(0)?.with_minute(0)?;
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = (time + Duration::hours(1)).with_minute(0)?;
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+}
+
+/// Parses a cron field such as `*`, `5`, `1-5`, `*/15` or `0,30` into a bit set of its values.
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut set = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|&s| s > 0)?),
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (start.parse().ok()?, end.parse().ok()?),
+                None => {
+                    let value = range.parse().ok()?;
+                    (value, if step > 1 { max } else { value })
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Some(set)
+}
+
+#[async_trait]
+impl Tool for SchedulerTool {
+    type Input = SchedulerToolInput;
+
+    type Output = SchedulerToolOutput;
+
+    type Error = SchedulerToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let now = Utc::now();
+        let schedule = Schedule::parse(&input.run_at_or_cron, now)?;
+        let next_run = match schedule.next_run(now) {
+            Some(time) if time < now => return Err(SchedulerToolError::InPast(time.to_rfc3339())),
+            Some(time) => time,
+            None => {
+                return Err(SchedulerToolError::NeverRuns(
+                    input.run_at_or_cron.trim().to_string(),
+                ))
+            }
+        };
+
+        let job_id = uuid::Uuid::new_v4().to_string();
+        let handler = self.handler.clone();
+        let jobs = self.jobs.clone();
+        let payload = input.payload.clone();
+        let id = job_id.clone();
+        // The lock is held until the job is added, so a job that finishes at once can't try to
+        // remove itself first.
+        let mut scheduled = self.jobs.lock().unwrap();
+        let job = tokio::spawn(async move {
+            let mut next = Some(next_run);
+            while let Some(time) = next {
+                let wait = (time - Utc::now()).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+                next = match &schedule {
+                    Schedule::Once(_) => None,
+                    Schedule::Cron(cron) => cron.next_after(time),
+                };
+                // A job is done before its last run, so it can't be cancelled from the handler.
+                if next.is_none() {
+                    jobs.lock().unwrap().remove(&id);
+                }
+                handler(&id, &payload);
+            }
+        });
+        scheduled.insert(job_id.clone(), job);
+        Ok(SchedulerToolOutput {
+            job_id,
+            next_run: next_run.to_rfc3339(),
+        })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "SchedulerTool",
+            "A tool that schedules an action to run later, once or repeatedly.",
+            "Use this to set reminders or to do something at a later time. Keep the job id to cancel the job.",
+            SchedulerToolInput::describe(),
+            SchedulerToolOutput::describe(),
+        )
+    }
+}
+
+/// A tool that cancels jobs scheduled with a [`SchedulerTool`].
+pub struct CancelScheduledTool {
+    jobs: Jobs,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CancelScheduledToolInput {
+    pub job_id: String,
+}
+
+impl Describe for CancelScheduledToolInput {
+    fn describe() -> Format {
+        vec![("job_id", "The id of the job to cancel").into()].into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CancelScheduledToolOutput {
+    /// Whether a job was cancelled, false if it had already finished or never existed.
+    pub cancelled: bool,
+}
+
+impl Describe for CancelScheduledToolOutput {
+    fn describe() -> Format {
+        vec![(
+            "cancelled",
+            "true if the job was cancelled, false if there is no such job or it already ran",
+        )
+            .into()]
+        .into()
+    }
+}
+
+#[async_trait]
+impl Tool for CancelScheduledTool {
+    type Input = CancelScheduledToolInput;
+
+    type Output = CancelScheduledToolOutput;
+
+    type Error = SchedulerToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let job = self.jobs.lock().unwrap().remove(input.job_id.trim());
+        if let Some(job) = &job {
+            job.abort();
+        }
+        Ok(CancelScheduledToolOutput {
+            cancelled: job.is_some(),
+        })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "CancelScheduledTool",
+            "A tool that cancels a job scheduled with the SchedulerTool.",
+            "Use this when a scheduled action is no longer needed.",
+            CancelScheduledToolInput::describe(),
+            CancelScheduledToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().into()
+    }
+
+    #[test]
+    fn test_finds_next_cron_run() {
+        let now = time("2023-06-01T09:30:20Z");
+        let next = |cron: &str| {
+            CronSchedule::parse(cron)
+                .unwrap()
+                .next_after(now)
+                .map(|t| t.to_rfc3339())
+        };
+        assert_eq!(next("* * * * *").unwrap(), "2023-06-01T09:31:00+00:00");
+        assert_eq!(next("*/15 * * * *").unwrap(), "2023-06-01T09:45:00+00:00");
+        assert_eq!(next("0 8 * * 1-5").unwrap(), "2023-06-02T08:00:00+00:00");
+        assert_eq!(next("0 0 1 1 *").unwrap(), "2024-01-01T00:00:00+00:00");
+        assert_eq!(next("0 0 31 2 *"), None);
+        assert!(CronSchedule::parse("60 * * * *").is_none());
+
+        assert_eq!(
+            Schedule::parse("15m", now).unwrap(),
+            Schedule::Once(time("2023-06-01T09:45:20Z"))
+        );
+        assert!(Schedule::parse("soon", now).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_runs_and_cancels_jobs() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let tool = SchedulerTool::new(move |_, payload| sender.send(payload.to_string()).unwrap());
+        let cancel = tool.cancel_tool();
+        let input = |run_at_or_cron: &str, payload: &str| SchedulerToolInput {
+            run_at_or_cron: run_at_or_cron.to_string(),
+            payload: payload.to_string(),
+        };
+
+        let soon = tool.invoke_typed(&input("0s", "now")).await.unwrap();
+        let later = tool.invoke_typed(&input("1d", "tomorrow")).await.unwrap();
+        assert_eq!(receiver.recv().await.unwrap(), "now");
+        let cancelled = |job_id: String| CancelScheduledToolInput { job_id };
+        let output = cancel.invoke_typed(&cancelled(later.job_id)).await.unwrap();
+        assert!(output.cancelled);
+        let output = cancel.invoke_typed(&cancelled(soon.job_id)).await.unwrap();
+        assert!(!output.cancelled);
+        assert!(tool.job_ids().is_empty());
+    }
+}
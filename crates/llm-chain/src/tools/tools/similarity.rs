@@ -0,0 +1,172 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+use crate::traits::{Embeddings, EmbeddingsError};
+
+/// A tool that measures how similar the meanings of two texts are.
+///
+/// Both texts are embedded with the given [`Embeddings`] and compared by the cosine similarity of
+/// their vectors, which doesn't require a vector store.
+pub struct SemanticSimilarityTool<E> {
+    embeddings: E,
+}
+
+impl<E: Embeddings> SemanticSimilarityTool<E> {
+    pub fn new(embeddings: E) -> Self {
+        SemanticSimilarityTool { embeddings }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SemanticSimilarityToolInput {
+    pub text_a: String,
+    pub text_b: String,
+}
+
+impl Describe for SemanticSimilarityToolInput {
+    fn describe() -> Format {
+        vec![
+            ("text_a", "The first text to compare").into(),
+            ("text_b", "The second text to compare").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct SemanticSimilarityToolOutput {
+    /// The cosine similarity of the texts' embeddings, from -1 to 1.
+    pub similarity: f32,
+}
+
+impl Describe for SemanticSimilarityToolOutput {
+    fn describe() -> Format {
+        vec![(
+            "similarity",
+            "How similar the meanings of the texts are, from -1 to 1. 1 means the same meaning, values near 0 unrelated texts",
+        )
+            .into()]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SemanticSimilarityToolError<E>
+where
+    E: std::fmt::Debug + std::error::Error + EmbeddingsError,
+{
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("Failed to embed the texts: {0}")]
+    Embeddings(E),
+    #[error("The embeddings model returned {0} embeddings for 2 texts")]
+    WrongEmbeddingCount(usize),
+}
+
+impl<E> ToolError for SemanticSimilarityToolError<E> where
+    E: std::fmt::Debug + std::error::Error + EmbeddingsError
+{
+}
+
+/// Returns the cosine similarity of two vectors, or 0 if either of them is zero.
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        (dot / norms).clamp(-1.0, 1.0)
+    }
+}
+
+#[async_trait]
+impl<E> Tool for SemanticSimilarityTool<E>
+where
+    E: Embeddings + Send + Sync,
+{
+    type Input = SemanticSimilarityToolInput;
+
+    type Output = SemanticSimilarityToolOutput;
+
+    type Error = SemanticSimilarityToolError<E::Error>;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let vectors = self
+            .embeddings
+            .embed_texts(vec![input.text_a.clone(), input.text_b.clone()])
+            .await
+            .map_err(SemanticSimilarityToolError::Embeddings)?;
+        let [a, b] = vectors.as_slice() else {
+            return Err(SemanticSimilarityToolError::WrongEmbeddingCount(
+                vectors.len(),
+            ));
+        };
+        Ok(SemanticSimilarityToolOutput {
+            similarity: cosine_similarity(a, b),
+        })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "SemanticSimilarityTool",
+            "A tool that measures how similar the meanings of two texts are.",
+            "Use this to check whether two texts say the same thing, e.g. to find duplicates or to check that a text is relevant to a question.",
+            SemanticSimilarityToolInput::describe(),
+            SemanticSimilarityToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Error)]
+    #[error("unreachable")]
+    struct FakeEmbeddingsError;
+
+    impl EmbeddingsError for FakeEmbeddingsError {}
+
+    /// Embeds texts by counting the vowels in them.
+    struct FakeEmbeddings;
+
+    #[async_trait]
+    impl Embeddings for FakeEmbeddings {
+        type Error = FakeEmbeddingsError;
+
+        async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Self::Error> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    "aeiou"
+                        .chars()
+                        .map(|vowel| text.matches(vowel).count() as f32)
+                        .collect()
+                })
+                .collect())
+        }
+
+        async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error> {
+            Ok(self.embed_texts(vec![query]).await?.remove(0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compares_embeddings() {
+        let tool = SemanticSimilarityTool::new(FakeEmbeddings);
+        let similarity = |text_a: &str, text_b: &str| SemanticSimilarityToolInput {
+            text_a: text_a.to_string(),
+            text_b: text_b.to_string(),
+        };
+
+        let same = tool.invoke_typed(&similarity("banana", "papaya")).await;
+        assert_eq!(same.unwrap().similarity, 1.0);
+        let unrelated = tool.invoke_typed(&similarity("banana", "kiwi")).await;
+        assert_eq!(unrelated.unwrap().similarity, 0.0);
+        let empty = tool.invoke_typed(&similarity("banana", "")).await;
+        assert_eq!(empty.unwrap().similarity, 0.0);
+    }
+}
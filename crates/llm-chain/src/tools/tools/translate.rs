@@ -0,0 +1,226 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    options::Options,
+    prompt::Prompt,
+    tokens::{Tokenizer, TokenizerError},
+    tools::{Describe, Format, Tool, ToolDescription, ToolError},
+    traits::{Executor, ExecutorError},
+};
+
+/// The largest chunk of text translated in one model call by default, in tokens.
+const DEFAULT_MAX_CHUNK_TOKENS: usize = 1000;
+
+/// A tool that asks the model to translate text into another language.
+///
+/// Long texts are split into chunks at paragraph breaks where possible, translated one chunk at a
+/// time and joined back together, so the translation isn't cut off by the model's output limit.
+pub struct TranslateTool<E: Executor> {
+    executor: E,
+    max_chunk_tokens: usize,
+}
+
+impl<E: Executor> TranslateTool<E> {
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            max_chunk_tokens: DEFAULT_MAX_CHUNK_TOKENS,
+        }
+    }
+
+    /// Sets the largest chunk of text translated in one model call, in tokens. Defaults to 1000.
+    pub fn with_max_chunk_tokens(mut self, max_chunk_tokens: usize) -> Self {
+        self.max_chunk_tokens = max_chunk_tokens.max(1);
+        self
+    }
+
+    fn translation_prompt(&self, text: &str, input: &TranslateToolInput) -> String {
+        let source = match input.source_language.as_deref().map(str::trim) {
+            Some(source) if !source.is_empty() => format!(" from {}", source),
+            _ => String::new(),
+        };
+        format!(
+            "Translate the text below{} into {}. Respond only with the translation, keeping the original formatting.
+
+Text:
+{}",
+            source,
+            input.target_language.trim(),
+            text
+        )
+    }
+
+    async fn complete(&self, prompt: String) -> Result<String, TranslateToolError> {
+        self.executor
+            .execute(Options::empty(), &Prompt::text(prompt))
+            .await?
+            .to_immediate()
+            .await?
+            .as_content()
+            .extract_last_body()
+            .cloned()
+            .ok_or(TranslateToolError::NoChoicesReturned)
+    }
+}
+
+/// Splits `text` into chunks of at most `max_tokens` tokens.
+///
+/// Whole paragraphs are kept together where they fit, and paragraphs that are too long on their own
+/// are split by the tokenizer.
+fn chunk_text<K: Tokenizer>(
+    tokenizer: &K,
+    text: &str,
+    max_tokens: usize,
+) -> Result<Vec<String>, TokenizerError> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+    let mut current_tokens = 0;
+    for paragraph in text.split("\n\n") {
+        let tokens = tokenizer.tokenize_str(paragraph)?.len();
+        if !current.is_empty() && current_tokens + tokens > max_tokens {
+            chunks.push(std::mem::take(&mut current));
+            current_tokens = 0;
+        }
+        if tokens > max_tokens {
+            chunks.extend(tokenizer.split_text(paragraph, max_tokens, 0)?);
+            continue;
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+        current_tokens += tokens;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    Ok(chunks)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TranslateToolInput {
+    pub text: String,
+    #[serde(default)]
+    pub target_language: String,
+    #[serde(default)]
+    pub source_language: Option<String>,
+}
+
+impl Describe for TranslateToolInput {
+    fn describe() -> Format {
+        vec![
+            ("text", "The text to translate").into(),
+            (
+                "target_language",
+                "The language to translate the text into, e.g. French",
+            )
+                .into(),
+            (
+                "source_language",
+                "The language the text is written in. Optional, detected from the text by default",
+            )
+                .into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TranslateToolOutput {
+    pub translation: String,
+}
+
+impl Describe for TranslateToolOutput {
+    fn describe() -> Format {
+        vec![("translation", "The translated text").into()].into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TranslateToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("The model call failed: {0}")]
+    ExecutorError(#[from] ExecutorError),
+    #[error("Failed to split the text: {0}")]
+    TokenizerError(#[from] TokenizerError),
+    #[error("Model response was empty or contained no choices")]
+    NoChoicesReturned,
+    #[error("The `target_language` field is required, e.g. `target_language: French`")]
+    MissingTargetLanguage,
+}
+
+impl ToolError for TranslateToolError {}
+
+#[async_trait]
+impl<E> Tool for TranslateTool<E>
+where
+    E: Executor + Send + Sync,
+{
+    type Input = TranslateToolInput;
+
+    type Output = TranslateToolOutput;
+
+    type Error = TranslateToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        if input.target_language.trim().is_empty() {
+            return Err(TranslateToolError::MissingTargetLanguage);
+        }
+        let chunks = {
+            let tokenizer = self.executor.get_tokenizer(Options::empty())?;
+            chunk_text(&tokenizer, &input.text, self.max_chunk_tokens)?
+        };
+        let mut translations = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            let translation = self
+                .complete(self.translation_prompt(&chunk, input))
+                .await?;
+            translations.push(translation.trim().to_string());
+        }
+        Ok(TranslateToolOutput {
+            translation: translations.join("\n\n"),
+        })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "TranslateTool",
+            "A tool that translates text from one language into another.",
+            "Use this to understand text written in another language, or to answer in the user's language.",
+            TranslateToolInput::describe(),
+            TranslateToolOutput::describe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::TokenCollection;
+
+    /// Uses one token per character.
+    struct CharTokenizer;
+
+    impl Tokenizer for CharTokenizer {
+        fn tokenize_str(&self, doc: &str) -> Result<TokenCollection, TokenizerError> {
+            Ok(doc.chars().map(|c| c as usize).collect::<Vec<_>>().into())
+        }
+
+        fn to_string(&self, tokens: TokenCollection) -> Result<String, TokenizerError> {
+            tokens
+                .as_usize()?
+                .into_iter()
+                .map(|t| char::from_u32(t as u32).ok_or(TokenizerError::ToStringError))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_chunks_text_at_paragraphs() {
+        let chunks = chunk_text(&CharTokenizer, "ab\n\ncd\n\nefghijk\n\nl", 6).unwrap();
+        assert_eq!(chunks, vec!["ab\n\ncd", "efghij", "k", "l"]);
+    }
+}
@@ -48,11 +48,11 @@ where
     V: std::fmt::Debug + std::error::Error + VectorStoreError,
     E: std::fmt::Debug + std::error::Error + EmbeddingsError,
 {
-    #[error(transparent)]
+    #[error("Invalid YAML: {0}")]
     YamlError(#[from] serde_yaml::Error),
-    #[error(transparent)]
+    #[error("The vector store failed: {0}")]
     VectorStoreError(#[from] V),
-    #[error(transparent)]
+    #[error("Failed to compute embeddings: {0}")]
     Embeddings(E),
 }
 
@@ -74,6 +74,25 @@ pub struct VectorStoreToolOutput {
     texts: Vec<String>,
 }
 
+/// The number of texts returned when the tool is given a plain query, e.g. by an agent.
+const DEFAULT_LIMIT: u32 = 4;
+
+impl From<String> for VectorStoreToolInput {
+    fn from(query: String) -> Self {
+        Self {
+            query,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+/// Joins the texts, separated by blank lines.
+impl From<VectorStoreToolOutput> for String {
+    fn from(output: VectorStoreToolOutput) -> Self {
+        output.texts.join("\n\n")
+    }
+}
+
 impl Describe for VectorStoreToolInput {
     fn describe() -> Format {
         vec![
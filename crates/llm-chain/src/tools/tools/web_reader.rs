@@ -0,0 +1,380 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::tools::{Describe, Format, Tool, ToolDescription, ToolError};
+
+/// Elements that never hold the main content of a page, skipped with everything inside them.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "iframe", "nav", "header", "footer", "aside",
+    "form", "button", "select",
+];
+
+/// Elements that start a new block of text.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "br",
+    "li",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "tr",
+    "td",
+    "th",
+    "table",
+    "blockquote",
+    "pre",
+    "figcaption",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+
+/// Blocks shorter than this are dropped as likely boilerplate, unless they are headings.
+const MIN_BLOCK_CHARS: usize = 25;
+
+/// Blocks where links make up more than this fraction of the text are dropped as navigation.
+const MAX_LINK_DENSITY: f32 = 0.5;
+
+/// A tool that fetches a web page and returns its main readable text.
+///
+/// Navigation, scripts, styles and other boilerplate are stripped, and blocks that are mostly links
+/// are dropped. If the page has an `<article>` or `<main>` element, only its content is kept.
+pub struct WebReaderTool {
+    max_chars: usize,
+    client: reqwest::Client,
+}
+
+impl WebReaderTool {
+    pub fn new() -> Self {
+        Self {
+            max_chars: 8000,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Truncates the text to at most `max_chars` characters. Defaults to 8000.
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars;
+        self
+    }
+}
+
+impl Default for WebReaderTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WebReaderToolInput {
+    pub url: String,
+}
+
+impl Describe for WebReaderToolInput {
+    fn describe() -> Format {
+        vec![("url", "The URL of the web page to read").into()].into()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WebReaderToolOutput {
+    pub title: String,
+    pub text: String,
+    pub url: String,
+}
+
+impl Describe for WebReaderToolOutput {
+    fn describe() -> Format {
+        vec![
+            ("title", "The title of the page").into(),
+            ("text", "The main text of the page").into(),
+            ("url", "The URL of the page, after redirects").into(),
+        ]
+        .into()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WebReaderToolError {
+    #[error("Invalid YAML: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("Failed to fetch the page: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("The page is not HTML, its content type is \"{0}\"")]
+    UnsupportedContentType(String),
+}
+
+impl ToolError for WebReaderToolError {}
+
+#[async_trait]
+impl Tool for WebReaderTool {
+    type Input = WebReaderToolInput;
+
+    type Output = WebReaderToolOutput;
+
+    type Error = WebReaderToolError;
+
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, Self::Error> {
+        let response = self
+            .client
+            .get(&input.url)
+            .header(reqwest::header::ACCEPT, "text/html,application/xhtml+xml")
+            .send()
+            .await?
+            .error_for_status()?;
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_lowercase();
+        if !content_type.contains("text/html") && !content_type.contains("application/xhtml") {
+            return Err(WebReaderToolError::UnsupportedContentType(content_type));
+        }
+        let url = response.url().to_string();
+        let html = response.text().await?;
+        let page = extract_readable(&html);
+        let mut text = page.text;
+        if let Some((cut, _)) = text.char_indices().nth(self.max_chars) {
+            text.truncate(cut);
+            text.push_str("\n[truncated]");
+        }
+        Ok(WebReaderToolOutput {
+            title: page.title,
+            text,
+            url,
+        })
+    }
+
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "WebReaderTool",
+            "A tool that fetches a web page and returns its title and main text, without navigation, ads or scripts.",
+            "Use this to read the content of a web page.",
+            WebReaderToolInput::describe(),
+            WebReaderToolOutput::describe(),
+        )
+    }
+}
+
+struct ReadablePage {
+    title: String,
+    text: String,
+}
+
+#[derive(Default)]
+struct Block {
+    text: String,
+    link_chars: usize,
+    heading: bool,
+}
+
+impl Block {
+    fn is_content(&self) -> bool {
+        let chars = self.text.chars().filter(|c| !c.is_whitespace()).count();
+        if chars == 0 {
+            return false;
+        }
+        if self.heading {
+            return true;
+        }
+        chars >= MIN_BLOCK_CHARS && (self.link_chars as f32 / chars as f32) <= MAX_LINK_DENSITY
+    }
+}
+
+/// Returns the part of `html` inside the first `<name>` element and the last `</name>`, if any.
+fn element_content<'a>(html: &'a str, lowercase: &str, name: &str) -> Option<&'a str> {
+    let open = lowercase.find(&format!("<{}", name))?;
+    let start = open + lowercase[open..].find('>')? + 1;
+    let end = lowercase
+        .rfind(&format!("</{}", name))
+        .filter(|&e| e >= start)?;
+    Some(&html[start..end])
+}
+
+fn extract_readable(html: &str) -> ReadablePage {
+    let lowercase = html.to_ascii_lowercase();
+    let title = element_content(html, &lowercase, "title")
+        .map(|t| collapse_whitespace(&decode_entities(t)))
+        .unwrap_or_default();
+    let content = element_content(html, &lowercase, "article")
+        .or_else(|| element_content(html, &lowercase, "main"))
+        .or_else(|| element_content(html, &lowercase, "body"))
+        .unwrap_or(html);
+
+    let mut blocks: Vec<Block> = vec![];
+    let mut current = Block::default();
+    let mut link_depth = 0usize;
+    let mut rest = content;
+    while !rest.is_empty() {
+        let Some(tag_start) = rest.find('<') else {
+            add_text(&mut current, rest, link_depth > 0);
+            break;
+        };
+        add_text(&mut current, &rest[..tag_start], link_depth > 0);
+        rest = &rest[tag_start..];
+
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..tag_end];
+        rest = &rest[tag_end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if !closing && !tag.ends_with('/') && SKIPPED_ELEMENTS.contains(&name.as_str()) {
+            rest = skip_element(rest, &name);
+        } else if name == "a" {
+            if closing {
+                link_depth = link_depth.saturating_sub(1);
+            } else {
+                link_depth += 1;
+            }
+        } else if BLOCK_ELEMENTS.contains(&name.as_str()) {
+            blocks.push(std::mem::take(&mut current));
+            current.heading = !closing && name.len() == 2 && name.starts_with('h');
+        }
+    }
+    blocks.push(current);
+
+    let text = blocks
+        .into_iter()
+        .map(|block| Block {
+            text: collapse_whitespace(&block.text),
+            ..block
+        })
+        .filter(Block::is_content)
+        .map(|b| b.text)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    ReadablePage { title, text }
+}
+
+fn add_text(block: &mut Block, raw: &str, in_link: bool) {
+    let text = decode_entities(raw);
+    if in_link {
+        block.link_chars += text
+            .split_whitespace()
+            .map(|w| w.chars().count())
+            .sum::<usize>();
+    }
+    block.text.push_str(&text);
+}
+
+/// Skips past the end of a `name` element whose opening tag was just consumed.
+fn skip_element<'a>(html: &'a str, name: &str) -> &'a str {
+    let lowercase = html.to_ascii_lowercase();
+    let open = format!("<{}", name);
+    let close = format!("</{}", name);
+    let mut depth = 1;
+    let mut pos = 0;
+    while depth > 0 {
+        let next_open = lowercase[pos..].find(&open).map(|i| i + pos);
+        let Some(next_close) = lowercase[pos..].find(&close).map(|i| i + pos) else {
+            return "";
+        };
+        match next_open {
+            Some(o) if o < next_close => {
+                depth += 1;
+                pos = o + open.len();
+            }
+            _ => {
+                depth -= 1;
+                pos = next_close + close.len();
+            }
+        }
+    }
+    html[pos..]
+        .find('>')
+        .map_or("", |end| &html[pos + end + 1..])
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let replacement = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        });
+        match (entity, replacement) {
+            (Some(entity), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extracts_article_text() {
+        let html = r#"<html><head><title>Rust &amp; You</title><script>var x = "<p>";</script></head>
+<body>
+  <nav><a href="/">Home</a> <a href="/blog">Blog</a></nav>
+  <article>
+    <h1>Why Rust</h1>
+    <p>Rust is a language empowering everyone to build <em>reliable</em> software.</p>
+    <div class="share"><a href="https://x.com">Share on X</a> <a href="https://fb.com">Share</a></div>
+    <!-- <p>hidden comment paragraph that is long enough</p> -->
+    <p>It&#39;s fast,&nbsp;memory-efficient and has great tooling.</p>
+  </article>
+  <footer>Copyright and a long enough footer text to pass the filter</footer>
+</body></html>"#;
+        let page = extract_readable(html);
+        assert_eq!(page.title, "Rust & You");
+        assert_eq!(
+            page.text,
+            "Why Rust\n\nRust is a language empowering everyone to build reliable software.\n\nIt's fast, memory-efficient and has great tooling."
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Normalizes or validates a value of type `T` after it is deserialized, see [`Transformed`].
+pub trait Transform<T> {
+    /// Returns the transformed value, or an error message if the value is unacceptable.
+    fn transform(value: T) -> Result<T, String>;
+}
+
+/// A tool input field that is passed through the transform `F` after it is deserialized.
+///
+/// Use it to keep normalization such as trimming or clamping out of `invoke_typed`, and to share it
+/// between tools. The tool only ever sees transformed values.
+///
+/// If the transform returns an error, deserializing the input fails with that message, and the tool
+/// is not invoked. The invocation then fails with the tool's `serde_yaml::Error` conversion, the same
+/// way as for an input that doesn't match its type.
+///
+/// # Example
+///
+/// ```
+/// use llm_chain::tools::{Transform, Transformed, Trim};
+/// use serde::Deserialize;
+///
+/// struct ClampTo100;
+///
+/// impl Transform<u32> for ClampTo100 {
+///     fn transform(value: u32) -> Result<u32, String> {
+///         Ok(value.min(100))
+///     }
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Input {
+///     query: Transformed<String, Trim>,
+///     limit: Transformed<u32, ClampTo100>,
+/// }
+///
+/// let input: Input = serde_yaml::from_str("query: ' rust '\nlimit: 500").unwrap();
+/// assert_eq!(*input.query, "rust");
+/// assert_eq!(*input.limit, 100);
+/// ```
+pub struct Transformed<T, F> {
+    value: T,
+    _transform: PhantomData<F>,
+}
+
+impl<T, F> Transformed<T, F> {
+    /// Returns the transformed value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T, F> Deref for Transformed<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Transformed<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+impl<T: Clone, F> Clone for Transformed<T, F> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            _transform: PhantomData,
+        }
+    }
+}
+
+impl<T: PartialEq, F> PartialEq for Transformed<T, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Serialize, F> Serialize for Transformed<T, F> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.value.serialize(serializer)
+    }
+}
+
+impl<'de, T, F> Deserialize<'de> for Transformed<T, F>
+where
+    T: Deserialize<'de>,
+    F: Transform<T>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value =
+            F::transform(T::deserialize(deserializer)?).map_err(serde::de::Error::custom)?;
+        Ok(Self {
+            value,
+            _transform: PhantomData,
+        })
+    }
+}
+
+/// Removes leading and trailing whitespace.
+pub struct Trim;
+
+impl Transform<String> for Trim {
+    fn transform(value: String) -> Result<String, String> {
+        Ok(value.trim().to_string())
+    }
+}
+
+/// Converts the text to lowercase.
+pub struct Lowercase;
+
+impl Transform<String> for Lowercase {
+    fn transform(value: String) -> Result<String, String> {
+        Ok(value.to_lowercase())
+    }
+}
+
+/// Rejects empty or blank text.
+pub struct NonEmpty;
+
+impl Transform<String> for NonEmpty {
+    fn transform(value: String) -> Result<String, String> {
+        if value.trim().is_empty() {
+            return Err("The value must not be empty".to_string());
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Input {
+        name: Transformed<String, Lowercase>,
+        query: Transformed<String, NonEmpty>,
+    }
+
+    #[test]
+    fn test_transforms_fields_and_reports_errors() {
+        let input: Input = serde_yaml::from_str("name: Rust\nquery: traits").unwrap();
+        assert_eq!(input.name.into_inner(), "rust");
+        assert_eq!(*input.query, "traits");
+
+        let err = serde_yaml::from_str::<Input>("name: Rust\nquery: ' '").unwrap_err();
+        assert!(err.to_string().contains("The value must not be empty"));
+    }
+}
@@ -15,7 +15,7 @@ use crate::{
     options::Options,
     output::Output,
     prompt::Prompt,
-    schema::{Document, EmptyMetadata},
+    schema::{Document, EmptyMetadata, Image},
     tokens::{PromptTokensError, TokenCount, Tokenizer, TokenizerError},
 };
 use async_trait::async_trait;
@@ -43,6 +43,12 @@ pub enum ExecutorError {
     PromptTokens(PromptTokensError),
     #[error("the context was to small to fit your input")]
     ContextTooSmall,
+    #[error("Model \"{model}\" is not available, available models are: {available:?}")]
+    /// The configured model isn't one of the models the executor can use.
+    UnknownModel {
+        model: String,
+        available: Vec<String>,
+    },
 }
 
 #[async_trait]
@@ -114,6 +120,40 @@ pub trait Executor: Sized {
     ///
     /// A `Result` containing a tokenizer, or an error if there was a problem.
     fn get_tokenizer(&self, options: &Options) -> Result<Self::StepTokenizer<'_>, TokenizerError>;
+
+    /// Returns the name of the model the executor is configured to use, if it has one.
+    fn configured_model(&self) -> Option<String> {
+        None
+    }
+
+    /// Lists the models the executor can use.
+    ///
+    /// Executors that can't list their models return an empty list.
+    async fn list_models(&self) -> Result<Vec<String>, ExecutorError>
+    where
+        Self: Sync,
+    {
+        Ok(vec![])
+    }
+
+    /// Checks that the configured model is available, so a misconfigured model name is reported
+    /// at startup instead of failing deep into a run.
+    ///
+    /// Succeeds if the executor has no configured model or can't list its models.
+    async fn validate_model(&self) -> Result<(), ExecutorError>
+    where
+        Self: Sync,
+    {
+        let Some(model) = self.configured_model() else {
+            return Ok(());
+        };
+        let available = self.list_models().await?;
+        if available.is_empty() || available.contains(&model) {
+            Ok(())
+        } else {
+            Err(ExecutorError::UnknownModel { model, available })
+        }
+    }
 }
 
 /// This marker trait is needed so the concrete VectorStore::Error can have a derived From<Embeddings::Error>
@@ -126,6 +166,47 @@ pub trait Embeddings {
     async fn embed_query(&self, query: String) -> Result<Vec<f32>, Self::Error>;
 }
 
+/// Embeddings that can also embed images, such as CLIP-style models.
+///
+/// Image vectors live in the same space as text vectors, which allows text queries to find images
+/// and the other way around.
+#[async_trait]
+pub trait ImageEmbeddings: Embeddings {
+    async fn embed_images(&self, images: Vec<Image>) -> Result<Vec<Vec<f32>>, Self::Error>;
+}
+
+/// This marker trait is needed so users of Reranker can derive From<Reranker::Error>
+pub trait RerankerError {}
+
+/// Scores how relevant documents are to a query, typically with a cross-encoder model.
+///
+/// Cross-encoders look at the query and a document together, which makes them much more precise
+/// than comparing embeddings, but too slow to run over a whole collection. They are used to reorder
+/// the candidates found by a vector search, see `VectorStore::similarity_search_with_reranker`.
+#[async_trait]
+pub trait Reranker {
+    type Error: Send + Debug + Error + RerankerError;
+    /// Returns the documents with their relevance to `query`, most relevant first.
+    async fn rerank<M: serde::Serialize + serde::de::DeserializeOwned + Send + 'static>(
+        &self,
+        query: &str,
+        documents: Vec<Document<M>>,
+    ) -> Result<Vec<(Document<M>, f32)>, Self::Error>;
+}
+
+/// An error from `VectorStore::similarity_search_with_reranker`.
+#[derive(thiserror::Error, Debug)]
+pub enum RerankedSearchError<V, R>
+where
+    V: Debug + Error,
+    R: Debug + Error,
+{
+    #[error(transparent)]
+    VectorStore(V),
+    #[error(transparent)]
+    Reranker(R),
+}
+
 /// This marker trait is needed so users of VectorStore can derive From<VectorStore::Error>
 pub trait VectorStoreError {}
 
@@ -143,4 +224,71 @@ where
         query: String,
         limit: u32,
     ) -> Result<Vec<Document<M>>, Self::Error>;
+    /// Like `similarity_search`, but also returns how similar each document is to the query.
+    ///
+    /// Higher scores mean more similar documents.
+    async fn similarity_search_with_score(
+        &self,
+        query: String,
+        limit: u32,
+    ) -> Result<Vec<(Document<M>, f32)>, Self::Error>;
+    /// Searches for similar documents and reorders them with a custom scoring function.
+    ///
+    /// Fetches the `fetch_k` most similar documents, then calls `rerank` with each document and its
+    /// similarity score, and returns the `k` documents with the highest new scores. Reranking can
+    /// only promote documents that were fetched, so `fetch_k` should be comfortably larger than `k`.
+    async fn similarity_search_reranked<F>(
+        &self,
+        query: String,
+        k: u32,
+        fetch_k: u32,
+        rerank: F,
+    ) -> Result<Vec<Document<M>>, Self::Error>
+    where
+        F: Fn(&Document<M>, f32) -> f32 + Send + Sync,
+        M: Send,
+        Self: Sync,
+    {
+        let results = self
+            .similarity_search_with_score(query, fetch_k.max(k))
+            .await?;
+        let mut rescored: Vec<(Document<M>, f32)> = results
+            .into_iter()
+            .map(|(doc, score)| {
+                let score = rerank(&doc, score);
+                (doc, score)
+            })
+            .collect();
+        rescored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(rescored
+            .into_iter()
+            .take(k as usize)
+            .map(|(doc, _)| doc)
+            .collect())
+    }
+    /// Fetches the `fetch_k` most similar documents, and returns the `k` that `reranker` finds most
+    /// relevant, with their relevance scores.
+    async fn similarity_search_with_reranker<R>(
+        &self,
+        query: String,
+        k: u32,
+        fetch_k: u32,
+        reranker: &R,
+    ) -> Result<Vec<(Document<M>, f32)>, RerankedSearchError<Self::Error, R::Error>>
+    where
+        R: Reranker + Sync,
+        M: Send + 'static,
+        Self: Sync,
+    {
+        let candidates = self
+            .similarity_search(query.clone(), fetch_k.max(k))
+            .await
+            .map_err(RerankedSearchError::VectorStore)?;
+        let mut reranked = reranker
+            .rerank(&query, candidates)
+            .await
+            .map_err(RerankedSearchError::Reranker)?;
+        reranked.truncate(k as usize);
+        Ok(reranked)
+    }
 }